@@ -0,0 +1,138 @@
+//! Compares the BVH builders: build time and the time to trace primary rays.
+//!
+//! ```sh
+//! cargo run --release --example bvh_bench
+//! ```
+use raytracer::bvh::{BvhNode, BvhOptions, SplitMethod};
+use raytracer::camera::Camera;
+use raytracer::hittable::Hit;
+use raytracer::hittable_list::HittableList;
+use raytracer::material::{Lambertian, Material};
+use raytracer::scenes;
+use raytracer::triangle::Triangle;
+use raytracer::vec3::{Color, Point3, Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const RESOLUTION: usize = 256;
+
+/// A sphere of `2 * rings * rings` triangles with a wavy surface.
+fn bumpy_sphere(rings: usize) -> HittableList {
+    let material: Arc<dyn Material> = Arc::new(Lambertian::new_color(Color::ones()));
+    let point = |i: usize, j: usize| {
+        let theta = PI * i as f64 / rings as f64;
+        let phi = 2.0 * PI * j as f64 / (2 * rings) as f64;
+        let r = 1.0 + 0.05 * (12.0 * theta).sin() * (12.0 * phi).cos();
+        r * Point3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    };
+    let mut list = HittableList::new();
+    for i in 0..rings {
+        for j in 0..2 * rings {
+            let (a, b) = (point(i, j), point(i, j + 1));
+            let (c, d) = (point(i + 1, j), point(i + 1, j + 1));
+            list.add(Arc::new(Triangle::new(a, c, b, material.clone())));
+            list.add(Arc::new(Triangle::new(b, c, d, material.clone())));
+        }
+    }
+    list
+}
+
+fn camera(lookfrom: Point3, lookat: Point3, vfov: f64) -> Camera {
+    Camera::new(
+        lookfrom,
+        lookat,
+        Vec3::new(0.0, 1.0, 0.0),
+        vfov,
+        1.0,
+        0.0,
+        10.0,
+        0.0,
+        1.0,
+    )
+}
+
+fn trace(world: &dyn Hit, camera: &Camera) -> (Duration, usize) {
+    let start = Instant::now();
+    let mut hits = 0;
+    for j in 0..RESOLUTION {
+        for i in 0..RESOLUTION {
+            let u = (i as f64 + 0.5) / RESOLUTION as f64;
+            let v = (j as f64 + 0.5) / RESOLUTION as f64;
+            if world
+                .hit(&camera.get_ray(u, v), 0.001, f64::INFINITY)
+                .is_some()
+            {
+                hits += 1;
+            }
+        }
+    }
+    (start.elapsed(), hits)
+}
+
+fn bench(name: &str, make_list: &dyn Fn() -> HittableList, camera: &Camera) {
+    let count = make_list().objects.len();
+    println!(
+        "{} ({} objects, {} primary rays)",
+        name,
+        count,
+        RESOLUTION * RESOLUTION
+    );
+    let builders = [
+        ("median", SplitMethod::Median, 1),
+        ("sah, leaf 1", SplitMethod::Sah, 1),
+        ("sah, leaf 4", SplitMethod::Sah, 4),
+    ];
+    for (label, split, max_leaf_size) in builders {
+        let list = make_list();
+        let start = Instant::now();
+        let world = BvhNode::build(
+            list,
+            0.0,
+            1.0,
+            &BvhOptions {
+                split,
+                max_leaf_size,
+            },
+        )
+        .unwrap();
+        let build = start.elapsed();
+        let (elapsed, hits) = trace(&*world, camera);
+        println!(
+            "  {:<12} build {:>9.2?}  trace {:>9.2?}  {:>6.2} Mrays/s  ({} hits)",
+            label,
+            build,
+            elapsed,
+            (RESOLUTION * RESOLUTION) as f64 / elapsed.as_secs_f64() / 1e6,
+            hits
+        );
+    }
+}
+
+fn main() {
+    bench(
+        "final",
+        &scenes::final_scene,
+        &camera(
+            Point3::new(478.0, 278.0, -600.0),
+            Point3::new(278.0, 278.0, 0.0),
+            40.0,
+        ),
+    );
+    bench(
+        "random",
+        &scenes::random_scene,
+        &camera(Point3::new(13.0, 2.0, 3.0), Point3::zero(), 20.0),
+    );
+    for rings in [100, 400] {
+        bench(
+            &format!("mesh {}", rings),
+            &|| bumpy_sphere(rings),
+            &camera(Point3::new(0.0, 0.5, 4.0), Point3::zero(), 35.0),
+        );
+    }
+}
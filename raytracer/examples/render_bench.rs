@@ -0,0 +1,73 @@
+//! Rays per second on the built-in scenes, on one thread.
+//!
+//! ```sh
+//! cargo run --release --example render_bench
+//! cargo run --release --example render_bench --features f32
+//! ```
+use raytracer::aabb::AaBb;
+use raytracer::hittable::{Hit, HitRecord};
+use raytracer::{scenes, Ray, RenderSettings, Renderer};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+const WIDTH: usize = 160;
+const SAMPLES: usize = 16;
+
+/// Counts the rays traced against the wrapped world.
+struct Counted {
+    world: Arc<dyn Hit>,
+    rays: AtomicU64,
+}
+
+impl Hit for Counted {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.rays.fetch_add(1, Ordering::Relaxed);
+        self.world.hit(r, t_min, t_max)
+    }
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AaBb> {
+        self.world.bounding_box(time0, time1)
+    }
+}
+
+fn main() {
+    let mut total_rays = 0;
+    let mut total_time = 0.0;
+    for name in scenes::NAMES {
+        let mut desc = scenes::builtin(name).unwrap();
+        let counted = Arc::new(Counted {
+            world: desc.scene.world.clone(),
+            rays: AtomicU64::new(0),
+        });
+        desc.scene.world = counted.clone();
+        let renderer = Renderer::new(RenderSettings {
+            width: WIDTH,
+            height: (WIDTH as f64 / desc.aspect_ratio) as usize,
+            samples_per_pixel: SAMPLES,
+            max_depth: desc.max_depth,
+            threads: 1,
+            seed: Some(1),
+            ..RenderSettings::default()
+        });
+        let start = Instant::now();
+        renderer.render(&desc.scene);
+        let elapsed = start.elapsed().as_secs_f64();
+        let rays = counted.rays.load(Ordering::Relaxed);
+        total_rays += rays;
+        total_time += elapsed;
+        println!(
+            "{:<20} {:>10} rays  {:>7.2} s  {:>6.3} Mrays/s",
+            name,
+            rays,
+            elapsed,
+            rays as f64 / elapsed / 1e6
+        );
+    }
+    println!(
+        "{:<20} {:>10} rays  {:>7.2} s  {:>6.3} Mrays/s",
+        "total",
+        total_rays,
+        total_time,
+        total_rays as f64 / total_time / 1e6
+    );
+}
@@ -0,0 +1,58 @@
+//! Error of each sampler against a high sample count reference, at equal samples.
+//!
+//! ```sh
+//! cargo run --release --example sampler_noise
+//! ```
+use raytracer::sampler::SamplerKind;
+use raytracer::{scenes, Film, RenderSettings, Renderer};
+
+const WIDTH: usize = 48;
+const REFERENCE_SAMPLES: usize = 4096;
+const SAMPLES: [usize; 3] = [4, 16, 64];
+const SEEDS: u64 = 4;
+
+fn render(name: &str, samples_per_pixel: usize, sampler: SamplerKind, seed: u64) -> Film {
+    let desc = scenes::builtin(name).unwrap();
+    Renderer::new(RenderSettings {
+        width: WIDTH,
+        height: (WIDTH as f64 / desc.aspect_ratio) as usize,
+        samples_per_pixel,
+        // a single pass, which the stratified sampler stratifies as a whole
+        pass_samples: samples_per_pixel,
+        max_depth: desc.max_depth,
+        threads: 1,
+        seed: Some(seed),
+        sampler,
+        ..RenderSettings::default()
+    })
+    .render(&desc.scene)
+}
+
+/// The root mean squared error of the displayable range, so that a few fireflies
+/// do not hide everything else.
+fn rmse(film: &Film, reference: &Film) -> f64 {
+    let squared: f64 = film
+        .data()
+        .iter()
+        .zip(reference.data())
+        .map(|(a, b)| (a.clamp(0.0, 1.0) - b.clamp(0.0, 1.0)).powi(2) as f64)
+        .sum();
+    (squared / film.data().len() as f64).sqrt()
+}
+
+fn main() {
+    for name in ["cornell_box", "simple_light", "random"] {
+        let reference = render(name, REFERENCE_SAMPLES, SamplerKind::Independent, 0);
+        println!("{}", name);
+        for samples in SAMPLES {
+            print!("  {:>3} spp", samples);
+            for sampler in SamplerKind::NAMES.map(|n| SamplerKind::from_name(n).unwrap()) {
+                let error: f64 = (1..=SEEDS)
+                    .map(|seed| rmse(&render(name, samples, sampler, seed), &reference))
+                    .sum();
+                print!("  {} {:.4}", sampler, error / SEEDS as f64);
+            }
+            println!();
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{vec3::*, Ray};
 use std::mem::swap;
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug)]
 pub struct AaBb {
     minimum: Vec3,
     maximum: Vec3,
@@ -10,18 +10,22 @@ impl AaBb {
         Self { minimum, maximum }
     }
     pub fn min(&self) -> Vec3 {
-        self.minimum.clone()
+        self.minimum
     }
     pub fn max(&self) -> Vec3 {
-        self.maximum.clone()
+        self.maximum
+    }
+    pub fn surface_area(&self) -> f64 {
+        let d = self.max() - self.min();
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
     }
     pub fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
         let mut t_min = t_min;
         let mut t_max = t_max;
         for a in 0..3 {
-            let inv_d = 1.0 / r.dir()[a];
-            let mut t0 = (self.min()[a] - r.orig()[a]) * inv_d;
-            let mut t1 = (self.max()[a] - r.orig()[a]) * inv_d;
+            let inv_d = 1.0 / r.dir().axis(a);
+            let mut t0 = (self.minimum.axis(a) - r.orig().axis(a)) * inv_d;
+            let mut t1 = (self.maximum.axis(a) - r.orig().axis(a)) * inv_d;
             if inv_d < 0.0 {
                 swap(&mut t0, &mut t1);
             }
@@ -1,11 +1,22 @@
 use crate::aabb::AaBb;
 use crate::hittable::{Hit, HitRecord};
 use crate::material::Material;
-use crate::rtweekend::random;
+use crate::sampler::sample_2d;
 use crate::vec3::Point3;
 use crate::{Ray, Vec3};
 use std::sync::Arc;
 
+/// The solid-angle density, seen from `o`, of sampling a point uniformly on a rect.
+fn rect_pdf_value(rect: &dyn Hit, o: &Point3, v: &Vec3, area: f64) -> f64 {
+    if let Some(rec) = rect.hit(&Ray::new(*o, *v, 0.0), 0.001, f64::INFINITY) {
+        let distance_squared = rec.t * rec.t * v.squared_length();
+        let cosine = (v.dot(rec.normal) / v.length()).abs();
+        distance_squared / (cosine * area)
+    } else {
+        0.0
+    }
+}
+
 pub struct XYRect {
     mp: Arc<dyn Material>,
     x0: f64,
@@ -25,18 +36,24 @@ impl XYRect {
             k,
         }
     }
-}
-impl Hit for XYRect {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    /// The `t` of the hit and where it is on the rect.
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
         let t = (self.k - r.orig().z()) / r.dir().z();
-        if t < t_min || t > t_max {
+        // written so that the NaN of a ray parallel to the rect is rejected too
+        if !(t_min..=t_max).contains(&t) {
             return None;
         }
         let x = r.orig().x() + t * r.dir().x();
         let y = r.orig().y() + t * r.dir().y();
-        if x < self.x0 || x > self.x1 || y < self.y0 || y > self.y1 {
+        if !(self.x0..=self.x1).contains(&x) || !(self.y0..=self.y1).contains(&y) {
             return None;
         }
+        Some((t, x, y))
+    }
+}
+impl Hit for XYRect {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t, x, y) = self.intersect(r, t_min, t_max)?;
         Some(HitRecord::new(
             r.at(t),
             t,
@@ -47,12 +64,28 @@ impl Hit for XYRect {
             &*self.mp,
         ))
     }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.intersect(r, t_min, t_max).is_some()
+    }
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AaBb> {
         Some(AaBb::new(
             Point3::new(self.x0, self.y0, self.k - 0.0001),
             Point3::new(self.x1, self.y1, self.k + 0.0001),
         ))
     }
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        let area = (self.x1 - self.x0) * (self.y1 - self.y0);
+        rect_pdf_value(self, o, v, area)
+    }
+    fn random(&self, o: &Vec3) -> Vec3 {
+        let (a, b) = sample_2d();
+        let random_point = Point3::new(
+            self.x0 + (self.x1 - self.x0) * a,
+            self.y0 + (self.y1 - self.y0) * b,
+            self.k,
+        );
+        random_point - *o
+    }
 }
 pub struct XZRect {
     mp: Arc<dyn Material>,
@@ -73,18 +106,24 @@ impl XZRect {
             k,
         }
     }
-}
-impl Hit for XZRect {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    /// The `t` of the hit and where it is on the rect.
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
         let t = (self.k - r.orig().y()) / r.dir().y();
-        if t < t_min || t > t_max {
+        // written so that the NaN of a ray parallel to the rect is rejected too
+        if !(t_min..=t_max).contains(&t) {
             return None;
         }
         let x = r.orig().x() + t * r.dir().x();
         let z = r.orig().z() + t * r.dir().z();
-        if x < self.x0 || x > self.x1 || z < self.z0 || z > self.z1 {
+        if !(self.x0..=self.x1).contains(&x) || !(self.z0..=self.z1).contains(&z) {
             return None;
         }
+        Some((t, x, z))
+    }
+}
+impl Hit for XZRect {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t, x, z) = self.intersect(r, t_min, t_max)?;
         Some(HitRecord::new(
             r.at(t),
             t,
@@ -95,6 +134,9 @@ impl Hit for XZRect {
             &*self.mp,
         ))
     }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.intersect(r, t_min, t_max).is_some()
+    }
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AaBb> {
         Some(AaBb::new(
             Point3::new(self.x0, self.k - 0.0001, self.z0),
@@ -102,18 +144,17 @@ impl Hit for XZRect {
         ))
     }
     fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
-        if let Some(rec) = self.hit(&Ray::new(o.clone(), v.clone(), 0.0), 0.001, f64::INFINITY) {
-            let area = (self.x1 - self.x0) * (self.z1 - self.z0);
-            let distance_squared = rec.t * rec.t * v.squared_length();
-            let cosine = (v.dot(rec.normal) / v.length()).abs();
-            distance_squared / (cosine * area)
-        } else {
-            0.0
-        }
+        let area = (self.x1 - self.x0) * (self.z1 - self.z0);
+        rect_pdf_value(self, o, v, area)
     }
     fn random(&self, o: &Vec3) -> Vec3 {
-        let random_point = Point3::new(random(self.x0, self.x1), self.k, random(self.z0, self.z1));
-        random_point - o.clone()
+        let (a, b) = sample_2d();
+        let random_point = Point3::new(
+            self.x0 + (self.x1 - self.x0) * a,
+            self.k,
+            self.z0 + (self.z1 - self.z0) * b,
+        );
+        random_point - *o
     }
 }
 pub struct YZRect {
@@ -135,18 +176,24 @@ impl YZRect {
             k,
         }
     }
-}
-impl Hit for YZRect {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    /// The `t` of the hit and where it is on the rect.
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
         let t = (self.k - r.orig().x()) / r.dir().x();
-        if t < t_min || t > t_max {
+        // written so that the NaN of a ray parallel to the rect is rejected too
+        if !(t_min..=t_max).contains(&t) {
             return None;
         }
         let y = r.orig().y() + t * r.dir().y();
         let z = r.orig().z() + t * r.dir().z();
-        if y < self.y0 || y > self.y1 || z < self.z0 || z > self.z1 {
+        if !(self.y0..=self.y1).contains(&y) || !(self.z0..=self.z1).contains(&z) {
             return None;
         }
+        Some((t, y, z))
+    }
+}
+impl Hit for YZRect {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t, y, z) = self.intersect(r, t_min, t_max)?;
         Some(HitRecord::new(
             r.at(t),
             t,
@@ -157,10 +204,26 @@ impl Hit for YZRect {
             &*self.mp,
         ))
     }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.intersect(r, t_min, t_max).is_some()
+    }
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AaBb> {
         Some(AaBb::new(
             Point3::new(self.k - 0.0001, self.y0, self.z0),
             Point3::new(self.k + 0.0001, self.y1, self.z1),
         ))
     }
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        let area = (self.y1 - self.y0) * (self.z1 - self.z0);
+        rect_pdf_value(self, o, v, area)
+    }
+    fn random(&self, o: &Vec3) -> Vec3 {
+        let (a, b) = sample_2d();
+        let random_point = Point3::new(
+            self.k,
+            self.y0 + (self.y1 - self.y0) * a,
+            self.z0 + (self.z1 - self.z0) * b,
+        );
+        random_point - *o
+    }
 }
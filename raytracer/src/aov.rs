@@ -0,0 +1,349 @@
+//! Auxiliary render passes (AOVs): what the camera rays hit first, for compositing and
+//! denoising next to the rendered image.
+use crate::color::srgb_to_linear;
+use crate::film::Film;
+use crate::hittable::HitRecord;
+use crate::rtweekend::derive_seed;
+use crate::vec3::{Color, Point3, Vec3};
+use crate::Ray;
+use std::collections::HashMap;
+
+/// One auxiliary pass.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aov {
+    /// The color of the surfaces without any lighting, from their textures.
+    Albedo,
+    /// The shading normal in world space, facing the camera.
+    Normal,
+    /// The distance from the camera, infinite where nothing is hit.
+    Depth,
+    /// The point hit, in world space.
+    Position,
+    /// The texture coordinates of the point hit.
+    Uv,
+    /// The top-level object hit, numbered from 1 in the order of the scene; 0 for none.
+    ObjectId,
+    /// The material hit, numbered from 1 in the order it first appears in the image,
+    /// row by row from the top; 0 for none. Objects that share a material share its ID.
+    MaterialId,
+}
+
+impl Aov {
+    pub const ALL: [Self; 7] = [
+        Self::Albedo,
+        Self::Normal,
+        Self::Depth,
+        Self::Position,
+        Self::Uv,
+        Self::ObjectId,
+        Self::MaterialId,
+    ];
+    pub const NAMES: [&'static str; 7] = [
+        "albedo", "normal", "depth", "position", "uv", "object", "material",
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let i = Self::NAMES.iter().position(|&n| n == name)?;
+        Some(Self::ALL[i])
+    }
+
+    pub fn name(self) -> &'static str {
+        Self::NAMES[Self::ALL.iter().position(|&a| a == self).unwrap()]
+    }
+
+    /// The channels of the pass, named as in the layers of an OpenEXR file.
+    pub fn channels(self) -> &'static [&'static str] {
+        match self {
+            Self::Albedo => &["R", "G", "B"],
+            Self::Normal | Self::Position => &["X", "Y", "Z"],
+            Self::Depth => &["Z"],
+            Self::Uv => &["U", "V"],
+            Self::ObjectId | Self::MaterialId => &["id"],
+        }
+    }
+}
+
+/// The passes of one pixel.
+#[derive(Clone, Copy)]
+pub struct AovPixel {
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub depth: f64,
+    pub position: Point3,
+    pub uv: (f64, f64),
+    pub object: u32,
+    pub material: u32,
+}
+
+impl AovPixel {
+    /// The values of `aov`, as many as it has channels.
+    pub fn values(&self, aov: Aov) -> Vec<f64> {
+        match aov {
+            Aov::Albedo => vec![self.albedo.x(), self.albedo.y(), self.albedo.z()],
+            Aov::Normal => vec![self.normal.x(), self.normal.y(), self.normal.z()],
+            Aov::Depth => vec![self.depth],
+            Aov::Position => vec![self.position.x(), self.position.y(), self.position.z()],
+            Aov::Uv => vec![self.uv.0, self.uv.1],
+            Aov::ObjectId => vec![self.object as f64],
+            Aov::MaterialId => vec![self.material as f64],
+        }
+    }
+}
+
+impl Default for AovPixel {
+    /// A pixel where nothing is hit.
+    fn default() -> Self {
+        Self {
+            albedo: Color::zero(),
+            normal: Vec3::zero(),
+            depth: f64::INFINITY,
+            position: Point3::zero(),
+            uv: (0.0, 0.0),
+            object: 0,
+            material: 0,
+        }
+    }
+}
+
+/// The first hits of the camera rays of a pixel, added up into its [`AovPixel`].
+/// Albedo is averaged over all the rays, the geometry over those that hit something,
+/// and the IDs are those of the object and material hit most often.
+pub(crate) struct AovSamples {
+    count: usize,
+    albedo: Color,
+    hits: usize,
+    normal: Vec3,
+    depth: f64,
+    position: Point3,
+    uv: (f64, f64),
+    /// The object ID and material address of every ray, 0 for a miss.
+    ids: Vec<(u32, usize)>,
+}
+
+impl AovSamples {
+    pub(crate) fn new() -> Self {
+        Self {
+            count: 0,
+            albedo: Color::zero(),
+            hits: 0,
+            normal: Vec3::zero(),
+            depth: 0.0,
+            position: Point3::zero(),
+            uv: (0.0, 0.0),
+            ids: Vec::new(),
+        }
+    }
+
+    /// Adds camera ray `r`, which hits `rec` or else escapes to `background`.
+    pub(crate) fn add(&mut self, r: &Ray, rec: Option<&HitRecord>, background: &Color) {
+        self.count += 1;
+        let rec = match rec {
+            Some(rec) => rec,
+            None => {
+                self.albedo += background.max(Color::zero()).min(Color::ones());
+                self.ids.push((0, 0));
+                return;
+            }
+        };
+        self.albedo += rec.material.albedo(rec);
+        self.hits += 1;
+        self.normal += rec.normal;
+        self.depth += (rec.p - r.orig()).length();
+        self.position += rec.p;
+        self.uv = (self.uv.0 + rec.u, self.uv.1 + rec.v);
+        self.ids.push((rec.object_id, rec.material.address()));
+    }
+
+    /// The passes of the pixel, with the address of its material rather than its ID.
+    pub(crate) fn finish(&self) -> (AovPixel, usize) {
+        let mut pixel = AovPixel::default();
+        if self.count > 0 {
+            pixel.albedo = self.albedo / self.count as f64;
+        }
+        if self.hits > 0 {
+            let n = self.hits as f64;
+            if !self.normal.near_zero() {
+                pixel.normal = self.normal.unit_vector();
+            }
+            pixel.depth = self.depth / n;
+            pixel.position = self.position / n;
+            pixel.uv = (self.uv.0 / n, self.uv.1 / n);
+        }
+        // both IDs of the same hits, so that they agree on the edges of objects
+        let (object, address) = most_common(self.ids.iter().copied()).unwrap_or((0, 0));
+        pixel.object = object;
+        (pixel, address)
+    }
+}
+
+/// The value that comes up most often, the first of them on a tie.
+fn most_common<T: Copy + PartialEq>(values: impl Iterator<Item = T> + Clone) -> Option<T> {
+    let mut best = None;
+    let mut best_count = 0;
+    for v in values.clone() {
+        let count = values.clone().filter(|&w| w == v).count();
+        if count > best_count {
+            best = Some(v);
+            best_count = count;
+        }
+    }
+    best
+}
+
+/// All the passes of an image. Row 0 is the top of the image, as in a [`Film`].
+#[derive(Clone)]
+pub struct Aovs {
+    width: usize,
+    height: usize,
+    pixels: Vec<AovPixel>,
+}
+
+impl Aovs {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![AovPixel::default(); width * height],
+        }
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn pixel(&self, x: usize, y: usize) -> &AovPixel {
+        &self.pixels[y * self.width + x]
+    }
+    pub fn pixel_mut(&mut self, x: usize, y: usize) -> &mut AovPixel {
+        &mut self.pixels[y * self.width + x]
+    }
+
+    /// Numbers the materials at `addresses`, one per pixel row by row from the top, in
+    /// the order they first appear. Address 0 is no material.
+    pub(crate) fn number_materials(&mut self, addresses: &[usize]) {
+        let mut ids = HashMap::new();
+        for (pixel, &address) in self.pixels.iter_mut().zip(addresses) {
+            pixel.material = match address {
+                0 => 0,
+                address => {
+                    let next = ids.len() as u32 + 1;
+                    *ids.entry(address).or_insert(next)
+                }
+            };
+        }
+    }
+
+    /// The channels of `aov`, named `<aov>.<channel>` as layers of an OpenEXR file.
+    pub fn layer(&self, aov: Aov) -> Vec<(String, Vec<f32>)> {
+        let values: Vec<Vec<f64>> = self.pixels.iter().map(|p| p.values(aov)).collect();
+        aov.channels()
+            .iter()
+            .enumerate()
+            .map(|(c, channel)| {
+                let data = values.iter().map(|v| v[c] as f32).collect();
+                (format!("{}.{}", aov.name(), channel), data)
+            })
+            .collect()
+    }
+
+    /// The values of `aov` as they are, in the red, green and blue of a film; passes
+    /// with a single channel are grey.
+    pub fn to_film(&self, aov: Aov) -> Film {
+        let mut film = Film::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let v = self.pixel(x, y).values(aov);
+                let color = match v.len() {
+                    1 => Color::new(v[0], v[0], v[0]),
+                    2 => Color::new(v[0], v[1], 0.0),
+                    _ => Color::new(v[0], v[1], v[2]),
+                };
+                film.set_pixel(x, y, &color);
+            }
+        }
+        film
+    }
+
+    /// `aov` made into a picture to look at: normals from `[-1, 1]` to `[0, 1]`, depth
+    /// from white at the nearest point to black at the farthest, positions stretched
+    /// over their bounds, and a color for every ID. Apart from albedo, which is a color
+    /// already, these are the values that end up in the file once it is sRGB-encoded.
+    pub fn to_display_film(&self, aov: Aov) -> Film {
+        let hits = || self.pixels.iter().filter(|p| p.depth.is_finite());
+        let (mut near, mut far) = (f64::INFINITY, 0.0_f64);
+        let mut low = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut high = -low;
+        for p in hits() {
+            near = near.min(p.depth);
+            far = far.max(p.depth);
+            low = low.min(p.position);
+            high = high.max(p.position);
+        }
+        let range = |v: f64, low: f64, high: f64| {
+            if high > low {
+                (v - low) / (high - low)
+            } else {
+                0.5
+            }
+        };
+        let mut film = Film::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let p = self.pixel(x, y);
+                let hit = p.depth.is_finite();
+                let encoded = match aov {
+                    Aov::Albedo => {
+                        film.set_pixel(x, y, &p.albedo);
+                        continue;
+                    }
+                    Aov::Normal if hit => p.normal * 0.5 + Vec3::new(0.5, 0.5, 0.5),
+                    Aov::Depth if hit => Color::ones() * (1.0 - range(p.depth, near, far)),
+                    Aov::Position if hit => Color::new(
+                        range(p.position.x(), low.x(), high.x()),
+                        range(p.position.y(), low.y(), high.y()),
+                        range(p.position.z(), low.z(), high.z()),
+                    ),
+                    Aov::Uv if hit => Color::new(p.uv.0, p.uv.1, 0.0),
+                    Aov::ObjectId => id_color(p.object),
+                    Aov::MaterialId => id_color(p.material),
+                    _ => Color::zero(),
+                };
+                let encoded = encoded.max(Color::zero()).min(Color::ones());
+                let linear = Color::new(
+                    srgb_to_linear(encoded.x()),
+                    srgb_to_linear(encoded.y()),
+                    srgb_to_linear(encoded.z()),
+                );
+                film.set_pixel(x, y, &linear);
+            }
+        }
+        film
+    }
+}
+
+/// A bright color picked at random by `id`, black for 0.
+fn id_color(id: u32) -> Color {
+    if id == 0 {
+        return Color::zero();
+    }
+    let bits = derive_seed(0x1D, &[id as u64]);
+    let channel = |shift: u32| 0.25 + 0.75 * ((bits >> shift) & 0xFF) as f64 / 255.0;
+    Color::new(channel(0), channel(8), channel(16))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aov_names() {
+        for (aov, name) in Aov::ALL.into_iter().zip(Aov::NAMES) {
+            assert_eq!(aov.name(), name);
+            assert_eq!(Aov::from_name(name), Some(aov));
+        }
+        assert_eq!(Aov::from_name("beauty"), None);
+        assert_eq!(most_common([3, 1, 1, 3, 2].into_iter()), Some(3));
+        assert_eq!(most_common(std::iter::empty::<u32>()), None);
+    }
+}
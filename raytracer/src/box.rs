@@ -1,7 +1,7 @@
 use crate::aabb::AaBb;
 use crate::hittable::{Hit, HitRecord};
 use crate::material::Material;
-use crate::vec3::Point3;
+use crate::vec3::{Point3, Vec3};
 use crate::{aarect::*, hittable_list::*, Ray};
 use std::sync::Arc;
 
@@ -63,18 +63,27 @@ impl Bbox {
             p0.x(),
         )));
         Self {
-            box_min: p0.clone(),
-            box_max: p1.clone(),
+            box_min: *p0,
+            box_max: *p1,
             sides,
         }
     }
 }
 
 impl Hit for Bbox {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         self.sides.hit(r, t_min, t_max)
     }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.sides.occluded(r, t_min, t_max)
+    }
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AaBb> {
-        Some(AaBb::new(self.box_min.clone(), self.box_max.clone()))
+        Some(AaBb::new(self.box_min, self.box_max))
+    }
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        self.sides.pdf_value(o, v)
+    }
+    fn random(&self, o: &Vec3) -> Vec3 {
+        self.sides.random(o)
     }
 }
@@ -1,68 +1,603 @@
 use crate::aabb::{surrounding_box, AaBb};
 use crate::hittable::{Hit, HitRecord};
 use crate::hittable_list::HittableList;
-use crate::rtweekend::random_i32;
+use crate::rtweekend::{random_i32, with_thread_seed, SCENE_SEED};
+use crate::vec3::Point3;
 use crate::Ray;
+use std::fmt;
 use std::sync::Arc;
 
+/// How the builder divides the objects of a node between its children.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitMethod {
+    /// The surface area heuristic over binned centroids, on the best of all three axes.
+    Sah,
+    /// The original builder: sort by the box minimum on a random x or y axis and
+    /// split at the median. Kept for comparison.
+    Median,
+}
+
+#[derive(Clone, Debug)]
+pub struct BvhOptions {
+    pub split: SplitMethod,
+    /// Nodes with at most this many objects may become leaves. `Median` always
+    /// splits down to single objects.
+    pub max_leaf_size: usize,
+}
+
+impl Default for BvhOptions {
+    fn default() -> Self {
+        Self {
+            split: SplitMethod::Sah,
+            max_leaf_size: 4,
+        }
+    }
+}
+
+// number of centroid bins per axis, and the cost of a node visit relative to
+// intersecting one object
+const SAH_BINS: usize = 12;
+const TRAVERSAL_COST: f64 = 1.0;
+// The traversal keeps the far children on a fixed stack. Below this depth the
+// builder only splits in halves, which keeps every tree shallow enough for it.
+const STACK_SIZE: usize = 64;
+const MAX_SAH_DEPTH: usize = 32;
+
+#[derive(Debug)]
+pub enum BvhError {
+    /// The bounding box of the object at `index` in the list is NaN or inside out.
+    InvalidBounds { index: usize },
+    /// More objects than the node layout can address.
+    TooManyObjects(usize),
+}
+
+impl fmt::Display for BvhError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BvhError::InvalidBounds { index } => {
+                write!(f, "object {} has an invalid bounding box", index)
+            }
+            BvhError::TooManyObjects(count) => {
+                write!(f, "{} objects are too many for one BVH", count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BvhError {}
+
+/// A bounding volume hierarchy flattened into one array of nodes in depth-first
+/// order. The first child of an interior node directly follows it, leaves own a
+/// range of `primitives`. Objects without finite bounds (infinite planes, sky
+/// domes) cannot be placed in the tree and are tested on every ray instead.
 pub struct BvhNode {
-    left: Arc<dyn Hit>,
-    right: Arc<dyn Hit>,
+    nodes: Vec<LinearNode>,
+    primitives: Vec<Arc<dyn Hit>>,
+    unbounded: Vec<Arc<dyn Hit>>,
+}
+
+struct LinearNode {
+    min: [f64; 3],
+    max: [f64; 3],
+    /// The first primitive of a leaf, or the second child of an interior node.
+    offset: u32,
+    /// The number of primitives, zero for interior nodes.
+    count: u16,
+    /// The axis an interior node was split on, which decides the visiting order.
+    axis: u8,
+}
+
+/// An object with its bounds, as the builder sees it.
+struct Primitive {
+    object: Arc<dyn Hit>,
     bbox: AaBb,
+    centroid: Point3,
 }
 
 impl BvhNode {
-    pub fn newnew(list: HittableList, time0: f64, time1: f64) -> Arc<dyn Hit> {
-        BvhNode::split(list.objects, time0, time1)
-    }
-    fn split(src_objects: Vec<Arc<dyn Hit>>, time0: f64, time1: f64) -> Arc<dyn Hit> {
-        let mut objects = src_objects; //
-        let axis: i32 = random_i32(0, 2);
-        objects.sort_by(|a, b| {
-            a.bounding_box(time0, time1).unwrap().min()[axis]
-                .partial_cmp(&b.bounding_box(time0, time1).unwrap().min()[axis])
-                .unwrap()
-        }); //先sort一下
-        let mut left = objects[0].clone();
-        let mut right = objects[0].clone();
-        match objects.len() {
-            1 => {}
-            2 => right = objects[1].clone(),
-            _ => {
-                let mut l = objects;
-                let r = l.split_off(l.len() / 2);
-                left = BvhNode::split(l, time0, time1);
-                right = BvhNode::split(r, time0, time1);
+    pub fn newnew(list: HittableList, time0: f64, time1: f64) -> Result<Arc<dyn Hit>, BvhError> {
+        BvhNode::build(list, time0, time1, &BvhOptions::default())
+    }
+    /// Builds the hierarchy over `list`, which may be empty.
+    pub fn build(
+        list: HittableList,
+        time0: f64,
+        time1: f64,
+        options: &BvhOptions,
+    ) -> Result<Arc<dyn Hit>, BvhError> {
+        let mut primitives = Vec::with_capacity(list.objects.len());
+        let mut unbounded = Vec::new();
+        for (index, object) in list.objects.into_iter().enumerate() {
+            let bbox = match object.bounding_box(time0, time1) {
+                Some(bbox) => bbox,
+                None => {
+                    unbounded.push(object);
+                    continue;
+                }
+            };
+            let (min, max) = (bbox.min(), bbox.max());
+            if (0..3)
+                .any(|a| min.axis(a).is_nan() || max.axis(a).is_nan() || min.axis(a) > max.axis(a))
+            {
+                return Err(BvhError::InvalidBounds { index });
+            }
+            if (0..3).any(|a| min.axis(a).is_infinite() || max.axis(a).is_infinite()) {
+                unbounded.push(object);
+                continue;
             }
+            let centroid = 0.5 * (min + max);
+            primitives.push(Primitive {
+                object,
+                bbox,
+                centroid,
+            });
+        }
+        if primitives.len() > u32::MAX as usize {
+            return Err(BvhError::TooManyObjects(primitives.len()));
+        }
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * primitives.len()),
+            primitives: Vec::new(),
+            unbounded,
         };
-        let bbox = surrounding_box(
-            left.bounding_box(time0, time1).unwrap(),
-            right.bounding_box(time0, time1).unwrap(),
+        if !primitives.is_empty() {
+            match options.split {
+                // the random axes are repeatable, whatever the thread drew before
+                SplitMethod::Median => {
+                    with_thread_seed(SCENE_SEED, || bvh.split(&mut primitives, 0))
+                }
+                SplitMethod::Sah => {
+                    let max_leaf_size = options.max_leaf_size.clamp(1, u16::MAX as usize);
+                    bvh.split_sah(&mut primitives, 0, max_leaf_size, 0)
+                }
+            };
+        }
+        // the builders reorder the primitives in place so that every leaf is a range
+        bvh.primitives = primitives.into_iter().map(|p| p.object).collect();
+        Ok(Arc::new(bvh))
+    }
+    /// Appends an interior node, whose second child is filled in later.
+    fn push_interior(&mut self, bbox: &AaBb, axis: usize) -> usize {
+        self.nodes.push(LinearNode::new(bbox, 0, 0, axis));
+        self.nodes.len() - 1
+    }
+    fn push_leaf(&mut self, bbox: &AaBb, offset: usize, count: usize) {
+        self.nodes.push(LinearNode::new(bbox, offset, count, 0));
+    }
+    fn split(&mut self, primitives: &mut [Primitive], offset: usize) {
+        let bbox = enclosing_box(primitives);
+        if primitives.len() == 1 {
+            self.push_leaf(&bbox, offset, 1);
+            return;
+        }
+        let axis = random_i32(0, 2) as usize;
+        primitives.sort_by(|a, b| a.bbox.min().axis(axis).total_cmp(&b.bbox.min().axis(axis))); //先sort一下
+        self.push_halves(primitives, &bbox, axis, offset, &mut |bvh, half, offset| {
+            bvh.split(half, offset)
+        });
+    }
+    fn split_sah(
+        &mut self,
+        primitives: &mut [Primitive],
+        offset: usize,
+        max_leaf_size: usize,
+        depth: usize,
+    ) {
+        let n = primitives.len();
+        let bbox = enclosing_box(primitives);
+        if n == 1 {
+            self.push_leaf(&bbox, offset, 1);
+            return;
+        }
+        let (centroid_min, centroid_max) = primitives.iter().fold(
+            (primitives[0].centroid, primitives[0].centroid),
+            |(lo, hi), p| (lo.min(p.centroid), hi.max(p.centroid)),
         );
-        Arc::new(Self { left, right, bbox })
+        let widest = (0..3)
+            .max_by(|&a, &b| {
+                (centroid_max.axis(a) - centroid_min.axis(a))
+                    .total_cmp(&(centroid_max.axis(b) - centroid_min.axis(b)))
+            })
+            .unwrap();
+        let mut recurse = |bvh: &mut Self, half: &mut [Primitive], offset: usize| {
+            bvh.split_sah(half, offset, max_leaf_size, depth + 1)
+        };
+        if depth >= MAX_SAH_DEPTH {
+            if n <= max_leaf_size {
+                self.push_leaf(&bbox, offset, n);
+                return;
+            }
+            primitives.sort_by(|a, b| a.centroid.axis(widest).total_cmp(&b.centroid.axis(widest)));
+            self.push_halves(primitives, &bbox, widest, offset, &mut recurse);
+            return;
+        }
+        // the cheapest split as (cost, axis, first bin of the right child)
+        let mut best: Option<(f64, usize, usize)> = None;
+        for axis in 0..3 {
+            let extent = centroid_max.axis(axis) - centroid_min.axis(axis);
+            if extent <= 0.0 {
+                continue;
+            }
+            let bin_of = |p: &Primitive| {
+                let b = ((p.centroid.axis(axis) - centroid_min.axis(axis)) / extent
+                    * SAH_BINS as f64) as usize;
+                b.min(SAH_BINS - 1)
+            };
+            let mut counts = [0usize; SAH_BINS];
+            let mut bounds: Vec<Option<AaBb>> = vec![None; SAH_BINS];
+            for p in primitives.iter() {
+                let b = bin_of(p);
+                counts[b] += 1;
+                bounds[b] = Some(match bounds[b].take() {
+                    Some(bb) => surrounding_box(bb, p.bbox),
+                    None => p.bbox,
+                });
+            }
+            // area * count of everything left of each boundary, swept from both ends
+            let sweep = |bins: &mut dyn Iterator<Item = usize>| -> Vec<f64> {
+                let mut acc: Option<AaBb> = None;
+                let mut count = 0;
+                let mut costs = Vec::with_capacity(SAH_BINS - 1);
+                for b in bins.take(SAH_BINS - 1) {
+                    count += counts[b];
+                    if let Some(bb) = &bounds[b] {
+                        acc = Some(match acc.take() {
+                            Some(a) => surrounding_box(a, *bb),
+                            None => *bb,
+                        });
+                    }
+                    costs.push(acc.as_ref().map_or(0.0, |a| a.surface_area()) * count as f64);
+                }
+                costs
+            };
+            let left = sweep(&mut (0..SAH_BINS));
+            let mut right = sweep(&mut (0..SAH_BINS).rev());
+            right.reverse();
+            for split in 1..SAH_BINS {
+                let cost = left[split - 1] + right[split - 1];
+                if best
+                    .as_ref()
+                    .is_none_or(|(best_cost, _, _)| cost < *best_cost)
+                {
+                    best = Some((cost, axis, split));
+                }
+            }
+        }
+        let area = bbox.surface_area();
+        match best {
+            Some((cost, axis, split)) => {
+                let split_cost = TRAVERSAL_COST + cost / area;
+                if n <= max_leaf_size && split_cost >= n as f64 {
+                    self.push_leaf(&bbox, offset, n);
+                    return;
+                }
+                let extent = centroid_max.axis(axis) - centroid_min.axis(axis);
+                let mid = partition(primitives, |p| {
+                    let b = ((p.centroid.axis(axis) - centroid_min.axis(axis)) / extent
+                        * SAH_BINS as f64) as usize;
+                    b.min(SAH_BINS - 1) < split
+                });
+                let node = self.push_interior(&bbox, axis);
+                let (left, right) = primitives.split_at_mut(mid);
+                recurse(self, left, offset);
+                self.nodes[node].offset = self.nodes.len() as u32;
+                recurse(self, right, offset + mid);
+            }
+            // every centroid is in the same place, no split can separate them
+            None => {
+                if n <= max_leaf_size {
+                    self.push_leaf(&bbox, offset, n);
+                    return;
+                }
+                self.push_halves(primitives, &bbox, widest, offset, &mut recurse);
+            }
+        }
+    }
+    /// Splits `primitives` in two halves as they are ordered and builds both.
+    fn push_halves(
+        &mut self,
+        primitives: &mut [Primitive],
+        bbox: &AaBb,
+        axis: usize,
+        offset: usize,
+        build: &mut dyn FnMut(&mut Self, &mut [Primitive], usize),
+    ) {
+        let mid = primitives.len() / 2;
+        let node = self.push_interior(bbox, axis);
+        let (left, right) = primitives.split_at_mut(mid);
+        build(self, left, offset);
+        self.nodes[node].offset = self.nodes.len() as u32;
+        build(self, right, offset + mid);
+    }
+    /// Like `hit_nodes`, but stops at the first hit found.
+    fn occluded_nodes(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        let (o, d) = (r.orig(), r.dir());
+        let orig = [o.x(), o.y(), o.z()];
+        let inv_dir = [1.0 / d.x(), 1.0 / d.y(), 1.0 / d.z()];
+        let mut stack = [0usize; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if node.hit(&orig, &inv_dir, t_min, t_max) {
+                if node.count > 0 {
+                    let first = node.offset as usize;
+                    if self.primitives[first..first + node.count as usize]
+                        .iter()
+                        .any(|object| object.occluded(r, t_min, t_max))
+                    {
+                        return true;
+                    }
+                } else {
+                    stack[stack_len] = node.offset as usize;
+                    stack_len += 1;
+                    current += 1;
+                    continue;
+                }
+            }
+            if stack_len == 0 {
+                return false;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+    }
+    /// The closest hit among the bounded objects, the tree must not be empty.
+    fn hit_nodes(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (o, d) = (r.orig(), r.dir());
+        let orig = [o.x(), o.y(), o.z()];
+        let inv_dir = [1.0 / d.x(), 1.0 / d.y(), 1.0 / d.z()];
+        let mut closest = None;
+        let mut closest_t = t_max;
+        let mut stack = [0usize; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            // every hit shrinks the interval, so farther boxes are culled early
+            if node.hit(&orig, &inv_dir, t_min, closest_t) {
+                if node.count > 0 {
+                    let first = node.offset as usize;
+                    for object in &self.primitives[first..first + node.count as usize] {
+                        if let Some(rec) = object.hit(r, t_min, closest_t) {
+                            closest_t = rec.t;
+                            closest = Some(rec);
+                        }
+                    }
+                } else {
+                    // visit the child on the side the ray comes from first
+                    let (near, far) = if inv_dir[node.axis as usize] < 0.0 {
+                        (node.offset as usize, current + 1)
+                    } else {
+                        (current + 1, node.offset as usize)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    current = near;
+                    continue;
+                }
+            }
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+        closest
+    }
+}
+
+impl LinearNode {
+    fn new(bbox: &AaBb, offset: usize, count: usize, axis: usize) -> Self {
+        let (min, max) = (bbox.min(), bbox.max());
+        Self {
+            min: [min.x(), min.y(), min.z()],
+            max: [max.x(), max.y(), max.z()],
+            offset: offset as u32,
+            count: count as u16,
+            axis: axis as u8,
+        }
+    }
+    /// The slab test with the reciprocal direction worked out once per ray.
+    fn hit(&self, orig: &[f64; 3], inv_dir: &[f64; 3], t_min: f64, t_max: f64) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for a in 0..3 {
+            let mut t0 = (self.min[a] - orig[a]) * inv_dir[a];
+            let mut t1 = (self.max[a] - orig[a]) * inv_dir[a];
+            if inv_dir[a] < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t0.max(t_min);
+            t_max = t1.min(t_max);
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn enclosing_box(primitives: &[Primitive]) -> AaBb {
+    primitives[1..]
+        .iter()
+        .fold(primitives[0].bbox, |b, p| surrounding_box(b, p.bbox))
+}
+
+/// Moves the items matching `pred` to the front and returns how many there are.
+fn partition<T>(items: &mut [T], pred: impl Fn(&T) -> bool) -> usize {
+    let mut first = 0;
+    for i in 0..items.len() {
+        if pred(&items[i]) {
+            items.swap(first, i);
+            first += 1;
+        }
     }
+    first
 }
+
 impl Hit for BvhNode {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        if !self.bbox.hit(r, t_min, t_max) {
-            return None;
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let mut closest = None;
+        let mut closest_t = t_max;
+        if !self.nodes.is_empty() {
+            closest = self.hit_nodes(r, t_min, t_max);
+            if let Some(rec) = &closest {
+                closest_t = rec.t;
+            }
         }
-        let hit_left = self.left.hit(r, t_min, t_max);
-        let hit_right = self.right.hit(r, t_min, t_max);
-        match (hit_left, hit_right) {
-            (Some(hit_left), Some(hit_right)) => {
-                if hit_left.t < hit_right.t {
-                    Some(hit_left)
-                } else {
-                    Some(hit_right)
-                } //先碰到左边/右边的光线就取那边的继续找下去
+        for object in &self.unbounded {
+            if let Some(rec) = object.hit(r, t_min, closest_t) {
+                closest_t = rec.t;
+                closest = Some(rec);
             }
-            (Some(hit_left), None) => Some(hit_left),
-            (None, Some(hit_right)) => Some(hit_right),
-            (None, None) => None,
         }
+        closest
+    }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        (!self.nodes.is_empty() && self.occluded_nodes(r, t_min, t_max))
+            || self
+                .unbounded
+                .iter()
+                .any(|object| object.occluded(r, t_min, t_max))
     }
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AaBb> {
-        Some(self.bbox.clone())
+        if self.nodes.is_empty() || !self.unbounded.is_empty() {
+            return None;
+        }
+        let root = &self.nodes[0];
+        Some(AaBb::new(
+            Point3::new(root.min[0], root.min[1], root.min[2]),
+            Point3::new(root.max[0], root.max[1], root.max[2]),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Material};
+    use crate::sphere::Sphere;
+    use crate::vec3::{Color, Vec3};
+
+    #[test]
+    fn test_traversal_finds_closest_hit() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new_color(Color::ones()));
+        let mut spheres = Vec::new();
+        for i in 0..10 {
+            for j in 0..10 {
+                for k in 0..10 {
+                    let center = Point3::new(i as f64, j as f64, k as f64 * 1.5);
+                    let sphere: Arc<dyn Hit> = Arc::new(Sphere::new(center, 0.3, material.clone()));
+                    spheres.push(sphere);
+                }
+            }
+        }
+        let list = || HittableList {
+            objects: spheres.clone(),
+        };
+        let sah = BvhNode::newnew(list(), 0.0, 1.0).unwrap();
+        let median = BvhNode::build(
+            list(),
+            0.0,
+            1.0,
+            &BvhOptions {
+                split: SplitMethod::Median,
+                ..BvhOptions::default()
+            },
+        )
+        .unwrap();
+        let brute = list();
+        for i in 0..200 {
+            let x = i as f64 * 0.05;
+            // rays both ways along every axis, so both visiting orders are used
+            let dir = if i % 2 == 0 {
+                Vec3::new(1.0, 0.1, -1.5)
+            } else {
+                Vec3::new(-1.0, -0.1, 1.5)
+            };
+            let orig = if i % 2 == 0 {
+                Point3::new(-5.0, x, 20.0)
+            } else {
+                Point3::new(15.0, x, -5.0)
+            };
+            let r = Ray::new(orig, dir, 0.0);
+            let expected = brute.hit(&r, 0.001, f64::INFINITY).map(|rec| rec.t);
+            let a = sah.hit(&r, 0.001, f64::INFINITY).map(|rec| rec.t);
+            let b = median.hit(&r, 0.001, f64::INFINITY).map(|rec| rec.t);
+            assert_eq!(a, expected);
+            assert_eq!(b, expected);
+            assert_eq!(sah.occluded(&r, 0.001, f64::INFINITY), expected.is_some());
+            // only what lies before the closest hit can block a shorter ray
+            if let Some(t) = expected {
+                assert!(!sah.occluded(&r, 0.001, t * 0.999));
+            }
+        }
+    }
+
+    /// The plane y = 0, which has no bounding box.
+    struct Floor(Arc<dyn Material>);
+
+    impl Hit for Floor {
+        fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+            let t = -r.orig().y() / r.dir().y();
+            if !(t_min..=t_max).contains(&t) {
+                return None;
+            }
+            let normal = Vec3::new(0.0, 1.0, 0.0);
+            Some(HitRecord::new(
+                r.at(t),
+                t,
+                0.0,
+                0.0,
+                &normal,
+                r.clone(),
+                &*self.0,
+            ))
+        }
+        fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AaBb> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_empty_and_unbounded() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new_color(Color::ones()));
+        let down = Ray::new(Point3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+
+        let empty = BvhNode::newnew(HittableList::new(), 0.0, 1.0).unwrap();
+        assert!(empty.hit(&down, 0.001, f64::INFINITY).is_none());
+        assert!(empty.bounding_box(0.0, 1.0).is_none());
+
+        let mut list = HittableList::new();
+        list.add(Arc::new(Floor(material.clone())));
+        let bvh = BvhNode::newnew(list, 0.0, 1.0).unwrap();
+        assert_eq!(bvh.hit(&down, 0.001, f64::INFINITY).unwrap().t, 5.0);
+
+        // the sphere in front of the floor wins, the floor is hit once it is missed
+        let mut list = HittableList::new();
+        list.add(Arc::new(Floor(material.clone())));
+        list.add(Arc::new(Sphere::new(
+            Point3::new(0.0, 2.0, 0.0),
+            1.0,
+            material.clone(),
+        )));
+        let bvh = BvhNode::newnew(list, 0.0, 1.0).unwrap();
+        assert!(bvh.bounding_box(0.0, 1.0).is_none());
+        assert_eq!(bvh.hit(&down, 0.001, f64::INFINITY).unwrap().t, 2.0);
+        let beside = Ray::new(Point3::new(3.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        assert_eq!(bvh.hit(&beside, 0.001, f64::INFINITY).unwrap().t, 5.0);
+
+        let mut list = HittableList::new();
+        list.add(Arc::new(Sphere::new(Point3::zero(), 1.0, material.clone())));
+        list.add(Arc::new(Sphere::new(
+            Point3::new(f64::NAN, 0.0, 0.0),
+            1.0,
+            material,
+        )));
+        assert!(matches!(
+            BvhNode::newnew(list, 0.0, 1.0),
+            Err(BvhError::InvalidBounds { index: 1 })
+        ));
     }
 }
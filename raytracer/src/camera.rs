@@ -1,4 +1,4 @@
-use crate::rtweekend::random;
+use crate::sampler::sample_1d;
 use crate::{ray::*, rtweekend::degrees_to_radians, vec3::*};
 #[derive(Clone)]
 pub struct Camera {
@@ -30,13 +30,13 @@ impl Camera {
         let h = (theta / 2.0).tan();
         let viewpoint_height = 2.0 * h;
         let viewpoint_width = aspect_ratio * viewpoint_height;
-        let ww = (lookfrom.clone() - lookat).unit_vector();
-        let uu = (vup.cross(ww.clone())).unit_vector();
-        let vv = ww.cross(uu.clone());
+        let ww = (lookfrom - lookat).unit_vector();
+        let uu = (vup.cross(ww)).unit_vector();
+        let vv = ww.cross(uu);
         let ori = lookfrom;
-        let hori = focus_dist * viewpoint_width * uu.clone();
-        let vert = focus_dist * viewpoint_height * vv.clone();
-        let llc = ori.clone() - hori.clone() / 2.0 - vert.clone() / 2.0 - focus_dist * ww;
+        let hori = focus_dist * viewpoint_width * uu;
+        let vert = focus_dist * viewpoint_height * vv;
+        let llc = ori - hori / 2.0 - vert / 2.0 - focus_dist * ww;
         Self {
             origin: ori,
             horizontal: hori,
@@ -50,17 +50,22 @@ impl Camera {
             time1,
         }
     }
+    /// Widens or narrows the view to a new aspect ratio, keeping the vertical field of view.
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
+        let scale = aspect_ratio * self.vertical.length() / self.horizontal.length();
+        let horizontal = self.horizontal * scale;
+        self.lower_left_corner += (self.horizontal - horizontal) / 2.0;
+        self.horizontal = horizontal;
+    }
+    /// The ray through `(s, t)` on the view, from a point on the lens (2D) at a time
+    /// in the shutter interval (1D), both drawn from the sampler of the thread.
     pub fn get_ray(&self, s: f64, t: f64) -> Ray {
         let rd = self.lens_radius * random_in_unit_disk();
-        let offset = self.u.clone() * rd.x() + self.v.clone() * rd.y();
+        let offset = self.u * rd.x() + self.v * rd.y();
         Ray::new(
-            self.origin.clone() + offset.clone(),
-            self.lower_left_corner.clone()
-                + self.horizontal.clone() * s
-                + self.vertical.clone() * t
-                - self.origin.clone()
-                - offset,
-            random(self.time0, self.time1),
+            self.origin + offset,
+            self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset,
+            self.time0 + (self.time1 - self.time0) * sample_1d(),
         )
     }
 }
@@ -0,0 +1,212 @@
+//! Saving the samples of an unfinished render, to carry on with it later.
+//!
+//! Every sample is drawn from a stream fixed by the seed, the pixel and its index, so
+//! the seed and the sampler are all of the random state there is, along with the size
+//! of the passes that the stratified sampler stratifies. The scene is not
+//! stored: a render has to be resumed with the scene that it was started with.
+use crate::film::{PixelStats, SampleFilm};
+use crate::filter::Filter;
+use crate::sampler::SamplerKind;
+use crate::vec3::Color;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"RTCKPT03";
+/// The bytes of one pixel: its sample count, mean, squared deviations and filtered sums.
+const PIXEL_BYTES: usize = 4 + 8 * 8;
+
+/// What a checkpoint records besides the samples, enough to take the next ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CheckpointInfo {
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    pub max_depth: usize,
+    pub pass_samples: usize,
+    pub filter: Filter,
+}
+
+/// Writes the samples of `film` and `info`, all little-endian.
+pub fn write_checkpoint<W: Write>(
+    info: &CheckpointInfo,
+    film: &SampleFilm,
+    mut w: W,
+) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    write_name(&mut w, info.sampler.name())?;
+    w.write_all(&info.seed.to_le_bytes())?;
+    w.write_all(&(info.max_depth as u32).to_le_bytes())?;
+    w.write_all(&(info.pass_samples as u32).to_le_bytes())?;
+    write_name(&mut w, info.filter.name())?;
+    let parameters = match info.filter {
+        Filter::Mitchell { radius, b, c } => [radius, b, c],
+        filter => [filter.radius(), 0.0, 0.0],
+    };
+    for v in parameters {
+        w.write_all(&v.to_le_bytes())?;
+    }
+    w.write_all(&(film.width() as u32).to_le_bytes())?;
+    w.write_all(&(film.height() as u32).to_le_bytes())?;
+    let mut bytes = Vec::with_capacity(PIXEL_BYTES * film.width());
+    for y in 0..film.height() {
+        bytes.clear();
+        for x in 0..film.width() {
+            let (count, mean, m2) = film.pixel(x, y).raw();
+            bytes.extend(count.to_le_bytes());
+            let [r, g, b, weight] = film.filtered(x, y);
+            for v in [mean.x(), mean.y(), mean.z(), m2, r, g, b, weight] {
+                bytes.extend(v.to_le_bytes());
+            }
+        }
+        w.write_all(&bytes)?;
+    }
+    w.flush()
+}
+
+/// Reads back what [`write_checkpoint`] wrote. Anything that is not a whole checkpoint
+/// is [`InvalidData`](io::ErrorKind::InvalidData), found before the film is allocated.
+pub fn read_checkpoint<R: Read>(mut r: R) -> io::Result<(CheckpointInfo, SampleFilm)> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a render checkpoint"));
+    }
+    let sampler = SamplerKind::from_name(&read_name(&mut r)?)
+        .ok_or_else(|| invalid("unknown sampler in checkpoint"))?;
+    let seed = u64::from_le_bytes(read_array(&mut r)?);
+    let max_depth = u32::from_le_bytes(read_array(&mut r)?) as usize;
+    let pass_samples = u32::from_le_bytes(read_array(&mut r)?) as usize;
+    if pass_samples == 0 {
+        return Err(invalid("no samples per pass in checkpoint"));
+    }
+    let filter = Filter::from_name(&read_name(&mut r)?)
+        .ok_or_else(|| invalid("unknown filter in checkpoint"))?;
+    let mut parameters = [0.0; 3];
+    for v in &mut parameters {
+        *v = f64::from_le_bytes(read_array(&mut r)?);
+    }
+    let finite = parameters.iter().all(|v| v.is_finite());
+    if !finite || !Filter::RADII.contains(&parameters[0]) {
+        return Err(invalid("invalid filter in checkpoint"));
+    }
+    let filter = match filter {
+        Filter::Mitchell { .. } => Filter::Mitchell {
+            radius: parameters[0],
+            b: parameters[1],
+            c: parameters[2],
+        },
+        filter => filter.with_radius(parameters[0]),
+    };
+    let width = u32::from_le_bytes(read_array(&mut r)?) as usize;
+    let height = u32::from_le_bytes(read_array(&mut r)?) as usize;
+    let size = width
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(PIXEL_BYTES))
+        .ok_or_else(|| invalid("checkpoint too large"))?;
+    // read before allocating the film, so that a corrupt size runs out of bytes first
+    let mut body = Vec::new();
+    r.take(size as u64 + 1).read_to_end(&mut body)?;
+    if body.len() != size {
+        return Err(invalid("checkpoint size does not match its pixels"));
+    }
+    let mut body = &body[..];
+    let mut film = SampleFilm::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let count = u32::from_le_bytes(read_array(&mut body)?);
+            let mut v = [0.0; 8];
+            for v in &mut v {
+                *v = f64::from_le_bytes(read_array(&mut body)?);
+            }
+            let mean = Color::new(v[0], v[1], v[2]);
+            *film.pixel_mut(x, y) = PixelStats::from_raw(count, mean, v[3]);
+            film.filtered_mut(x, y).copy_from_slice(&v[4..]);
+        }
+    }
+    let info = CheckpointInfo {
+        seed,
+        sampler,
+        max_depth,
+        pass_samples,
+        filter,
+    };
+    Ok((info, film))
+}
+
+/// Writes a checkpoint to `path` by way of a temporary file next to it, so that dying
+/// halfway through leaves the previous checkpoint as it was.
+pub fn save_checkpoint(path: &Path, info: &CheckpointInfo, film: &SampleFilm) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    write_checkpoint(info, film, BufWriter::new(File::create(&temporary)?))?;
+    fs::rename(&temporary, path)
+}
+
+pub fn load_checkpoint(path: &Path) -> io::Result<(CheckpointInfo, SampleFilm)> {
+    read_checkpoint(BufReader::new(File::open(path)?))
+}
+
+fn write_name(w: &mut impl Write, name: &str) -> io::Result<()> {
+    w.write_all(&[name.len() as u8])?;
+    w.write_all(name.as_bytes())
+}
+
+fn read_name(r: &mut impl Read) -> io::Result<String> {
+    let mut name = vec![0; read_array::<1>(r)?[0] as usize];
+    r.read_exact(&mut name)?;
+    Ok(String::from_utf8_lossy(&name).into_owned())
+}
+
+fn read_array<const N: usize>(r: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::film::Splats;
+
+    #[test]
+    fn test_checkpoint_reads_back() {
+        let filter = Filter::from_name("mitchell").unwrap().with_radius(1.5);
+        let mut film = SampleFilm::new(3, 2);
+        let mut splats = Splats::new(filter, 0..3, 1..2, 3, 2);
+        for (i, v) in [0.5, 2.0, 0.25].into_iter().enumerate() {
+            film.pixel_mut(i, 1).add(&Color::new(v, 1.0, 0.0));
+            film.pixel_mut(i, 1).add(&Color::new(0.0, v, 3.0));
+            splats.add(i as f64 + 0.3, 1.6, &Color::new(v, 1.0, 0.0));
+        }
+        film.add_splats(&splats);
+        let info = CheckpointInfo {
+            seed: 0xDEAD_BEEF_0000_0001,
+            sampler: SamplerKind::Halton,
+            max_depth: 12,
+            pass_samples: 8,
+            filter,
+        };
+        let mut bytes = Vec::new();
+        write_checkpoint(&info, &film, &mut bytes).unwrap();
+        assert_eq!(read_checkpoint(&bytes[..]).unwrap(), (info, film));
+        let error = |bytes: &[u8]| read_checkpoint(bytes).unwrap_err().kind();
+        assert_eq!(error(&bytes[..bytes.len() - 1]), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error(&[&bytes[..], &[0]].concat()),
+            io::ErrorKind::InvalidData
+        );
+        // a header claiming 2^32 - 1 pixels squared, with none after it
+        let pixels = bytes.len() - 3 * 2 * PIXEL_BYTES;
+        let huge = [&bytes[..pixels - 8], &[0xFF; 8]].concat();
+        assert_eq!(error(&huge), io::ErrorKind::InvalidData);
+        let radius = bytes.iter().position(|&b| b == b'm').unwrap() + "mitchell".len();
+        for bad in [0.0, 0.25, -1.0, 1e300, f64::NAN, f64::INFINITY] {
+            let mut broken = bytes.clone();
+            broken[radius..radius + 8].copy_from_slice(&bad.to_le_bytes());
+            assert_eq!(error(&broken), io::ErrorKind::InvalidData);
+        }
+        bytes[0] = b'X';
+        assert!(read_checkpoint(&bytes[..]).is_err());
+    }
+}
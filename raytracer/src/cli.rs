@@ -0,0 +1,624 @@
+//! Command-line options of the renderer.
+use raytracer::aov::Aov;
+use raytracer::filter::Filter;
+use raytracer::output::ExrCompression;
+use raytracer::sampler::SamplerKind;
+use raytracer::scenes;
+use raytracer::tonemap::ToneMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+Usage: raytracer [OPTIONS] [SCENE_FILE]
+
+Renders SCENE_FILE, or a built-in scene if no file is given.
+
+Options:
+  -s, --scene <NAME>         built-in scene to render [default: cornell_box]
+                             one of: random, two_spheres, two_perlin_spheres, earth,
+                             simple_light, cornell_box, cornell_smoke, final
+  -w, --width <PIXELS>       image width [default: from the scene]
+      --height <PIXELS>      image height [default: width / aspect ratio of the scene]
+  -n, --samples <N>          samples per pixel, the most a pixel gets with
+                             --noise-threshold [default: from the scene]
+      --noise-threshold <E>  sample adaptively, until the relative standard error of
+                             every pixel and its neighbours is below E (e.g. 0.01)
+      --min-samples <N>      samples of every pixel before its noise is measured
+                             [default: 16]
+      --pass-samples <N>     samples a pixel gets per pass [default: 16]
+      --time-limit <TIME>    stop after TIME (e.g. 90, 90s, 10m or 1.5h) and write
+                             the image so far, with no limit on the samples unless
+                             --samples is given; Ctrl-C stops early the same way
+      --checkpoint <PATH>    save the samples so far to PATH between passes, and
+                             at the end
+      --checkpoint-every <S> least seconds from one checkpoint to the next [default: 60]
+      --resume <PATH>        carry on with the render saved in a checkpoint, up to
+                             --samples; size, seed, sampler, filter, depth and pass
+                             samples come from it, and it is updated unless
+                             --checkpoint goes elsewhere
+  -d, --max-depth <N>        maximum number of bounces [default: from the scene]
+  -t, --threads <N>          number of render threads [default: number of cores]
+      --tile-size <PIXELS>   side of the square tiles handed to the threads [default: 16]
+      --seed <N>             seed for the random numbers, makes renders repeatable
+      --sampler <NAME>       sample sequence [default: sobol]
+                             one of: independent, stratified, halton, sobol
+      --filter <NAME>        pixel reconstruction filter [default: box]
+                             one of: box, triangle, gaussian, mitchell, lanczos
+      --filter-radius <PX>   reach of the filter, from 0.5 to 16 [default: 0.5, 1,
+                             1.5, 2 and 3 for the filters in that order]
+  -o, --output <PATH>        output image [default: output/test.jpg]
+  -f, --format <FORMAT>      output format [default: from the extension of the output]
+                             one of: jpeg, png, ppm, bmp (sRGB-encoded),
+                             or pfm, hdr, exr for the linear float film
+  -q, --quality <1-100>      JPEG quality [default: 60]
+      --bit-depth <8|16>     bits per channel of PNG and PPM output [default: 8]
+      --dither               dither the sRGB-encoded output instead of banding
+      --exposure <EV>        scale the image by 2^EV before tone mapping [default: 0]
+      --tonemap <NAME>       how radiance above 1 is shown [default: clamp]
+                             one of: clamp, reinhard, reinhard-extended, hable, aces
+      --white <L>            luminance that reinhard-extended maps to white
+                             [default: the brightest pixel]
+                             exposure and tone mapping leave pfm, hdr and exr output alone
+      --exr-compression <C>  none or zip [default: zip]
+      --heatmap <PATH>       also write the sample count of every pixel as an image
+      --aov <LIST>           also write these passes of what the camera sees first,
+                             comma-separated, or all: albedo, normal, depth,
+                             position, uv, object, material; as layers of exr
+                             output, else next to it as <output>.<pass>.<ext>
+  -h, --help                 print this help
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Jpeg,
+    Png,
+    Ppm,
+    Bmp,
+    Pfm,
+    Hdr,
+    Exr,
+}
+
+impl OutputFormat {
+    /// The format called `name`, or with `name` as file extension.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "jpeg" | "jpg" => Some(Self::Jpeg),
+            "png" => Some(Self::Png),
+            "ppm" => Some(Self::Ppm),
+            "bmp" => Some(Self::Bmp),
+            "pfm" => Some(Self::Pfm),
+            "hdr" => Some(Self::Hdr),
+            "exr" => Some(Self::Exr),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SceneSource {
+    Builtin(String),
+    File(PathBuf),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub scene: SceneSource,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub samples_per_pixel: Option<usize>,
+    pub noise_threshold: Option<f64>,
+    pub min_samples: Option<usize>,
+    pub pass_samples: Option<usize>,
+    pub time_limit: Option<Duration>,
+    pub max_depth: Option<usize>,
+    pub threads: Option<usize>,
+    pub tile_size: Option<usize>,
+    pub seed: Option<u64>,
+    pub sampler: Option<SamplerKind>,
+    pub filter: Option<Filter>,
+    pub output: PathBuf,
+    pub format: OutputFormat,
+    pub quality: u8,
+    pub bit_depth: u8,
+    pub dither: bool,
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    pub exr_compression: ExrCompression,
+    pub heatmap: Option<PathBuf>,
+    pub aovs: Vec<Aov>,
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_interval: f64,
+    pub resume: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            scene: SceneSource::Builtin("cornell_box".to_string()),
+            width: None,
+            height: None,
+            samples_per_pixel: None,
+            noise_threshold: None,
+            min_samples: None,
+            pass_samples: None,
+            time_limit: None,
+            max_depth: None,
+            threads: None,
+            tile_size: None,
+            seed: None,
+            sampler: None,
+            filter: None,
+            output: PathBuf::from("output/test.jpg"),
+            format: OutputFormat::Jpeg,
+            quality: 60,
+            bit_depth: 8,
+            dither: false,
+            exposure: 0.0,
+            tone_map: ToneMap::Clamp,
+            exr_compression: ExrCompression::Zip,
+            heatmap: None,
+            aovs: Vec::new(),
+            checkpoint: None,
+            checkpoint_interval: 60.0,
+            resume: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Help,
+    Render(Box<Options>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CliError(pub String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// Parses the arguments, not including the program name.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, CliError> {
+    let mut options = Options::default();
+    let mut scene_name = None;
+    let mut scene_file = None;
+    let mut format = None;
+    let mut white = None;
+    let mut filter_radius = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // accept both `--flag value` and `--flag=value`
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value)),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || -> Result<String, CliError> {
+            match inline {
+                Some(value) => Ok(value.to_string()),
+                None => args
+                    .next()
+                    .ok_or_else(|| CliError(format!("missing value for `{}`", flag))),
+            }
+        };
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-s" | "--scene" => {
+                let name = value()?;
+                if !scenes::NAMES.contains(&name.as_str()) {
+                    return Err(CliError(format!("unknown scene `{}`", name)));
+                }
+                scene_name = Some(name);
+            }
+            "-w" | "--width" => options.width = Some(positive(&flag, &value()?)?),
+            "--height" => options.height = Some(positive(&flag, &value()?)?),
+            "-n" | "--samples" => options.samples_per_pixel = Some(positive(&flag, &value()?)?),
+            "--noise-threshold" => {
+                let v = value()?;
+                match number(&flag, &v)? {
+                    e if e > 0.0 => options.noise_threshold = Some(e),
+                    _ => {
+                        return Err(CliError(format!(
+                            "invalid value `{}` for `{}`: expected a positive number",
+                            v, flag
+                        )))
+                    }
+                }
+            }
+            "--min-samples" => options.min_samples = Some(positive(&flag, &value()?)?),
+            "--pass-samples" => options.pass_samples = Some(positive(&flag, &value()?)?),
+            "--time-limit" => options.time_limit = Some(duration(&flag, &value()?)?),
+            "-d" | "--max-depth" => options.max_depth = Some(positive(&flag, &value()?)?),
+            "-t" | "--threads" => options.threads = Some(positive(&flag, &value()?)?),
+            "--tile-size" => options.tile_size = Some(positive(&flag, &value()?)?),
+            "--seed" => {
+                let v = value()?;
+                options.seed = Some(v.parse().map_err(|_| {
+                    CliError(format!(
+                        "invalid value `{}` for `{}`: expected an integer",
+                        v, flag
+                    ))
+                })?);
+            }
+            "--sampler" => {
+                let name = value()?;
+                options.sampler = Some(
+                    SamplerKind::from_name(&name)
+                        .ok_or_else(|| CliError(format!("unknown sampler `{}`", name)))?,
+                );
+            }
+            "--filter" => {
+                let name = value()?;
+                options.filter = Some(
+                    Filter::from_name(&name)
+                        .ok_or_else(|| CliError(format!("unknown filter `{}`", name)))?,
+                );
+            }
+            "--filter-radius" => {
+                let v = value()?;
+                match number(&flag, &v)? {
+                    r if Filter::RADII.contains(&r) => filter_radius = Some(r),
+                    _ => {
+                        return Err(CliError(format!(
+                            "invalid value `{}` for `{}`: expected a number from {} to {}",
+                            v,
+                            flag,
+                            Filter::RADII.start(),
+                            Filter::RADII.end()
+                        )))
+                    }
+                }
+            }
+            "-o" | "--output" => options.output = PathBuf::from(value()?),
+            "-f" | "--format" => {
+                let name = value()?;
+                format = Some(
+                    OutputFormat::from_name(&name)
+                        .ok_or_else(|| CliError(format!("unknown output format `{}`", name)))?,
+                );
+            }
+            "-q" | "--quality" => {
+                let v = value()?;
+                options.quality = match v.parse::<u8>() {
+                    Ok(q) if (1..=100).contains(&q) => q,
+                    _ => {
+                        return Err(CliError(format!(
+                            "invalid value `{}` for `{}`: expected 1 to 100",
+                            v, flag
+                        )))
+                    }
+                };
+            }
+            "--bit-depth" => {
+                let v = value()?;
+                options.bit_depth = match v.as_str() {
+                    "8" => 8,
+                    "16" => 16,
+                    _ => {
+                        return Err(CliError(format!(
+                            "invalid value `{}` for `{}`: expected 8 or 16",
+                            v, flag
+                        )))
+                    }
+                };
+            }
+            "--dither" => options.dither = true,
+            "--exposure" => options.exposure = number(&flag, &value()?)?,
+            "--tonemap" => {
+                let name = value()?;
+                options.tone_map = ToneMap::from_name(&name)
+                    .ok_or_else(|| CliError(format!("unknown tone mapping `{}`", name)))?;
+            }
+            "--white" => {
+                let v = value()?;
+                match number(&flag, &v)? {
+                    w if w > 0.0 => white = Some(w),
+                    _ => {
+                        return Err(CliError(format!(
+                            "invalid value `{}` for `{}`: expected a positive number",
+                            v, flag
+                        )))
+                    }
+                }
+            }
+            "--exr-compression" => {
+                options.exr_compression = match value()?.to_ascii_lowercase().as_str() {
+                    "none" => ExrCompression::None,
+                    "zip" => ExrCompression::Zip,
+                    other => return Err(CliError(format!("unknown EXR compression `{}`", other))),
+                }
+            }
+            "--heatmap" => options.heatmap = Some(PathBuf::from(value()?)),
+            "--aov" => {
+                for name in value()?.split(',') {
+                    let aovs = match name {
+                        "all" => Aov::ALL.to_vec(),
+                        name => vec![Aov::from_name(name)
+                            .ok_or_else(|| CliError(format!("unknown pass `{}`", name)))?],
+                    };
+                    for aov in aovs {
+                        if !options.aovs.contains(&aov) {
+                            options.aovs.push(aov);
+                        }
+                    }
+                }
+            }
+            "--checkpoint" => options.checkpoint = Some(PathBuf::from(value()?)),
+            "--checkpoint-every" => {
+                let v = value()?;
+                match number(&flag, &v)? {
+                    s if s >= 0.0 => options.checkpoint_interval = s,
+                    _ => {
+                        return Err(CliError(format!(
+                            "invalid value `{}` for `{}`: expected seconds",
+                            v, flag
+                        )))
+                    }
+                }
+            }
+            "--resume" => options.resume = Some(PathBuf::from(value()?)),
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(CliError(format!("unknown option `{}`", arg)));
+            }
+            _ => {
+                if scene_file.is_some() {
+                    return Err(CliError(format!("unexpected argument `{}`", arg)));
+                }
+                scene_file = Some(PathBuf::from(arg));
+            }
+        }
+    }
+    options.scene = match (scene_name, scene_file) {
+        (Some(_), Some(_)) => {
+            return Err(CliError(
+                "give either a scene file or `--scene`, not both".to_string(),
+            ))
+        }
+        (Some(name), None) => SceneSource::Builtin(name),
+        (None, Some(file)) => SceneSource::File(file),
+        (None, None) => options.scene,
+    };
+    options.format = match format {
+        Some(format) => format,
+        None => options
+            .output
+            .extension()
+            .and_then(|e| OutputFormat::from_name(&e.to_string_lossy()))
+            .ok_or_else(|| {
+                CliError(format!(
+                    "cannot tell the format of `{}` from its extension, give `--format`",
+                    options.output.display()
+                ))
+            })?,
+    };
+    if let Some(radius) = filter_radius {
+        options.filter = Some(options.filter.unwrap_or_default().with_radius(radius));
+    }
+    if let Some(w) = white {
+        match &mut options.tone_map {
+            ToneMap::ReinhardExtended { white } => *white = Some(w),
+            _ => {
+                return Err(CliError(
+                    "`--white` goes with `--tonemap reinhard-extended`".to_string(),
+                ))
+            }
+        }
+    }
+    if let Some(heatmap) = &options.heatmap {
+        let format = heatmap
+            .extension()
+            .and_then(|e| OutputFormat::from_name(&e.to_string_lossy()));
+        if !matches!(
+            format,
+            Some(OutputFormat::Jpeg | OutputFormat::Png | OutputFormat::Ppm | OutputFormat::Bmp)
+        ) {
+            return Err(CliError(format!(
+                "the heatmap `{}` needs a .png, .ppm, .bmp or .jpg extension",
+                heatmap.display()
+            )));
+        }
+    }
+    if options.bit_depth == 16 && !matches!(options.format, OutputFormat::Png | OutputFormat::Ppm) {
+        return Err(CliError(
+            "16 bits per channel needs PNG or PPM output".to_string(),
+        ));
+    }
+    Ok(Command::Render(Box::new(options)))
+}
+
+fn number(flag: &str, value: &str) -> Result<f64, CliError> {
+    match value.parse::<f64>() {
+        Ok(x) if x.is_finite() => Ok(x),
+        _ => Err(CliError(format!(
+            "invalid value `{}` for `{}`: expected a number",
+            value, flag
+        ))),
+    }
+}
+
+/// Seconds, or a number of seconds, minutes or hours with an `s`, `m` or `h` after it.
+fn duration(flag: &str, value: &str) -> Result<Duration, CliError> {
+    let (number, unit) = match value.char_indices().last() {
+        Some((i, 's')) => (&value[..i], 1.0),
+        Some((i, 'm')) => (&value[..i], 60.0),
+        Some((i, 'h')) => (&value[..i], 3600.0),
+        _ => (value, 1.0),
+    };
+    let seconds = number
+        .parse::<f64>()
+        .ok()
+        .filter(|&x| x > 0.0)
+        .map(|x| x * unit);
+    match seconds.and_then(|s| Duration::try_from_secs_f64(s).ok()) {
+        Some(duration) => Ok(duration),
+        None => Err(CliError(format!(
+            "invalid value `{}` for `{}`: expected a time such as 90s, 10m or 1.5h",
+            value, flag
+        ))),
+    }
+}
+
+fn positive(flag: &str, value: &str) -> Result<usize, CliError> {
+    match value.parse::<usize>() {
+        Ok(x) if x > 0 => Ok(x),
+        _ => Err(CliError(format!(
+            "invalid value `{}` for `{}`: expected a positive integer",
+            value, flag
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, CliError> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(parse(&[]), Ok(Command::Render(Box::default())));
+        assert_eq!(parse(&["--help"]), Ok(Command::Help));
+    }
+
+    #[test]
+    fn test_options() {
+        let command = parse(&[
+            "scenes/cornell_box.scene",
+            "-w",
+            "300",
+            "--samples=16",
+            "--threads",
+            "4",
+            "--tile-size=8",
+            "--seed",
+            "42",
+            "--sampler",
+            "halton",
+            "-o",
+            "out.png",
+            "--format",
+            "exr",
+            "--exr-compression",
+            "none",
+            "--noise-threshold",
+            "0.02",
+            "--min-samples=8",
+            "--heatmap",
+            "heat.png",
+            "--pass-samples",
+            "32",
+            "--resume",
+            "render.ckpt",
+            "--checkpoint-every=0",
+            "--time-limit",
+            "1.5m",
+            "--filter-radius=2.5",
+            "--filter",
+            "gaussian",
+            "--aov=depth,normal",
+            "--aov",
+            "normal,object",
+        ])
+        .unwrap();
+        let expected = Options {
+            scene: SceneSource::File(PathBuf::from("scenes/cornell_box.scene")),
+            width: Some(300),
+            samples_per_pixel: Some(16),
+            threads: Some(4),
+            tile_size: Some(8),
+            seed: Some(42),
+            sampler: Some(SamplerKind::Halton),
+            output: PathBuf::from("out.png"),
+            format: OutputFormat::Exr,
+            exr_compression: ExrCompression::None,
+            noise_threshold: Some(0.02),
+            min_samples: Some(8),
+            heatmap: Some(PathBuf::from("heat.png")),
+            pass_samples: Some(32),
+            resume: Some(PathBuf::from("render.ckpt")),
+            checkpoint_interval: 0.0,
+            time_limit: Some(Duration::from_secs(90)),
+            filter: Some(Filter::Gaussian { radius: 2.5 }),
+            aovs: vec![Aov::Depth, Aov::Normal, Aov::ObjectId],
+            ..Options::default()
+        };
+        assert_eq!(command, Command::Render(Box::new(expected)));
+        // the format follows the extension unless given
+        let command = parse(&[
+            "-o",
+            "out/a.PPM",
+            "--bit-depth",
+            "16",
+            "--dither",
+            "--exposure=-1.5",
+            "--white",
+            "8",
+            "--tonemap",
+            "reinhard-extended",
+            "--aov",
+            "all",
+        ])
+        .unwrap();
+        let expected = Options {
+            output: PathBuf::from("out/a.PPM"),
+            format: OutputFormat::Ppm,
+            bit_depth: 16,
+            dither: true,
+            exposure: -1.5,
+            tone_map: ToneMap::ReinhardExtended { white: Some(8.0) },
+            aovs: Aov::ALL.to_vec(),
+            ..Options::default()
+        };
+        assert_eq!(command, Command::Render(Box::new(expected)));
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(parse(&["--width", "0"]).is_err());
+        assert!(parse(&["--samples", "-3"]).is_err());
+        assert!(parse(&["--quality", "101"]).is_err());
+        assert!(parse(&["--scene", "teapot"]).is_err());
+        assert!(parse(&["--format", "gif"]).is_err());
+        assert!(parse(&["-o", "a.gif"]).is_err());
+        assert!(parse(&["-o", "a"]).is_err());
+        assert!(parse(&["-o", "a", "-f", "bmp"]).is_ok());
+        assert!(parse(&["-o", "a.jpg", "--bit-depth", "16"]).is_err());
+        assert!(parse(&["-o", "a.png", "--bit-depth", "12"]).is_err());
+        assert!(parse(&["--tonemap", "filmic"]).is_err());
+        assert!(parse(&["--exposure", "bright"]).is_err());
+        assert!(parse(&["--tonemap", "aces", "--white", "4"]).is_err());
+        assert!(parse(&["--tonemap", "reinhard-extended", "--white", "0"]).is_err());
+        assert!(parse(&["--exr-compression", "rle"]).is_err());
+        assert!(parse(&["--sampler", "random"]).is_err());
+        assert!(parse(&["--noise-threshold", "0"]).is_err());
+        assert!(parse(&["--min-samples", "0"]).is_err());
+        assert!(parse(&["--heatmap", "heat.exr"]).is_err());
+        assert!(parse(&["--pass-samples", "0"]).is_err());
+        assert!(parse(&["--checkpoint-every", "-1"]).is_err());
+        assert!(parse(&["--time-limit", "10min"]).is_err());
+        assert!(parse(&["--time-limit", "0"]).is_err());
+        assert!(parse(&["--time-limit", "m"]).is_err());
+        assert!(parse(&["--filter", "sinc"]).is_err());
+        assert!(parse(&["--filter-radius", "0"]).is_err());
+        assert!(parse(&["--filter-radius", "0.25"]).is_err());
+        assert!(parse(&["--filter-radius", "1e300"]).is_err());
+        assert!(parse(&["--filter-radius", "0.5"]).is_ok());
+        assert!(parse(&["--time-limit", "1e30"]).is_err());
+        assert!(parse(&["--time-limit", "1e16h"]).is_err());
+        assert!(parse(&["--aov", "normals"]).is_err());
+        assert!(parse(&["--aov", "depth,"]).is_err());
+        assert!(parse(&["--threads"]).is_err());
+        assert!(parse(&["--tile-size", "0"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+        assert!(parse(&["a.scene", "b.scene"]).is_err());
+        assert!(parse(&["a.scene", "--scene", "earth"]).is_err());
+    }
+}
@@ -1,7 +1,68 @@
-use image::RgbImage;
-/// the multi-sample write_color() function
-pub fn write_color(pixel_color: [u8; 3], img: &mut RgbImage, i: usize, j: usize) {
-    let pixel = img.get_pixel_mut(i.try_into().unwrap(), j.try_into().unwrap());
-    *pixel = image::Rgb(pixel_color);
-    // Write the translated [0,255] value of each color component.
+//! Encoding linear colors for display.
+use crate::rtweekend::derive_seed;
+
+/// The sRGB transfer curve, from linear light to the encoded value, both in `[0, 1]`.
+pub fn linear_to_srgb(linear: f64) -> f64 {
+    if linear <= 0.003_130_8 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The inverse of [`linear_to_srgb`].
+pub fn srgb_to_linear(encoded: f64) -> f64 {
+    if encoded <= 0.040_45 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Quantises an encoded value in `[0, 1]` to an integer in `0..=max`. With `dither`,
+/// triangular noise of one step either way, fixed by `key`, is added first, which turns
+/// the bands of smooth gradients into fine grain. Exact black and white are left alone.
+pub fn quantize(encoded: f64, max: u16, dither: bool, key: [u64; 3]) -> u16 {
+    let max = max as f64;
+    let noise = if dither && encoded > 0.0 && encoded < 1.0 {
+        let hash = derive_seed(0xD1_7E4, &key);
+        let unit = |bits: u64| (bits & 0xFFFF_FFFF) as f64 / 4_294_967_296.0;
+        unit(hash) + unit(hash >> 32) - 1.0
+    } else {
+        0.0
+    };
+    (encoded * max + noise).round().clamp(0.0, max) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_srgb_curve() {
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-12);
+        // the linear part and the power part meet
+        let knee: f64 = 0.003_130_8;
+        assert!((12.92 * knee - (1.055 * knee.powf(1.0 / 2.4) - 0.055)).abs() < 1e-6);
+        assert!((linear_to_srgb(0.18) - 0.4614).abs() < 1e-4);
+        for i in 0..=100 {
+            let x = i as f64 / 100.0;
+            assert!((srgb_to_linear(linear_to_srgb(x)) - x).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_dithering_keeps_the_mean() {
+        // 100.3 steps is 100 without dithering, and 100.3 on average with it
+        let encoded = 100.3 / 255.0;
+        assert_eq!(quantize(encoded, 255, false, [0, 0, 0]), 100);
+        let n = 10_000;
+        let sum: u32 = (0..n)
+            .map(|i| quantize(encoded, 255, true, [i, 7, 1]) as u32)
+            .sum();
+        assert!((sum as f64 / n as f64 - 100.3).abs() < 0.02);
+        assert_eq!(quantize(0.0, 255, true, [3, 4, 0]), 0);
+        assert_eq!(quantize(1.0, 65535, true, [3, 4, 0]), 65535);
+    }
 }
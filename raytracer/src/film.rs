@@ -0,0 +1,420 @@
+use crate::color::{linear_to_srgb, quantize};
+use crate::filter::Filter;
+use crate::tonemap::luminance;
+use crate::vec3::Color;
+use image::{ImageBuffer, Rgb, RgbImage};
+use std::ops::Range;
+
+/// A linear RGB float image, the result of a render. Row 0 is the top of the image.
+#[derive(Clone)]
+pub struct Film {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0.0; width * height * 3],
+        }
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    /// The pixels as `[r, g, b, r, g, b, ...]`, row by row from the top.
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        let idx = 3 * (y * self.width + x);
+        Color::new(
+            self.data[idx] as f64,
+            self.data[idx + 1] as f64,
+            self.data[idx + 2] as f64,
+        )
+    }
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: &Color) {
+        let idx = 3 * (y * self.width + x);
+        self.data[idx] = color.x() as f32;
+        self.data[idx + 1] = color.y() as f32;
+        self.data[idx + 2] = color.z() as f32;
+    }
+    /// Adds the pixels of `other`, a film of the same size.
+    pub fn merge(&mut self, other: &Film) {
+        assert_eq!(
+            (self.width, self.height),
+            (other.width, other.height),
+            "films of different sizes"
+        );
+        for (a, b) in self.data.iter_mut().zip(&other.data) {
+            *a += b;
+        }
+    }
+    /// sRGB-encodes the film and quantises it to 8 bits per channel, clipping what is
+    /// outside `[0, 1]`. See [`quantize`] for `dither`.
+    pub fn to_rgb8(&self, dither: bool) -> RgbImage {
+        let data = self.encode(u8::MAX as u16, dither);
+        RgbImage::from_raw(
+            self.width as u32,
+            self.height as u32,
+            data.map(|v| v as u8).collect(),
+        )
+        .unwrap()
+    }
+    /// Like [`to_rgb8`](Self::to_rgb8), with 16 bits per channel.
+    pub fn to_rgb16(&self, dither: bool) -> ImageBuffer<Rgb<u16>, Vec<u16>> {
+        let data = self.encode(u16::MAX, dither);
+        ImageBuffer::from_raw(self.width as u32, self.height as u32, data.collect()).unwrap()
+    }
+    fn encode(&self, max: u16, dither: bool) -> impl Iterator<Item = u16> + '_ {
+        self.data.iter().enumerate().map(move |(i, &v)| {
+            // NaN stays NaN through the curve and quantises to 0
+            let encoded = linear_to_srgb((v as f64).clamp(0.0, 1.0));
+            let (pixel, channel) = (i / 3, i % 3);
+            let key = [
+                (pixel % self.width) as u64,
+                (pixel / self.width) as u64,
+                channel as u64,
+            ];
+            quantize(encoded, max, dither, key)
+        })
+    }
+}
+
+/// The running mean and variance of the samples of one pixel, by Welford's method.
+/// The variance is that of the luminance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PixelStats {
+    count: u32,
+    mean: Color,
+    /// Sum of the squared differences of the luminances from their mean.
+    m2: f64,
+}
+
+impl PixelStats {
+    pub fn new() -> Self {
+        Self {
+            count: 0,
+            mean: Color::zero(),
+            m2: 0.0,
+        }
+    }
+    /// Statistics from their raw parts, as [`raw`](Self::raw) gives them.
+    pub(crate) fn from_raw(count: u32, mean: Color, m2: f64) -> Self {
+        Self { count, mean, m2 }
+    }
+    pub(crate) fn raw(&self) -> (u32, Color, f64) {
+        (self.count, self.mean, self.m2)
+    }
+    pub fn add(&mut self, sample: &Color) {
+        let old = luminance(&self.mean);
+        self.count += 1;
+        self.mean += (sample - self.mean) / self.count as f64;
+        self.m2 += (luminance(sample) - old) * (luminance(sample) - luminance(&self.mean));
+    }
+    pub fn count(&self) -> usize {
+        self.count as usize
+    }
+    pub fn mean(&self) -> Color {
+        self.mean
+    }
+    /// The sample variance of the luminance, 0 before the second sample.
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            self.m2 / (self.count - 1) as f64
+        }
+    }
+    /// The noise left in the pixel: the standard error of its mean luminance, relative
+    /// to that mean. Dark pixels are measured against a luminance of 0.01 instead, so
+    /// that black does not need endless samples. Infinite before the second sample.
+    pub fn error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let standard_error = (self.variance() / self.count as f64).sqrt();
+        standard_error / luminance(&self.mean).max(0.01)
+    }
+}
+
+impl Default for PixelStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The samples taken so far: the running statistics of the samples in every pixel,
+/// and the filtered sums of the samples around it that make the image. Row 0 is the
+/// top of the image, as in [`Film`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SampleFilm {
+    width: usize,
+    height: usize,
+    pixels: Vec<PixelStats>,
+    /// Weighted red, green and blue, and the sum of the weights.
+    sums: Vec<[f64; 4]>,
+}
+
+impl SampleFilm {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![PixelStats::new(); width * height],
+            sums: vec![[0.0; 4]; width * height],
+        }
+    }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn pixel(&self, x: usize, y: usize) -> &PixelStats {
+        &self.pixels[y * self.width + x]
+    }
+    pub fn pixel_mut(&mut self, x: usize, y: usize) -> &mut PixelStats {
+        &mut self.pixels[y * self.width + x]
+    }
+    /// The weighted sum of the samples around a pixel, with the sum of their weights
+    /// last.
+    pub fn filtered(&self, x: usize, y: usize) -> [f64; 4] {
+        self.sums[y * self.width + x]
+    }
+    pub(crate) fn filtered_mut(&mut self, x: usize, y: usize) -> &mut [f64; 4] {
+        &mut self.sums[y * self.width + x]
+    }
+    pub fn add_splats(&mut self, splats: &Splats) {
+        let mut sums = splats.sums.iter();
+        for y in splats.ys.clone() {
+            for x in splats.xs.clone() {
+                let pixel = self.filtered_mut(x, y);
+                for (a, b) in pixel.iter_mut().zip(sums.next().unwrap()) {
+                    *a += b;
+                }
+            }
+        }
+    }
+    /// The number of samples of all pixels together.
+    pub fn total_count(&self) -> usize {
+        self.pixels.iter().map(PixelStats::count).sum()
+    }
+    /// The largest number of samples of any pixel.
+    pub fn max_count(&self) -> usize {
+        self.pixels.iter().map(PixelStats::count).max().unwrap_or(0)
+    }
+    /// The image: the weighted average of the samples around every pixel, black where
+    /// there are none. Where the negative lobes of a filter leave the weights with
+    /// little or less than nothing, as they can at a few samples per pixel, the pixel
+    /// is the plain mean of its own samples instead.
+    pub fn to_film(&self) -> Film {
+        const MIN_WEIGHT: f64 = 1e-3;
+        let mut film = Film::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let [r, g, b, weight] = self.filtered(x, y);
+                if weight > MIN_WEIGHT {
+                    film.set_pixel(x, y, &(Color::new(r, g, b) / weight));
+                } else {
+                    film.set_pixel(x, y, &self.pixel(x, y).mean());
+                }
+            }
+        }
+        film
+    }
+    /// The sample count of every pixel as colors, from black for none through purple,
+    /// orange and yellow to white for `max_samples`.
+    pub fn heatmap(&self, max_samples: usize) -> RgbImage {
+        const RAMP: [[f64; 3]; 5] = [
+            [0.0, 0.0, 0.0],
+            [120.0, 28.0, 109.0],
+            [237.0, 105.0, 37.0],
+            [250.0, 225.0, 60.0],
+            [255.0, 255.0, 255.0],
+        ];
+        let mut image = RgbImage::new(self.width as u32, self.height as u32);
+        for (pixel, stats) in image.pixels_mut().zip(&self.pixels) {
+            let t = (stats.count() as f64 / max_samples.max(1) as f64).min(1.0);
+            let at = t * (RAMP.len() - 1) as f64;
+            let i = (at as usize).min(RAMP.len() - 2);
+            let f = at - i as f64;
+            for c in 0..3 {
+                pixel[c] = (RAMP[i][c] + f * (RAMP[i + 1][c] - RAMP[i][c])).round() as u8;
+            }
+        }
+        image
+    }
+}
+
+/// The samples of a rectangle of pixels spread by a filter over the pixels around
+/// them, to be added to a [`SampleFilm`] at once.
+pub struct Splats {
+    filter: Filter,
+    xs: Range<usize>,
+    ys: Range<usize>,
+    sums: Vec<[f64; 4]>,
+}
+
+impl Splats {
+    /// Room for the samples in columns `xs` and rows `ys` of a `width` by `height` film,
+    /// and for what `filter` spreads of them into the pixels around.
+    pub fn new(
+        filter: Filter,
+        xs: Range<usize>,
+        ys: Range<usize>,
+        width: usize,
+        height: usize,
+    ) -> Self {
+        let margin = (filter.radius() - 0.5).ceil().max(0.0) as usize;
+        let xs = xs.start.saturating_sub(margin)..(xs.end + margin).min(width);
+        let ys = ys.start.saturating_sub(margin)..(ys.end + margin).min(height);
+        Self {
+            filter,
+            sums: vec![[0.0; 4]; xs.len() * ys.len()],
+            xs,
+            ys,
+        }
+    }
+    /// Adds a sample at `(x, y)`, in pixels from the top left corner of the film, to
+    /// every pixel whose centre is within the radius of the filter.
+    pub fn add(&mut self, x: f64, y: f64, color: &Color) {
+        let radius = self.filter.radius();
+        let reach = |c: f64, range: &Range<usize>| {
+            let first = (c - 0.5 - radius).ceil().max(range.start as f64);
+            let last = (c - 0.5 + radius).floor().min(range.end as f64 - 1.0);
+            first as usize..(last + 1.0).max(first) as usize
+        };
+        let columns = reach(x, &self.xs);
+        for py in reach(y, &self.ys) {
+            for px in columns.clone() {
+                let weight = self
+                    .filter
+                    .evaluate(x - (px as f64 + 0.5), y - (py as f64 + 0.5));
+                if weight == 0.0 {
+                    continue;
+                }
+                let i = (py - self.ys.start) * self.xs.len() + (px - self.xs.start);
+                let sum = &mut self.sums[i];
+                sum[0] += weight * color.x();
+                sum[1] += weight * color.y();
+                sum[2] += weight * color.z();
+                sum[3] += weight;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixel_stats() {
+        let samples = [0.5, 1.5, 0.25, 2.0, 0.75];
+        let mut stats = PixelStats::new();
+        assert_eq!(stats.error(), f64::INFINITY);
+        for &v in &samples {
+            stats.add(&Color::new(v, v, v));
+        }
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let variance = samples.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        assert_eq!(stats.count(), 5);
+        assert!((stats.mean().x() - mean).abs() < 1e-12);
+        assert!((stats.variance() - variance).abs() < 1e-12);
+        assert!((stats.error() - (variance / n).sqrt() / mean).abs() < 1e-12);
+        // a constant pixel has converged
+        let mut flat = PixelStats::new();
+        flat.add(&Color::zero());
+        flat.add(&Color::zero());
+        assert_eq!(flat.error(), 0.0);
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let mut film = SampleFilm::new(3, 1);
+        for _ in 0..4 {
+            film.pixel_mut(2, 0).add(&Color::ones());
+        }
+        film.pixel_mut(1, 0).add(&Color::ones());
+        film.pixel_mut(1, 0).add(&Color::ones());
+        assert_eq!(film.max_count(), 4);
+        let heatmap = film.heatmap(4);
+        assert_eq!(heatmap.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(heatmap.get_pixel(1, 0).0, [237, 105, 37]);
+        assert_eq!(heatmap.get_pixel(2, 0).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_splats() {
+        // the box filter keeps samples in their pixel
+        let mut film = SampleFilm::new(4, 3);
+        let mut splats = Splats::new(Filter::default(), 1..3, 1..2, 4, 3);
+        splats.add(1.2, 1.7, &Color::new(1.0, 2.0, 3.0));
+        splats.add(1.9, 1.1, &Color::new(3.0, 2.0, 1.0));
+        splats.add(2.5, 1.5, &Color::ones());
+        film.add_splats(&splats);
+        let image = film.to_film();
+        assert_eq!(image.pixel(1, 1), Color::new(2.0, 2.0, 2.0));
+        assert_eq!(image.pixel(2, 1), Color::ones());
+        assert_eq!(image.pixel(0, 1), Color::zero());
+        // a sample on the edge between two pixels goes to the one it was taken for
+        let mut film = SampleFilm::new(4, 3);
+        let mut splats = Splats::new(Filter::default(), 0..4, 0..3, 4, 3);
+        splats.add(2.0, 1.0, &Color::ones());
+        film.add_splats(&splats);
+        assert_eq!(film.filtered(2, 1), [1.0; 4]);
+        assert_eq!(film.to_film().pixel(2, 1), Color::ones());
+        assert_eq!(film.filtered(1, 1)[3] + film.filtered(2, 0)[3], 0.0);
+        // a wider one spreads them, and stops at the edges of the film
+        let tent = Filter::Triangle { radius: 1.0 };
+        let mut splats = Splats::new(tent, 0..1, 0..1, 4, 3);
+        assert_eq!((splats.xs.clone(), splats.ys.clone()), (0..2, 0..2));
+        splats.add(0.75, 0.5, &Color::ones());
+        let mut film = SampleFilm::new(4, 3);
+        film.add_splats(&splats);
+        assert_eq!(film.filtered(0, 0)[3], 0.75);
+        assert_eq!(film.filtered(1, 0)[3], 0.25);
+        assert_eq!(film.filtered(0, 1)[3], 0.0);
+    }
+
+    #[test]
+    fn test_negative_weights() {
+        // one sample per pixel under Lanczos: the middle pixel's own sample is in its
+        // corner, those of its neighbours on their far sides, in the negative lobes
+        let lanczos = Filter::from_name("lanczos").unwrap();
+        let mut film = SampleFilm::new(3, 3);
+        let mut splats = Splats::new(lanczos, 0..3, 0..3, 3, 3);
+        let samples = [
+            (1.0, 1.0, 1.0),
+            (0.0, 1.5, 0.0),
+            (2.999, 1.5, 0.0),
+            (1.5, 0.0, 0.0),
+            (1.5, 2.999, 0.0),
+            (0.5, 0.5, 0.0),
+            (2.5, 0.5, 0.0),
+            (0.5, 2.5, 0.0),
+            (2.5, 2.5, 0.0),
+        ];
+        for (x, y, v) in samples {
+            let color = Color::new(v, v, v);
+            film.pixel_mut(x as usize, y as usize).add(&color);
+            splats.add(x, y, &color);
+        }
+        film.add_splats(&splats);
+        assert!(film.filtered(1, 1)[3] < 0.0);
+        let image = film.to_film();
+        assert_eq!(image.pixel(1, 1), Color::ones());
+        for (x, y) in [(0, 0), (1, 0), (0, 1)] {
+            let c = image.pixel(x, y);
+            assert!(c.x().is_finite() && c.x().abs() <= 1.0, "{:?}", c.x());
+        }
+    }
+}
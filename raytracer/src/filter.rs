@@ -0,0 +1,184 @@
+//! Pixel reconstruction filters: how much a sample counts towards the pixels around it.
+use std::f64::consts::PI;
+use std::ops::RangeInclusive;
+
+/// A separable filter, centred on the sample, that weighs its contribution to every
+/// pixel whose centre is less than `radius` pixels away on both axes. Pixels are the
+/// weighted averages of the samples around them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    /// Every sample counts fully, but only in its own pixel with the default radius of
+    /// 0.5: the plain average of the samples in the pixel.
+    Box { radius: f64 },
+    /// Weights fall off linearly to 0 at `radius`.
+    Triangle { radius: f64 },
+    /// A Gaussian with a standard deviation of a third of `radius`, shifted down to
+    /// reach 0 there.
+    Gaussian { radius: f64 },
+    /// The cubic of Mitchell and Netravali, stretched over `radius`. Its negative lobes
+    /// sharpen edges; `b = c = 1/3` is their suggested balance of blur and ringing.
+    Mitchell { radius: f64, b: f64, c: f64 },
+    /// Sinc windowed by a wider sinc, with `radius` lobes on each side: the sharpest,
+    /// and the most prone to ringing.
+    Lanczos { radius: f64 },
+}
+
+impl Filter {
+    pub const NAMES: [&'static str; 5] = ["box", "triangle", "gaussian", "mitchell", "lanczos"];
+    /// The radii a filter may have. Under half a pixel, the samples near the edges of a
+    /// pixel count towards no pixel at all; the upper bound keeps every sample's reach,
+    /// and the margins of the tiles, small.
+    pub const RADII: RangeInclusive<f64> = 0.5..=16.0;
+
+    /// The filter called `name`, with its usual radius.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "box" => Some(Self::Box { radius: 0.5 }),
+            "triangle" => Some(Self::Triangle { radius: 1.0 }),
+            "gaussian" => Some(Self::Gaussian { radius: 1.5 }),
+            "mitchell" => Some(Self::Mitchell {
+                radius: 2.0,
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            }),
+            "lanczos" => Some(Self::Lanczos { radius: 3.0 }),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Box { .. } => "box",
+            Self::Triangle { .. } => "triangle",
+            Self::Gaussian { .. } => "gaussian",
+            Self::Mitchell { .. } => "mitchell",
+            Self::Lanczos { .. } => "lanczos",
+        }
+    }
+
+    pub fn radius(self) -> f64 {
+        match self {
+            Self::Box { radius }
+            | Self::Triangle { radius }
+            | Self::Gaussian { radius }
+            | Self::Mitchell { radius, .. }
+            | Self::Lanczos { radius } => radius,
+        }
+    }
+
+    /// The same filter over another radius, which should be one of [`RADII`](Self::RADII).
+    pub fn with_radius(mut self, new: f64) -> Self {
+        match &mut self {
+            Self::Box { radius }
+            | Self::Triangle { radius }
+            | Self::Gaussian { radius }
+            | Self::Mitchell { radius, .. }
+            | Self::Lanczos { radius } => *radius = new,
+        }
+        self
+    }
+
+    /// The weight of a sample at `(dx, dy)` pixels from a pixel centre. The filter
+    /// reaches from `-radius` up to but not including `radius` on each axis, so that a
+    /// sample on the edge between two pixels counts fully in one of them with the box.
+    pub fn evaluate(self, dx: f64, dy: f64) -> f64 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(self, d: f64) -> f64 {
+        let radius = self.radius();
+        if d < -radius || d >= radius {
+            return 0.0;
+        }
+        let d = d.abs();
+        match self {
+            Self::Box { .. } => 1.0,
+            Self::Triangle { .. } => 1.0 - d / radius,
+            Self::Gaussian { .. } => {
+                let gaussian = |x: f64| {
+                    let sigma = radius / 3.0;
+                    (-x * x / (2.0 * sigma * sigma)).exp()
+                };
+                gaussian(d) - gaussian(radius)
+            }
+            Self::Mitchell { b, c, .. } => {
+                let x = 2.0 * d / radius;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                }
+            }
+            Self::Lanczos { .. } => sinc(d) * sinc(d / radius),
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::Box { radius: 0.5 }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters() {
+        for name in Filter::NAMES {
+            let filter = Filter::from_name(name).unwrap();
+            assert_eq!(filter.name(), name);
+            let r = filter.radius();
+            assert!(Filter::RADII.contains(&r), "{}", name);
+            // peaked in the middle, symmetric, and 0 from the radius on
+            assert!(filter.evaluate(0.0, 0.0) > 0.0, "{}", name);
+            for i in 1..20 {
+                let d = r * i as f64 / 20.0;
+                assert!(
+                    filter.evaluate(d, 0.0) <= filter.evaluate(0.0, 0.0),
+                    "{}",
+                    name
+                );
+                assert_eq!(
+                    filter.evaluate(d, 0.3),
+                    filter.evaluate(-d, -0.3),
+                    "{}",
+                    name
+                );
+            }
+            assert_eq!(filter.evaluate(r, 0.0), 0.0, "{}", name);
+            assert_eq!(filter.evaluate(0.0, -1.01 * r), 0.0, "{}", name);
+        }
+        // the box counts one edge of its pixel and not the other
+        assert_eq!(Filter::default().evaluate(-0.5, 0.0), 1.0);
+        assert_eq!(Filter::default().evaluate(0.5, 0.0), 0.0);
+        // the negative lobes
+        let mitchell = Filter::from_name("mitchell").unwrap();
+        assert!(mitchell.evaluate_1d(1.5) < 0.0);
+        let lanczos = Filter::from_name("lanczos").unwrap();
+        assert!(lanczos.evaluate_1d(1.5) < 0.0);
+        assert!(lanczos.evaluate_1d(1.0).abs() < 1e-12);
+        // Mitchell-Netravali is continuous where its two pieces meet
+        let m = |d: f64| mitchell.evaluate_1d(d);
+        assert!((m(1.0 - 1e-9) - m(1.0 + 1e-9)).abs() < 1e-6);
+        let wide = Filter::from_name("triangle").unwrap().with_radius(2.0);
+        assert_eq!(wide, Filter::Triangle { radius: 2.0 });
+        assert_eq!(wide.evaluate_1d(1.0), 0.5);
+    }
+}
@@ -1,16 +1,24 @@
 use crate::aabb::AaBb;
+use crate::matrix::Matrix4;
 use crate::rtweekend::degrees_to_radians;
 use crate::{material::*, ray::*, vec3::*};
 use std::sync::Arc;
 
 pub trait Hit: Send + Sync {
     //此处返回Option<HitRecord>的思想改编自助教分享的https://zhuanlan.zhihu.com/p/436876484
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>>;
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AaBb>;
-    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+    /// Whether anything is hit between `t_min` and `t_max`, for shadow rays. Worth
+    /// overriding to stop at the first hit and skip building the record. The path
+    /// tracer samples lights through their pdfs and traces no separate shadow rays, so
+    /// only visibility tests such as those of `pdf_value` call it.
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.hit(r, t_min, t_max).is_some()
+    }
+    fn pdf_value(&self, _o: &Point3, _v: &Vec3) -> f64 {
         0.0
     }
-    fn random(&self, o: &Vec3) -> Vec3 {
+    fn random(&self, _o: &Vec3) -> Vec3 {
         Vec3::new(1.0, 0.0, 0.0)
     }
 }
@@ -23,6 +31,11 @@ pub struct HitRecord<'a> {
     pub v: f64,
     pub t: f64,
     pub front_face: bool,
+    /// Which top-level object of the scene was hit, counting from 1; 0 until an
+    /// [`ObjectId`] around the object sets it.
+    pub object_id: u32,
+    /// The vertex colors of a mesh that has them, blended at the hit.
+    pub color: Option<Color>,
 }
 impl<'a> HitRecord<'a> {
     pub fn new(
@@ -34,11 +47,11 @@ impl<'a> HitRecord<'a> {
         r: Ray,
         material: &'a dyn Material,
     ) -> Self {
-        let front_face = r.dir().dot(outward_normal.clone()) < 0.0;
+        let front_face = r.dir().dot(*outward_normal) < 0.0;
         let normal = if front_face {
-            outward_normal.clone()
+            *outward_normal
         } else {
-            -outward_normal.clone()
+            -*outward_normal
         };
         Self {
             p,
@@ -48,6 +61,8 @@ impl<'a> HitRecord<'a> {
             v,
             front_face,
             material,
+            object_id: 0,
+            color: None,
         }
     }
 }
@@ -63,30 +78,42 @@ impl Translate {
 }
 
 impl Hit for Translate {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let moved_r = Ray::new(r.orig() - self.offset.clone(), r.dir(), r.time());
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let moved_r = Ray::new(r.orig() - self.offset, r.dir(), r.time());
         if let Some(rec) = self.ptr.hit(&moved_r, t_min, t_max) {
-            return Some(HitRecord::new(
-                rec.p + self.offset.clone(),
+            let mut moved = HitRecord::new(
+                rec.p + self.offset,
                 rec.t,
                 rec.u,
                 rec.v,
                 &rec.normal,
                 moved_r,
                 rec.material,
-            ));
+            );
+            moved.color = rec.color;
+            return Some(moved);
         }
         None
     }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        let moved_r = Ray::new(r.orig() - self.offset, r.dir(), r.time());
+        self.ptr.occluded(&moved_r, t_min, t_max)
+    }
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AaBb> {
         if let Some(output_box) = self.ptr.bounding_box(time0, time1) {
             return Some(AaBb::new(
-                output_box.min() + self.offset.clone(),
-                output_box.max() + self.offset.clone(),
+                output_box.min() + self.offset,
+                output_box.max() + self.offset,
             ));
         }
         None
     }
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        self.ptr.pdf_value(&(*o - self.offset), v)
+    }
+    fn random(&self, o: &Vec3) -> Vec3 {
+        self.ptr.random(&(*o - self.offset))
+    }
 }
 pub struct RotateY {
     ptr: Arc<dyn Hit>,
@@ -117,10 +144,8 @@ impl RotateY {
                             let newx = cos_theta * x + sin_theta * z;
                             let newz = -sin_theta * x + cos_theta * z;
                             let tester = Vec3::new(newx, y, newz);
-                            for c in 0..3 {
-                                min[c] = min[c].min(tester[c]);
-                                max[c] = max[c].max(tester[c]);
-                            }
+                            min = min.min(tester);
+                            max = max.max(tester);
                         }
                     }
                 }
@@ -137,36 +162,145 @@ impl RotateY {
     }
 }
 
+impl RotateY {
+    fn to_object(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x() - self.sin_theta * v.z(),
+            v.y(),
+            self.sin_theta * v.x() + self.cos_theta * v.z(),
+        )
+    }
+    fn to_world(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x() + self.sin_theta * v.z(),
+            v.y(),
+            -self.sin_theta * v.x() + self.cos_theta * v.z(),
+        )
+    }
+}
+
 impl Hit for RotateY {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let mut origin = r.orig();
-        let mut direction = r.dir();
-        origin[0] = self.cos_theta * r.orig()[0] - self.sin_theta * r.orig()[2];
-        origin[2] = self.sin_theta * r.orig()[0] + self.cos_theta * r.orig()[2];
-        direction[0] = self.cos_theta * r.dir()[0] - self.sin_theta * r.dir()[2];
-        direction[2] = self.sin_theta * r.dir()[0] + self.cos_theta * r.dir()[2];
-        let rotated_r = Ray::new(origin, direction, r.time());
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let rotated_r = Ray::new(
+            self.to_object(&r.orig()),
+            self.to_object(&r.dir()),
+            r.time(),
+        );
         if let Some(rec) = self.ptr.hit(&rotated_r, t_min, t_max) {
-            let mut p = rec.p.clone();
-            let mut normal = rec.normal.clone();
-            p[0] = self.cos_theta * rec.p[0] + self.sin_theta * rec.p[2];
-            p[2] = -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2];
-            normal[0] = self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
-            normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
-            return Some(HitRecord::new(
-                p,
-                rec.t,
-                rec.u,
-                rec.v,
-                &normal,
-                rotated_r,
-                rec.material,
-            ));
+            let p = self.to_world(&rec.p);
+            let normal = self.to_world(&rec.normal);
+            let mut rotated =
+                HitRecord::new(p, rec.t, rec.u, rec.v, &normal, rotated_r, rec.material);
+            rotated.color = rec.color;
+            return Some(rotated);
         }
         None
     }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        let rotated_r = Ray::new(
+            self.to_object(&r.orig()),
+            self.to_object(&r.dir()),
+            r.time(),
+        );
+        self.ptr.occluded(&rotated_r, t_min, t_max)
+    }
     fn bounding_box(&self, _: f64, _: f64) -> Option<AaBb> {
-        self.bbox.clone()
+        self.bbox
+    }
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        self.ptr.pdf_value(&self.to_object(o), &self.to_object(v))
+    }
+    fn random(&self, o: &Vec3) -> Vec3 {
+        self.to_world(&self.ptr.random(&self.to_object(o)))
+    }
+}
+
+/// An object placed by an arbitrary affine transform (rotation, scale, shear,
+/// translation). The object is shared, so it can be instanced many times.
+pub struct Instance {
+    ptr: Arc<dyn Hit>,
+    // object space to world space
+    transform: Matrix4,
+    inverse: Matrix4,
+    // transforms normals to world space
+    normal_matrix: Matrix4,
+}
+
+impl Instance {
+    /// # Panics
+    ///
+    /// If `transform` is singular.
+    pub fn new(ptr: Arc<dyn Hit>, transform: Matrix4) -> Self {
+        let inverse = transform
+            .inverse()
+            .expect("an instance needs an invertible transform");
+        let normal_matrix = inverse.transpose();
+        Self {
+            ptr,
+            transform,
+            inverse,
+            normal_matrix,
+        }
+    }
+    pub fn transform(&self) -> &Matrix4 {
+        &self.transform
+    }
+    // the direction is not normalized, so `t` means the same in both spaces
+    fn to_object(&self, r: &Ray) -> Ray {
+        Ray::new(
+            self.inverse.transform_point(&r.orig()),
+            self.inverse.transform_vector(&r.dir()),
+            r.time(),
+        )
+    }
+}
+
+impl Hit for Instance {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let mut rec = self.ptr.hit(&self.to_object(r), t_min, t_max)?;
+        rec.p = self.transform.transform_point(&rec.p);
+        // front_face is kept, the normal still faces the ray after the transform
+        rec.normal = self
+            .normal_matrix
+            .transform_vector(&rec.normal)
+            .unit_vector();
+        Some(rec)
+    }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.ptr.occluded(&self.to_object(r), t_min, t_max)
+    }
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AaBb> {
+        let object_box = self.ptr.bounding_box(time0, time1)?;
+        let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Point3::new(-f64::INFINITY, -f64::INFINITY, -f64::INFINITY);
+        for i in 0..8 {
+            let pick = |axis: usize| {
+                if i & (1 << axis) == 0 {
+                    object_box.min().axis(axis)
+                } else {
+                    object_box.max().axis(axis)
+                }
+            };
+            let corner = self
+                .transform
+                .transform_point(&Point3::new(pick(0), pick(1), pick(2)));
+            min = min.min(corner);
+            max = max.max(corner);
+        }
+        Some(AaBb::new(min, max))
+    }
+    // solid angles only survive similarity transforms, so these are exact for
+    // rotations, uniform scales and translations; the scene format only allows
+    // lights under those
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        self.ptr.pdf_value(
+            &self.inverse.transform_point(o),
+            &self.inverse.transform_vector(v),
+        )
+    }
+    fn random(&self, o: &Vec3) -> Vec3 {
+        let v = self.ptr.random(&self.inverse.transform_point(o));
+        self.transform.transform_vector(&v)
     }
 }
 
@@ -180,7 +314,7 @@ impl FlipFace {
 }
 
 impl Hit for FlipFace {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         if let Some(mut rec) = self.ptr.hit(r, t_min, t_max) {
             rec.front_face = !rec.front_face;
             Some(rec)
@@ -188,7 +322,81 @@ impl Hit for FlipFace {
             None
         }
     }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.ptr.occluded(r, t_min, t_max)
+    }
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AaBb> {
+        self.ptr.bounding_box(time0, time1)
+    }
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        self.ptr.pdf_value(o, v)
+    }
+    fn random(&self, o: &Vec3) -> Vec3 {
+        self.ptr.random(o)
+    }
+}
+
+/// Tags the hits on an object with its ID, for the object ID pass.
+pub struct ObjectId {
+    id: u32,
+    ptr: Arc<dyn Hit>,
+}
+impl ObjectId {
+    pub fn new(ptr: Arc<dyn Hit>, id: u32) -> Self {
+        Self { id, ptr }
+    }
+}
+
+impl Hit for ObjectId {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let mut rec = self.ptr.hit(r, t_min, t_max)?;
+        rec.object_id = self.id;
+        Some(rec)
+    }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.ptr.occluded(r, t_min, t_max)
+    }
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AaBb> {
         self.ptr.bounding_box(time0, time1)
     }
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        self.ptr.pdf_value(o, v)
+    }
+    fn random(&self, o: &Vec3) -> Vec3 {
+        self.ptr.random(o)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_instance() {
+        // a unit sphere squashed into an ellipsoid 4 wide and 1 high, moved up by 10
+        let sphere: Arc<dyn Hit> = Arc::new(Sphere::new(
+            Point3::zero(),
+            1.0,
+            Arc::new(Lambertian::new_color(Color::ones())),
+        ));
+        let transform = Matrix4::translation(&Vec3::new(0.0, 10.0, 0.0))
+            * Matrix4::scaling(&Vec3::new(4.0, 1.0, 1.0));
+        let instance = Instance::new(sphere, transform);
+        let bbox = instance.bounding_box(0.0, 1.0).unwrap();
+        assert_eq!(bbox.min(), Point3::new(-4.0, 9.0, -1.0));
+        assert_eq!(bbox.max(), Point3::new(4.0, 11.0, 1.0));
+        let r = Ray::new(Point3::new(10.0, 10.0, 0.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        let rec = instance.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 6.0).abs() < 1e-9);
+        assert!((rec.p - Point3::new(4.0, 10.0, 0.0)).length() < 1e-9);
+        assert!(rec.front_face);
+        assert!(instance.occluded(&r, 0.001, 6.5));
+        assert!(!instance.occluded(&r, 0.001, 5.5));
+        // off-axis the normal is not the scaled object-space normal
+        let r = Ray::new(Point3::new(2.0, 10.5, 10.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = instance.hit(&r, 0.001, f64::INFINITY).unwrap();
+        let expected = Vec3::new(0.5 / 4.0, 0.5, 0.5f64.sqrt()).unit_vector();
+        assert!((rec.normal - expected).length() < 1e-6);
+    }
 }
@@ -1,4 +1,6 @@
 use crate::aabb::{surrounding_box, AaBb};
+use crate::rtweekend::random_f64;
+use crate::vec3::{Point3, Vec3};
 use crate::{hittable::*, ray::*};
 use std::sync::Arc;
 
@@ -24,7 +26,7 @@ impl Default for HittableList {
     }
 }
 impl Hit for HittableList {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let mut tmp_rec: Option<HitRecord> = None;
         let mut closest_so_far = t_max;
         for object in self.objects.iter() {
@@ -35,6 +37,11 @@ impl Hit for HittableList {
         }
         tmp_rec
     }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.objects
+            .iter()
+            .any(|object| object.occluded(r, t_min, t_max))
+    }
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AaBb> {
         if self.objects.is_empty() {
             return None;
@@ -55,4 +62,22 @@ impl Hit for HittableList {
             None
         }
     } //自己改写的方法
+    /// The average of the pdfs of the objects, matching `random`.
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects
+            .iter()
+            .map(|object| weight * object.pdf_value(o, v))
+            .sum()
+    }
+    /// Samples a direction towards one of the objects, each picked with equal chance.
+    fn random(&self, o: &Vec3) -> Vec3 {
+        // the pick does not take a sampler dimension, so that the point on the object
+        // lines up with the direction that BSDF sampling draws at the same bounce
+        let index = (random_f64() * self.objects.len() as f64) as usize;
+        self.objects[index.min(self.objects.len() - 1)].random(o)
+    }
 }
@@ -0,0 +1,57 @@
+//! A path tracer following the "Ray Tracing in One Weekend" book series.
+//!
+//! Build a [`Scene`] (by hand, from [`scenes::builtin`] or from a text file with
+//! [`scene_file::load`]), then hand it to a [`Renderer`] to get a linear float [`Film`]:
+//!
+//! ```
+//! use raytracer::{scenes, RenderSettings, Renderer};
+//!
+//! let desc = scenes::builtin("two_spheres").unwrap();
+//! let renderer = Renderer::new(RenderSettings {
+//!     width: 32,
+//!     height: 18,
+//!     samples_per_pixel: 4,
+//!     ..RenderSettings::default()
+//! });
+//! let film = renderer.render(&desc.scene);
+//! assert_eq!(film.data().len(), 32 * 18 * 3);
+//! ```
+pub mod aabb;
+pub mod aarect;
+pub mod aov;
+pub mod r#box;
+pub mod bvh;
+pub mod camera;
+pub mod checkpoint;
+pub mod color;
+pub mod film;
+pub mod filter;
+pub mod hittable;
+pub mod hittable_list;
+pub mod material;
+pub mod matrix;
+pub mod medium;
+pub mod obj;
+pub mod onb;
+pub mod output;
+pub mod pdf;
+pub mod perlin;
+pub mod ply;
+pub mod ray;
+pub mod renderer;
+pub mod rtweekend;
+pub mod sampler;
+pub mod scene;
+pub mod scene_file;
+pub mod scenes;
+pub mod sphere;
+pub mod texture;
+pub mod tonemap;
+pub mod triangle;
+pub mod vec3;
+
+pub use crate::film::Film;
+pub use crate::ray::Ray;
+pub use crate::renderer::{RenderSettings, Renderer};
+pub use crate::scene::{Scene, SceneDescription};
+pub use crate::vec3::Vec3;
@@ -1,723 +1,341 @@
-extern crate core;
+mod cli;
 
-mod aabb;
-mod aarect;
-mod r#box;
-mod bvh;
-mod camera;
-mod color;
-mod hittable;
-mod hittable_list;
-mod material;
-mod medium;
-mod onb;
-mod pdf;
-mod perlin;
-mod ray;
-mod rtweekend;
-mod sphere;
-mod texture;
-mod vec3;
-
-use crate::bvh::BvhNode;
-use crate::camera::Camera;
-use crate::hittable::{FlipFace, Hit, RotateY, Translate};
-use crate::hittable_list::HittableList;
-use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal};
-pub use crate::ray::Ray;
-use crate::rtweekend::*;
-use crate::sphere::{MovingSphere, Sphere};
-use color::write_color;
-
-use crate::aarect::{XYRect, XZRect, YZRect};
-use crate::medium::ConstantMedium;
-use crate::r#box::Bbox;
-use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture};
-use crate::vec3::{Color, Point3};
-use image::ImageBuffer;
+use crate::cli::{Command, Options, OutputFormat, SceneSource};
 use indicatif::ProgressBar;
+use raytracer::checkpoint::{load_checkpoint, save_checkpoint, CheckpointInfo};
+use raytracer::film::SampleFilm;
+use raytracer::rtweekend::entropy_seed;
+use raytracer::{output, scene_file, scenes, Film, RenderSettings, Renderer, SceneDescription};
+use std::env;
 use std::fs::File;
-// use std::sync::mpsc::channel;
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::SystemTime;
-
-// use threadpool::ThreadPool;
-use crate::pdf::{CosinePdf, HittablePdf, MixturePdf, Pdf};
-pub use vec3::Vec3;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
 const AUTHOR: &str = "Dizzy_D";
 
+/// The most samples of a pixel that its auxiliary passes are made from.
+const AOV_SAMPLES: usize = 16;
+
+/// Set by the first Ctrl-C, which ends the render with the samples taken so far.
+static CANCEL: AtomicBool = AtomicBool::new(false);
+
 fn is_ci() -> bool {
     option_env!("CI").unwrap_or_default() == "true"
 }
 
-fn ray_color(
-    r: Ray,
-    background: &Color,
-    world: &dyn Hit,
-    lights: Arc<dyn Hit>,
-    depth: i32,
-) -> Color {
-    if depth <= 0 {
-        return Vec3::zero();
-    }
-    let mut pdf = 0.0;
-    return if let Some(rec) = world.hit(&r, 0.001, f64::INFINITY) {
-        let emitted = rec.material.emitted(&r, &rec, rec.u, rec.v, &rec.p);
-        if let Some((mut scattered, attenuation)) = rec.material.scatter(&r, &rec, &mut pdf) {
-            let on_light = Point3::new(random(213.0, 343.0), 554.0, random(227.0, 332.0));
-            let mut to_light = on_light - rec.p.clone();
-            to_light = to_light.unit_vector();
-            if to_light.dot(rec.normal.clone()) < 0.0 {
-                return emitted;
-            }
-            let light_cosine = to_light.y().abs();
-            if light_cosine < 0.000001 {
-                return emitted;
-            }
-            let p0 = Arc::new(HittablePdf::new(lights.clone(), rec.p.clone()));
-            let p1 = Arc::new(CosinePdf::new(&rec.normal));
-            let mix_pdf = MixturePdf::new(p0, p1);
-            scattered = Ray::new(rec.p.clone(), mix_pdf.generate(), r.time());
-            pdf = mix_pdf.value(&scattered.dir());
-            emitted
-                + attenuation
-                    * rec.material.scattering_pdf(&r, &rec, &scattered)
-                    * ray_color(scattered, background, world, lights, depth - 1)
-                    / pdf
-        } else {
-            emitted
+/// Writes `film` to `file` in the format of the options, `display` being what the
+/// sRGB-encoded formats get, and `layers` more channels of an OpenEXR file.
+fn write_image(
+    mut file: File,
+    options: &Options,
+    film: &Film,
+    display: &Film,
+    layers: &[(String, Vec<f32>)],
+) -> Result<(), String> {
+    match options.format {
+        OutputFormat::Jpeg | OutputFormat::Png | OutputFormat::Bmp => {
+            let output_image = if options.bit_depth == 16 {
+                image::DynamicImage::ImageRgb16(display.to_rgb16(options.dither))
+            } else {
+                image::DynamicImage::ImageRgb8(display.to_rgb8(options.dither))
+            };
+            let format = match options.format {
+                OutputFormat::Jpeg => image::ImageOutputFormat::Jpeg(options.quality),
+                OutputFormat::Png => image::ImageOutputFormat::Png,
+                _ => image::ImageOutputFormat::Bmp,
+            };
+            output_image
+                .write_to(&mut file, format)
+                .map_err(|e| e.to_string())
         }
-    } else {
-        background.clone()
-    };
-}
-
-pub fn random_scene() -> HittableList {
-    let mut world = HittableList::new();
-    let checker = Arc::new(CheckerTexture::new_color(
-        Color::new(0.9, 0.9, 0.9),
-        Color::new(0.2, 0.3, 0.1),
-    ));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Lambertian::new_arc(checker),
-    )));
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random_f64();
-            let center = Vec3::new(
-                a as f64 + 0.9 * random_f64(),
-                0.2,
-                b as f64 + 0.9 * random_f64(),
-            );
-            if (center.clone() - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.8 {
-                    let albedo = Vec3::random_f64() * Vec3::random_f64();
-                    let sphere_material = Lambertian::new_color(albedo);
-                    let center2 = center.clone() + Vec3::new(0.0, random(0.0, 0.5), 0.0);
-                    world.add(Arc::new(MovingSphere::new(
-                        center,
-                        center2,
-                        0.0,
-                        1.0,
-                        0.2,
-                        sphere_material,
-                    )));
-                } else if choose_mat < 0.95 {
-                    let albedo = Vec3::random(0.5, 1.0);
-                    let fuzz = random(0.0, 0.5);
-                    let sphere_material = Metal::new(&albedo, fuzz);
-                    world.add(Arc::new(Sphere::new(center, 0.2, sphere_material)));
-                } else {
-                    let sphere_material = Dielectric::new(1.5);
-                    world.add(Arc::new(Sphere::new(center, 0.2, sphere_material)));
+        OutputFormat::Ppm | OutputFormat::Pfm | OutputFormat::Hdr | OutputFormat::Exr => {
+            let file = BufWriter::new(file);
+            match options.format {
+                OutputFormat::Ppm => {
+                    output::write_ppm(display, file, options.bit_depth, options.dither)
                 }
+                // the float film as it is
+                OutputFormat::Pfm => output::write_pfm(film, file),
+                OutputFormat::Hdr => output::write_rgbe(film, file),
+                _ => output::write_exr_layers(film, layers, file, options.exr_compression),
             }
+            .map_err(|e| e.to_string())
         }
     }
-    let material1 = Dielectric::new(1.5);
-    world.add(Arc::new(Sphere::new(
-        Vec3::new(0.0, 1.0, 0.0),
-        1.0,
-        material1,
-    )));
-    let material2 = Lambertian::new_color(Color::new(0.4, 0.2, 0.1));
-    world.add(Arc::new(Sphere::new(
-        Vec3::new(-4.0, 1.0, 0.0),
-        1.0,
-        material2,
-    )));
-    let material3 = Metal::new(&Vec3::new(0.7, 0.6, 0.5), 0.0);
-    world.add(Arc::new(Sphere::new(
-        Vec3::new(4.0, 1.0, 0.0),
-        1.0,
-        material3,
-    )));
-    world
 }
 
-fn two_spheres() -> HittableList {
-    let checker = Arc::new(CheckerTexture::new_color(
-        Color::new(0.9, 0.9, 0.9),
-        Color::new(0.2, 0.3, 0.1),
-    ));
-    let mut objects = HittableList::new();
-    objects.add(Arc::new(Sphere::new(
-        Point3::new(0.0, -10.0, 0.0),
-        10.0,
-        Lambertian::new_arc(checker.clone()),
-    )));
-    objects.add(Arc::new(Sphere::new(
-        Point3::new(0.0, 10.0, 0.0),
-        10.0,
-        Lambertian::new_arc(checker),
-    )));
-    objects
-}
-fn two_perlin_spheres() -> HittableList {
-    let mut obj = HittableList::new();
-    let pertext = Arc::new(NoiseTexture::new(4.0));
-    obj.add(Arc::new(Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Lambertian::new_arc(pertext.clone()),
-    )));
-    obj.add(Arc::new(Sphere::new(
-        Point3::new(0.0, 2.0, 0.0),
-        2.0,
-        Lambertian::new_arc(pertext),
-    )));
-    obj
-}
-fn earth() -> HittableList {
-    let earth_texture = ImageTexture::new();
-    let mut obj = HittableList::new();
-    let earth_surface = Lambertian::new_arc(Arc::new(earth_texture));
-    obj.add(Arc::new(Sphere::new(
-        Point3::new(0.0, 0.0, 0.0),
-        2.0,
-        earth_surface,
-    )));
-    obj
-}
-fn simple_light() -> HittableList {
-    let mut obj = HittableList::new();
-    let pertext = Arc::new(NoiseTexture::new(4.0));
-    obj.add(Arc::new(Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Lambertian::new_arc(pertext.clone()),
-    )));
-    obj.add(Arc::new(Sphere::new(
-        Point3::new(0.0, 2.0, 0.0),
-        2.0,
-        Lambertian::new_arc(pertext),
-    )));
-    let difflight = Arc::new(DiffuseLight::new_color(Color::new(4.0, 4.0, 4.0)));
-    obj.add(Arc::new(XYRect::new(difflight, 3.0, 5.0, 1.0, 3.0, -2.0)));
-    obj.add(Arc::new(Sphere::new(
-        Point3::new(0.0, 7.0, 0.0),
-        2.0,
-        DiffuseLight::new_color(Color::new(4.0, 4.0, 4.0)),
-    )));
-    obj
-}
-fn cornell_box() -> HittableList {
-    let mut obj = HittableList::new();
-    let red = Arc::new(Lambertian::new_color(Color::new(0.65, 0.05, 0.05)));
-    let white = Arc::new(Lambertian::new_color(Color::new(0.73, 0.73, 0.73)));
-    let green = Arc::new(Lambertian::new_color(Color::new(0.12, 0.45, 0.15)));
-    let light = Arc::new(DiffuseLight::new_color(Color::new(15.0, 15.0, 15.0)));
-    obj.add(Arc::new(YZRect::new(green, 0.0, 555.0, 0.0, 555.0, 555.0)));
-    obj.add(Arc::new(YZRect::new(red, 0.0, 555.0, 0.0, 555.0, 0.0)));
-    obj.add(Arc::new(FlipFace::new(Arc::new(XZRect::new(
-        light, 213.0, 343.0, 227.0, 332.0, 554.0,
-    )))));
-    obj.add(Arc::new(XZRect::new(
-        white.clone(),
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        0.0,
-    )));
-    obj.add(Arc::new(XZRect::new(
-        white.clone(),
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        555.0,
-    )));
-    obj.add(Arc::new(XYRect::new(
-        white.clone(),
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        555.0,
-    )));
-    let box1 = Arc::new(Translate::new(
-        Arc::new(RotateY::new(
-            Arc::new(Bbox::new(
-                &Point3::zero(),
-                &Point3::new(165.0, 330.0, 165.0),
-                white.clone(),
-            )),
-            15.0,
-        )),
-        Vec3::new(265.0, 0.0, 295.0),
-    ));
-    obj.add(box1);
-
-    let box2 = Arc::new(Bbox::new(
-        &Point3::zero(),
-        &Point3::new(165.0, 165.0, 165.0),
-        white,
-    ));
-    let box2 = Arc::new(RotateY::new(box2, -18.0));
-    let box2 = Arc::new(Translate::new(box2, Vec3::new(130.0, 0.0, 65.0)));
-    obj.add(box2);
-    obj
+/// Where pass `name` goes next to the image at `path`: `out.png` has `out.depth.png`.
+fn aov_path(path: &Path, name: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, name, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, name),
+    };
+    path.with_file_name(file_name)
 }
-fn cornell_smoke() -> HittableList {
-    let mut obj = HittableList::new();
-    let red = Arc::new(Lambertian::new_color(Color::new(0.65, 0.05, 0.05)));
-    let white = Arc::new(Lambertian::new_color(Color::new(0.73, 0.73, 0.73)));
-    let green = Arc::new(Lambertian::new_color(Color::new(0.12, 0.45, 0.15)));
-    let light = Arc::new(DiffuseLight::new_color(Color::new(15.0, 15.0, 15.0)));
-    obj.add(Arc::new(YZRect::new(green, 0.0, 555.0, 0.0, 555.0, 555.0)));
-    obj.add(Arc::new(YZRect::new(red, 0.0, 555.0, 0.0, 555.0, 0.0)));
-    obj.add(Arc::new(XZRect::new(
-        light, 213.0, 343.0, 227.0, 332.0, 554.0,
-    )));
-    obj.add(Arc::new(XZRect::new(
-        white.clone(),
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        0.0,
-    )));
-    obj.add(Arc::new(XZRect::new(
-        white.clone(),
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        555.0,
-    )));
-    obj.add(Arc::new(XYRect::new(
-        white.clone(),
-        0.0,
-        555.0,
-        0.0,
-        555.0,
-        555.0,
-    )));
-    let box1 = Arc::new(Translate::new(
-        Arc::new(RotateY::new(
-            Arc::new(Bbox::new(
-                &Point3::zero(),
-                &Point3::new(165.0, 330.0, 165.0),
-                white.clone(),
-            )),
-            15.0,
-        )),
-        Vec3::new(265.0, 0.0, 295.0),
-    ));
 
-    let box2 = Arc::new(Bbox::new(
-        &Point3::zero(),
-        &Point3::new(165.0, 165.0, 165.0),
-        white,
-    ));
-    let box2 = Arc::new(RotateY::new(box2, -18.0));
-    let box2 = Arc::new(Translate::new(box2, Vec3::new(130.0, 0.0, 65.0)));
-    obj.add(Arc::new(ConstantMedium::new_color(
-        box1,
-        0.01,
-        Color::zero(),
-    )));
-    obj.add(Arc::new(ConstantMedium::new_color(
-        box2,
-        0.01,
-        Color::ones(),
-    )));
-    obj
-}
-fn final_scene() -> HittableList {
-    let mut boxes1 = HittableList::new();
-    let ground = Arc::new(Lambertian::new_color(Color::new(0.48, 0.83, 0.53)));
-    let boxes_per_side = 20;
-    for i in 0..boxes_per_side {
-        for j in 0..boxes_per_side {
-            let w = 100.0;
-            let x0 = -1000.0 + i as f64 * w;
-            let z0 = -1000.0 + j as f64 * w;
-            let y0 = 0.0;
-            let x1 = x0 + w;
-            let y1 = random(1.0, 101.0);
-            let z1 = z0 + w;
-            boxes1.add(Arc::new(Bbox::new(
-                &Point3::new(x0, y0, z0),
-                &Point3::new(x1, y1, z1),
-                ground.clone(),
-            )));
-        }
-    }
-    let mut obj = HittableList::new();
-    obj.add(BvhNode::newnew(boxes1, 0.0, 1.0));
-    let light = Arc::new(DiffuseLight::new_color(Color::new(7.0, 7.0, 7.0)));
-    obj.add(Arc::new(XZRect::new(
-        light, 123.0, 423.0, 147.0, 412.0, 554.0,
-    )));
-    let center1 = Point3::new(400.0, 400.0, 400.0);
-    let center2 = center1.clone() + Vec3::new(30.0, 0.0, 0.0);
-    let moving_sphere_material = Lambertian::new_color(Color::new(0.7, 0.3, 0.1));
-    obj.add(Arc::new(MovingSphere::new(
-        center1,
-        center2,
-        0.0,
-        1.0,
-        50.0,
-        moving_sphere_material,
-    )));
-    obj.add(Arc::new(Sphere::new(
-        Point3::new(260.0, 150.0, 45.0),
-        50.0,
-        Dielectric::new(1.5),
-    )));
-    obj.add(Arc::new(Sphere::new(
-        Point3::new(0.0, 150.0, 145.0),
-        50.0,
-        Metal::new(&Color::new(0.8, 0.8, 0.8), 1.0),
-    )));
-    let mut boundary = Arc::new(Sphere::new(
-        Point3::new(360.0, 150.0, 145.0),
-        70.0,
-        Dielectric::new(1.05),
-    ));
-    obj.add(boundary.clone());
-    obj.add(Arc::new(ConstantMedium::new_color(
-        boundary,
-        0.2,
-        Color::new(0.2, 0.4, 0.9),
-    )));
-    boundary = Arc::new(Sphere::new(Point3::zero(), 5000.0, Dielectric::new(1.5)));
-    obj.add(Arc::new(ConstantMedium::new_color(
-        boundary,
-        0.0001,
-        Color::ones(),
-    )));
-    let emat = Lambertian::new_arc(Arc::new(ImageTexture::new()));
-    obj.add(Arc::new(Sphere::new(
-        Point3::new(400.0, 200.0, 400.0),
-        100.0,
-        emat,
-    )));
-    let pertext = Arc::new(NoiseTexture::new(0.1));
-    obj.add(Arc::new(Sphere::new(
-        Point3::new(220.0, 280.0, 300.0),
-        80.0,
-        Lambertian::new_arc(pertext),
-    )));
-    let mut boxes2 = HittableList::new();
-    let ns = 1000;
-    for _ in 0..ns {
-        boxes2.add(Arc::new(Sphere::new(
-            Point3::random(0.0, 165.0),
-            10.0,
-            Lambertian::new_color(Color::new(0.73, 0.73, 0.73)),
-        )));
-    }
-    obj.add(Arc::new(Translate::new(
-        Arc::new(RotateY::new(BvhNode::newnew(boxes2, 0.0, 1.0), 15.0)),
-        Vec3::new(-100.0, 270.0, 395.0),
-    )));
-    obj
-}
 fn main() {
+    let options = match cli::parse_args(env::args().skip(1)) {
+        Ok(Command::Render(options)) => *options,
+        Ok(Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
     // get environment variable CI, which is true for GitHub Actions
     let is_ci = is_ci();
 
-    println!("CI: {}", is_ci);
-
-    let path = "output/test.jpg";
-    let quality = 60; // From 0 to 100, suggested value: 60
-                      //time
-    let sys_time1 = SystemTime::now();
-    //image
-    const ASPECT_RATIO: f64 = 16.0 / 9.0;
-    const IMAGE_WIDTH: usize = 400;
-    // const IMAGE_HEIGHT: usize = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as usize;
-    let mut samples_per_pixel: usize = 100;
-    const MAX_DEPTH: usize = 50;
-    let mut width = IMAGE_WIDTH;
-    let mut aspect_ratio = ASPECT_RATIO;
-
-    //world
-    let obj;
-    let lookfrom;
-    let lookat;
-    let vfov;
-    let mut aperture = 0.0;
-    let background;
-    let mode = 6;
-    match mode {
-        1 => {
-            obj = random_scene();
-            lookfrom = Point3::new(13.0, 2.0, 3.0);
-            lookat = Point3::new(0.0, 0.0, 0.0);
-            background = Color::new(0.7, 0.8, 1.0);
-            vfov = 20.0;
-            aperture = 0.1;
-        }
-        2 => {
-            obj = two_spheres();
-            lookfrom = Point3::new(13.0, 2.0, 3.0);
-            lookat = Point3::new(0.0, 0.0, 0.0);
-            background = Color::new(0.7, 0.8, 1.0);
-            vfov = 20.0;
-        }
-        3 => {
-            obj = two_perlin_spheres();
-            lookfrom = Point3::new(13.0, 2.0, 3.0);
-            lookat = Point3::new(0.0, 0.0, 0.0);
-            background = Color::new(0.7, 0.8, 1.0);
-            vfov = 20.0;
-        }
-        4 => {
-            obj = earth();
-            lookfrom = Point3::new(13.0, 2.0, 3.0);
-            lookat = Point3::new(0.0, 0.0, 0.0);
-            background = Color::new(0.7, 0.8, 1.0);
-            vfov = 20.0;
+    let path = &options.output;
+    // fail before rendering rather than after it
+    let output_file = match File::create(path) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("error: cannot create \"{}\": {}", path.display(), e);
+            process::exit(1);
         }
-        5 => {
-            obj = simple_light();
-            samples_per_pixel = 400;
-            background = Color::zero();
-            lookfrom = Point3::new(26.0, 3.0, 6.0);
-            lookat = Point3::new(0.0, 2.0, 0.0);
-            vfov = 20.0;
-        }
-        6 => {
-            obj = cornell_box();
-            aspect_ratio = 1.0;
-            width = 600;
-            samples_per_pixel = 1000;
-            background = Color::zero();
-            lookfrom = Point3::new(278.0, 278.0, -800.0);
-            lookat = Point3::new(278.0, 278.0, 0.0);
-            vfov = 40.0;
-        }
-        7 => {
-            obj = cornell_smoke();
-            aspect_ratio = 1.0;
-            width = 600;
-            samples_per_pixel = 200;
-            background = Color::zero();
-            lookfrom = Point3::new(278.0, 278.0, -800.0);
-            lookat = Point3::new(278.0, 278.0, 0.0);
-            vfov = 40.0;
+    };
+    //time
+    let sys_time1 = SystemTime::now();
+    let desc = match &options.scene {
+        SceneSource::File(file) => scene_file::load(file).unwrap_or_else(|e| {
+            eprintln!("{}: {}", file.display(), e);
+            process::exit(1);
+        }),
+        SceneSource::Builtin(name) => scenes::builtin(name).expect("unknown built-in scene"),
+    };
+    let SceneDescription {
+        mut scene,
+        image_width,
+        aspect_ratio,
+        samples_per_pixel,
+        max_depth,
+    } = desc;
+    let resumed = options.resume.as_ref().map(|path| {
+        load_checkpoint(path).unwrap_or_else(|e| {
+            eprintln!(
+                "error: cannot read checkpoint \"{}\": {}",
+                path.display(),
+                e
+            );
+            process::exit(1);
+        })
+    });
+    if let Some((info, film)) = &resumed {
+        let differs = [
+            ("--width", options.width.is_some_and(|w| w != film.width())),
+            (
+                "--height",
+                options.height.is_some_and(|h| h != film.height()),
+            ),
+            ("--seed", options.seed.is_some_and(|s| s != info.seed)),
+            (
+                "--sampler",
+                options.sampler.is_some_and(|s| s != info.sampler),
+            ),
+            ("--filter", options.filter.is_some_and(|f| f != info.filter)),
+            (
+                "--max-depth",
+                options.max_depth.is_some_and(|d| d != info.max_depth),
+            ),
+            (
+                "--pass-samples",
+                options.pass_samples.is_some_and(|n| n != info.pass_samples),
+            ),
+        ];
+        if let Some((flag, _)) = differs.iter().find(|(_, differs)| *differs) {
+            eprintln!("error: `{}` differs from the checkpoint", flag);
+            process::exit(2);
         }
-        _ => {
-            obj = final_scene();
-            aspect_ratio = 1.0;
-            width = 800;
-            // width = 300;
-            // samples_per_pixel = 10000;
-            samples_per_pixel = 100;
-            background = Color::zero();
-            lookfrom = Point3::new(478.0, 278.0, -600.0);
-            lookat = Point3::new(278.0, 278.0, 0.0);
-            vfov = 40.0;
+    }
+    let size = match &resumed {
+        Some((_, film)) => (Some(film.width()), Some(film.height())),
+        None => (options.width, options.height),
+    };
+    let (width, height) = match size {
+        (Some(w), Some(h)) => {
+            scene.camera.set_aspect_ratio(w as f64 / h as f64);
+            (w, h)
         }
+        (Some(w), None) => (w, (w as f64 / aspect_ratio) as usize),
+        (None, Some(h)) => ((h as f64 * aspect_ratio) as usize, h),
+        (None, None) => (image_width, (image_width as f64 / aspect_ratio) as usize),
+    };
+    if width < 2 || height < 2 {
+        eprintln!(
+            "error: the image must be at least 2x2 pixels, got {}x{}",
+            width, height
+        );
+        process::exit(2);
     }
-    let world = BvhNode::newnew(obj, 0.0, 1.0);
-    let lights = Arc::new(XZRect::new(
-        Arc::new(DiffuseLight::new_color(Color::new(15.0, 15.0, 15.0))),
-        213.0,
-        343.0,
-        227.0,
-        332.0,
-        554.0,
-    ));
-    let height = (width as f64 / aspect_ratio) as usize;
-    //camera
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        vfov,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-        0.0,
-        1.0,
-    );
-
-    // Create image data
-    // let mut img = ImageBuffer::new(width.try_into().unwrap(), height.try_into().unwrap());
+    let defaults = RenderSettings::default();
+    // a checkpoint has to know the seed, so it is picked here rather than by the renderer
+    let info = match &resumed {
+        Some((info, _)) => *info,
+        None => CheckpointInfo {
+            seed: options.seed.unwrap_or_else(entropy_seed),
+            sampler: options.sampler.unwrap_or(defaults.sampler),
+            max_depth: options.max_depth.unwrap_or(max_depth),
+            pass_samples: options.pass_samples.unwrap_or(defaults.pass_samples),
+            filter: options.filter.unwrap_or(defaults.filter),
+        },
+    };
+    // a time limit alone sets no bound on the samples
+    let samples_per_pixel = match (options.samples_per_pixel, options.time_limit) {
+        (Some(n), _) => n,
+        (None, Some(_)) => usize::MAX,
+        (None, None) => samples_per_pixel,
+    };
+    let renderer = Renderer::new(RenderSettings {
+        width,
+        height,
+        samples_per_pixel,
+        max_depth: info.max_depth,
+        threads: options.threads.unwrap_or(defaults.threads),
+        tile_size: options.tile_size.unwrap_or(defaults.tile_size),
+        seed: Some(info.seed),
+        sampler: info.sampler,
+        noise_threshold: options.noise_threshold,
+        min_samples: options.min_samples.unwrap_or(defaults.min_samples),
+        pass_samples: info.pass_samples,
+        time_limit: options.time_limit,
+        filter: info.filter,
+    });
+    let checkpoint = options.checkpoint.as_ref().or(options.resume.as_ref());
+    let save = |film: &SampleFilm| {
+        if let Some(path) = checkpoint {
+            if let Err(e) = save_checkpoint(path, &info, film) {
+                eprintln!(
+                    "warning: cannot write checkpoint \"{}\": {}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+    };
+    let interval = Duration::from_secs_f64(options.checkpoint_interval);
+    let mut last_checkpoint = Instant::now();
 
     // Progress bar UI powered by library `indicatif`
-    // You can use indicatif::ProgressStyle to make it more beautiful
-    // You can also use indicatif::MultiProgress in multi-threading to show progress of each thread
-
-    // let sections = 20;
-    // let workers = 20; //20,20 2min38s
-    // let bar = if is_ci {
-    //     ProgressBar::hidden()
-    // } else {
-    //     ProgressBar::new((height * width) as u64)
-    //     // ProgressBar::new(sections as u64)
-    // };
     let bar = if is_ci {
-        Arc::new(ProgressBar::hidden())
+        ProgressBar::hidden()
+    } else if let Some(limit) = options.time_limit {
+        // in milliseconds of the time limit
+        ProgressBar::new(limit.as_millis() as u64)
     } else {
-        Arc::new(ProgressBar::new((height * width) as u64))
+        // in samples, of which adaptive sampling may take fewer
+        ProgressBar::new((height * width * samples_per_pixel) as u64)
     };
-    // for j in 0..height {
-    //     for i in 0..width {
-    //         let mut color = Vec3::new(0.0, 0.0, 0.0);
-    //         for _s in 0..samples_per_pixel {
-    //             let u = (i as f64 + random_f64()) / ((width - 1) as f64);
-    //             let v = (j as f64 + random_f64()) / ((height - 1) as f64);
-    //             let r = cam.get_ray(u, v);
-    //             color += ray_color(r, &background, &*world, MAX_DEPTH as i32);
-    //         }
-    //         let scale = 1.0 / samples_per_pixel as f64;
-    //         let r = (color.x() * scale).sqrt();
-    //         let g = (color.y() * scale).sqrt();
-    //         let b = (color.z() * scale).sqrt();
-    //         let pixel_color = [
-    //             (256.0 * clamp(r, 0.0, 0.999)) as u8,
-    //             (256.0 * clamp(g, 0.0, 0.999)) as u8,
-    //             (256.0 * clamp(b, 0.0, 0.999)) as u8,
-    //         ];
-    //
-    //         write_color(pixel_color, &mut img, i, height - j - 1);
-    //         bar.inc(1);
-    //     }
-    // }
-
-    //方法一：mpsc
-    // let (sender, receiver) = channel();
-    // let pool = ThreadPool::new(workers);
-    // for t in 0..sections {
-    //     let sender = sender.clone();
-    //     let worldd = world.clone();
-    //     let camm = cam.clone();
-    //     let bg = background.clone();
-    //     pool.execute(move || {
-    //         let begin = height * t / sections;
-    //         let end = height * (t + 1) / sections;
-    //         let mut res = ImageBuffer::new(width as u32, height as u32 / sections as u32);
-    //         for (img_j, j) in (begin..end).enumerate() {
-    //             for i in 0..width {
-    //                 let mut color = Vec3::new(0.0, 0.0, 0.0);
-    //                 for _s in 0..samples_per_pixel {
-    //                     let u = (i as f64 + random_f64()) / ((width - 1) as f64);
-    //                     let v = (j as f64 + random_f64()) / ((height - 1) as f64);
-    //                     let r = camm.get_ray(u, v);
-    //                     color += ray_color(r, &bg, &*worldd, MAX_DEPTH as i32);
-    //                 }
-    //                 let scale = 1.0 / samples_per_pixel as f64;
-    //                 let r = (color.x() * scale).sqrt();
-    //                 let g = (color.y() * scale).sqrt();
-    //                 let b = (color.z() * scale).sqrt();
-    //                 let pixel_color = [
-    //                     (256.0 * clamp(r, 0.0, 0.999)) as u8,
-    //                     (256.0 * clamp(g, 0.0, 0.999)) as u8,
-    //                     (256.0 * clamp(b, 0.0, 0.999)) as u8,
-    //                 ];
-    //                 write_color(pixel_color, &mut res, i, img_j);
-    //             }
-    //         }
-    //         sender
-    //             .send((begin..end, res))
-    //             .expect("Fail to send the result!");
-    //     });
-    // }
-    // let mut img = ImageBuffer::new(width as u32, height as u32);
-    // for (rows, data) in receiver.iter().take(sections) {
-    //     for (idx, row) in rows.enumerate() {
-    //         for col in 0..width {
-    //             *img.get_pixel_mut(col as u32, (height - row - 1) as u32) =
-    //                 *data.get_pixel(col as u32, idx as u32);
-    //             bar.inc(1);
-    //         }
-    //     }
-    // }
-
-    //方法二：Arc+Mutex
-    let img = Arc::new(Mutex::new(ImageBuffer::new(
-        width.try_into().unwrap(),
-        height.try_into().unwrap(),
-    )));
-    let mut handles = vec![];
-    let thread_number = 31; //
-    for t in 0..thread_number {
-        let world = Arc::clone(&world);
-        let img = Arc::clone(&img);
-        let bar = Arc::clone(&bar);
-        let bg = background.clone();
-        let camm = cam.clone();
-        let lit = Arc::clone(&lights);
-        let handle = thread::spawn(move || {
-            for j in (t * height / thread_number)..((t + 1) * height / thread_number) {
-                for i in 0..width {
-                    let mut color = Color::zero();
-                    for _s in 0..samples_per_pixel {
-                        let u = (i as f64 + random_f64()) / (width - 1) as f64;
-                        let v = (j as f64 + random_f64()) / (height - 1) as f64;
-                        let r = camm.get_ray(u, v);
-                        color += ray_color(r, &bg, &*world, lit.clone(), MAX_DEPTH as i32);
-                    }
-                    let scale = 1.0 / samples_per_pixel as f64;
-                    let r = (color.x() * scale).sqrt();
-                    let g = (color.y() * scale).sqrt();
-                    let b = (color.z() * scale).sqrt();
-                    let pixel_color = [
-                        (256.0 * clamp(r, 0.0, 0.999)) as u8,
-                        (256.0 * clamp(g, 0.0, 0.999)) as u8,
-                        (256.0 * clamp(b, 0.0, 0.999)) as u8,
-                    ];
-                    write_color(pixel_color, &mut img.lock().unwrap(), i, height - j - 1);
-                    bar.inc(1);
-                }
-            }
-        });
-        handles.push(handle);
-    }
-    for handle in handles {
-        handle.join().unwrap();
+    // a second Ctrl-C does not wait for the tiles in progress
+    if let Err(e) = ctrlc::set_handler(|| {
+        if CANCEL.swap(true, Ordering::Relaxed) {
+            process::exit(130);
+        }
+    }) {
+        eprintln!("warning: Ctrl-C will not stop the render cleanly: {}", e);
     }
+    let film = match resumed {
+        Some((_, film)) => film,
+        None => SampleFilm::new(width, height),
+    };
+    let render_start = Instant::now();
+    let samples = renderer.resume(
+        &scene,
+        film,
+        |done| match options.time_limit {
+            Some(_) => bar.set_position(render_start.elapsed().as_millis() as u64),
+            None => bar.set_position(done as u64),
+        },
+        |film| {
+            if last_checkpoint.elapsed() >= interval {
+                save(film);
+                last_checkpoint = Instant::now();
+            }
+        },
+        &CANCEL,
+    );
     // Finish progress bar
     bar.finish();
+    if CANCEL.load(Ordering::Relaxed) {
+        println!("stopped by Ctrl-C");
+    }
+    println!(
+        "{:.1} samples per pixel on average",
+        samples.total_count() as f64 / (width * height) as f64
+    );
+    save(&samples);
+    let film = samples.to_film();
+    let aovs = (!options.aovs.is_empty()).then(|| {
+        let names: Vec<_> = options.aovs.iter().map(|aov| aov.name()).collect();
+        println!("rendering passes: {}", names.join(", "));
+        renderer.render_aovs(&scene, samples_per_pixel.min(AOV_SAMPLES))
+    });
 
     // Output image to file
     let sys_time2 = SystemTime::now();
-    println!("Ouput image as \"{}\"\n Author: {}", path, AUTHOR);
+    println!(
+        "Output image as \"{}\"\n Author: {}",
+        path.display(),
+        AUTHOR
+    );
     let difference = sys_time2
         .duration_since(sys_time1)
         .expect("Clock may have gone backwards");
     println!(
         "thread number is {};running time is {:?}",
-        thread_number, difference
+        renderer.settings().threads,
+        difference
     );
-    // let output_image = image::DynamicImage::ImageRgb8(img);
-    let output_image =
-        image::DynamicImage::ImageRgb8(Mutex::into_inner(Arc::into_inner(img).unwrap()).unwrap());
-    let mut output_file = File::create(path).unwrap();
-    match output_image.write_to(&mut output_file, image::ImageOutputFormat::Jpeg(quality)) {
-        Ok(_) => {}
-        Err(_) => println!("Outputting image fails."),
+    // what is shown on a display goes through exposure and tone mapping first
+    let display = match options.format {
+        OutputFormat::Pfm | OutputFormat::Hdr | OutputFormat::Exr => None,
+        _ => Some(options.tone_map.apply(&film, options.exposure)),
+    };
+    let display = display.as_ref().unwrap_or(&film);
+    // an OpenEXR file takes the passes as layers, other formats get a file for each
+    let layers = match (&aovs, options.format) {
+        (Some(aovs), OutputFormat::Exr) => options
+            .aovs
+            .iter()
+            .flat_map(|&aov| aovs.layer(aov))
+            .collect(),
+        _ => Vec::new(),
+    };
+    let written = write_image(output_file, &options, &film, display, &layers);
+    if let Err(e) = written {
+        eprintln!("error: cannot write \"{}\": {}", path.display(), e);
+        process::exit(1);
+    }
+    if let (Some(aovs), false) = (&aovs, options.format == OutputFormat::Exr) {
+        for &aov in &options.aovs {
+            let path = aov_path(path, aov.name());
+            // the values themselves in float formats, a picture of them in the others
+            let raw = aovs.to_film(aov);
+            let display = aovs.to_display_film(aov);
+            let written = File::create(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|file| write_image(file, &options, &raw, &display, &[]));
+            if let Err(e) = written {
+                eprintln!("error: cannot write \"{}\": {}", path.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(heatmap) = &options.heatmap {
+        // without a bound on the samples, white is the most that any pixel got
+        let max_samples = match samples_per_pixel {
+            usize::MAX => samples.max_count(),
+            n => n,
+        };
+        if let Err(e) = samples.heatmap(max_samples).save(heatmap) {
+            eprintln!("error: cannot write \"{}\": {}", heatmap.display(), e);
+            process::exit(1);
+        }
     }
 }
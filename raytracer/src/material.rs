@@ -1,27 +1,91 @@
 use crate::hittable::HitRecord;
-use crate::onb::Onb;
-use crate::rtweekend::random_f64;
+use crate::pdf::{CosinePdf, Pdf, SpherePdf};
+use crate::sampler::sample_1d;
 use crate::{ray::*, texture::*, vec3::*};
 use std::f64::consts::PI;
 use std::sync::Arc;
 
+/// How a material scatters an incoming ray.
+pub struct ScatterRecord {
+    pub attenuation: Color,
+    /// The one direction a delta BSDF (mirror, glass) scatters into. Such rays are
+    /// followed as they are, without light sampling.
+    pub specular_ray: Option<Ray>,
+    /// The distribution of scattered directions of every other material, which
+    /// the integrator mixes with light sampling.
+    pub pdf: Option<Arc<dyn Pdf>>,
+}
+impl ScatterRecord {
+    pub fn specular(specular_ray: Ray, attenuation: Color) -> Self {
+        Self {
+            attenuation,
+            specular_ray: Some(specular_ray),
+            pdf: None,
+        }
+    }
+    pub fn diffuse(pdf: Arc<dyn Pdf>, attenuation: Color) -> Self {
+        Self {
+            attenuation,
+            specular_ray: None,
+            pdf: Some(pdf),
+        }
+    }
+    pub fn is_specular(&self) -> bool {
+        self.specular_ray.is_some()
+    }
+}
+
 //有关生命周期的部分学习了https://zhuanlan.zhihu.com/p/441138623
 pub trait Material: Send + Sync {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, pdf: &mut f64) -> Option<(Ray, Color)> {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
         None
     }
-    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
         0.0
     }
     fn emitted(&self, _r_in: &Ray, _rec: &HitRecord, _u: f64, _v: f64, _p: &Point3) -> Color {
         Color::zero()
     }
+    /// The color of the surface at `rec` without any lighting, for the albedo pass.
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::zero()
+    }
+    /// Tells materials apart for the material ID pass: the address of the material,
+    /// whatever `Arc`s it is shared through.
+    fn address(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+}
+impl<M: Material + ?Sized> Material for Arc<M> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        (**self).scatter(r_in, rec)
+    }
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        (**self).scattering_pdf(r_in, rec, scattered)
+    }
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: &Point3) -> Color {
+        (**self).emitted(r_in, rec, u, v, p)
+    }
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        (**self).albedo(rec)
+    }
+    fn address(&self) -> usize {
+        (**self).address()
+    }
 }
 pub struct Lambertian {
     pub albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
+    /// The texture, tinted by the vertex colors where the surface has them.
+    fn color(&self, rec: &HitRecord) -> Color {
+        let color = self.albedo.value(rec.u, rec.v, &rec.p);
+        match rec.color {
+            Some(tint) => color * tint,
+            None => color,
+        }
+    }
     pub fn new_color(a: Color) -> Self {
         Self {
             albedo: Arc::new(SolidColor::new(a)),
@@ -32,25 +96,16 @@ impl Lambertian {
     }
 }
 impl Material for Lambertian {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, pdf: &mut f64) -> Option<(Ray, Color)> {
-        // let mut scatter_direction = rec.normal.clone() + random_unit_vector();
-        // if scatter_direction.near_zero() {
-        //     scatter_direction = rec.clone().normal;
-        // }
-        // let dir = scatter_direction.unit_vector();
-        let mut uvw = Onb {
-            axis: vec![Vec3::zero(), Vec3::zero(), Vec3::zero()],
-        };
-        uvw.build_from_w(&rec.normal);
-        let dir = uvw.local_vector(&random_cosine_direction()).unit_vector();
-        // *pdf = rec.normal.dot(dir.clone()) / PI;
-        *pdf = uvw.w().dot(dir.clone()) / PI;
-        Some((
-            Ray::new(rec.p.clone(), dir, r_in.time()),
-            self.albedo.value(rec.u, rec.v, &rec.p),
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::diffuse(
+            Arc::new(CosinePdf::new(&rec.normal)),
+            self.color(rec),
         ))
     }
-    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.color(rec)
+    }
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
         let cosine = rec.normal.dot(scattered.dir().unit_vector());
         if cosine < 0.0 {
             0.0
@@ -73,20 +128,24 @@ impl Metal {
     }
 }
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, pdf: &mut f64) -> Option<(Ray, Vec3)> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
         let reflected = reflect(&r_in.dir().unit_vector(), &rec.normal);
         let scattered = Ray::new(
-            rec.p.clone(),
+            rec.p,
             reflected + self.fuzz * random_in_unit_sphere(),
             r_in.time(),
         );
-        let attenuation = self.albedo.clone();
-        if scattered.dir().dot(rec.normal.clone()) > 0.0 {
-            Some((scattered, attenuation))
+        let attenuation = self.albedo;
+        // a fuzzy reflection is still treated as specular, there is no pdf for it
+        if scattered.dir().dot(rec.normal) > 0.0 {
+            Some(ScatterRecord::specular(scattered, attenuation))
         } else {
             None
         }
     }
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
 }
 #[derive(Clone)]
 pub struct Dielectric {
@@ -103,7 +162,7 @@ impl Dielectric {
     }
 }
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, pdf: &mut f64) -> Option<(Ray, Vec3)> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
         let attenuation = Vec3::ones();
         let refraction_ratio: f64 = if rec.clone().front_face {
             1.0 / self.ir
@@ -111,17 +170,25 @@ impl Material for Dielectric {
             self.ir
         };
         let unit_direction = r_in.dir().unit_vector();
-        let cos_theta: f64 = (-unit_direction.clone()).dot(rec.normal.clone()).min(1.0);
+        let cos_theta: f64 = (-unit_direction).dot(rec.normal).min(1.0);
         let sin_theta: f64 = (1.0 - cos_theta * cos_theta).sqrt();
         let cannot_refract: bool = refraction_ratio * sin_theta > 1.0;
-        let direction = if cannot_refract
-            || Dielectric::reflectance(cos_theta, refraction_ratio) > random_f64()
-        {
-            reflect(&unit_direction, &rec.normal)
-        } else {
-            refract(&unit_direction, &rec.normal, refraction_ratio)
-        };
-        Some((Ray::new(rec.p.clone(), direction, r_in.time()), attenuation))
+        // drawn whether or not it is needed, so that the dimensions stay aligned
+        let u = sample_1d();
+        let direction =
+            if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > u {
+                reflect(&unit_direction, &rec.normal)
+            } else {
+                refract(&unit_direction, &rec.normal, refraction_ratio)
+            };
+        Some(ScatterRecord::specular(
+            Ray::new(rec.p, direction, r_in.time()),
+            attenuation,
+        ))
+    }
+    // clear glass, as denoisers expect it
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::ones()
     }
 }
 
@@ -129,9 +196,9 @@ pub struct DiffuseLight {
     emit: Arc<dyn Texture>,
 }
 impl DiffuseLight {
-    // pub fn new_arc(emit: Arc<dyn Texture>) -> Self {
-    //     Self { emit }
-    // }
+    pub fn new_arc(emit: Arc<dyn Texture>) -> Self {
+        Self { emit }
+    }
     pub fn new_color(c: Color) -> Self {
         Self {
             emit: Arc::new(SolidColor::new(c)),
@@ -139,9 +206,6 @@ impl DiffuseLight {
     }
 }
 impl Material for DiffuseLight {
-    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _pdf: &mut f64) -> Option<(Ray, Vec3)> {
-        None
-    }
     fn emitted(&self, _r_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: &Point3) -> Color {
         if rec.front_face {
             self.emit.value(u, v, p)
@@ -149,6 +213,12 @@ impl Material for DiffuseLight {
             Color::zero()
         }
     }
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.emit
+            .value(rec.u, rec.v, &rec.p)
+            .max(Color::zero())
+            .min(Color::ones())
+    }
 }
 pub struct Isotropic {
     albedo: Arc<dyn Texture>,
@@ -166,10 +236,16 @@ impl Isotropic {
 }
 
 impl Material for Isotropic {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord, pdf: &mut f64) -> Option<(Ray, Vec3)> {
-        Some((
-            Ray::new(rec.p.clone(), random_in_unit_sphere(), r_in.time()),
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::diffuse(
+            Arc::new(SpherePdf),
             self.albedo.value(rec.u, rec.v, &rec.p),
         ))
     }
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p)
+    }
 }
@@ -0,0 +1,227 @@
+//! 4x4 matrices for affine transforms.
+use crate::rtweekend::degrees_to_radians;
+use crate::vec3::{Point3, Vec3};
+use std::ops::Mul;
+
+/// A row-major 4x4 matrix acting on column vectors, so `a * b` applies `b` first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Default for Matrix4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Matrix4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Self { m }
+    }
+    pub fn identity() -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Self { m }
+    }
+    pub fn translation(offset: &Vec3) -> Self {
+        let mut t = Self::identity();
+        t.m[0][3] = offset.x();
+        t.m[1][3] = offset.y();
+        t.m[2][3] = offset.z();
+        t
+    }
+    pub fn scaling(factors: &Vec3) -> Self {
+        let mut s = Self::identity();
+        s.m[0][0] = factors.x();
+        s.m[1][1] = factors.y();
+        s.m[2][2] = factors.z();
+        s
+    }
+    /// Rotation by `angle` degrees about the x axis, turning y towards z.
+    pub fn rotation_x(angle: f64) -> Self {
+        let (sin, cos) = degrees_to_radians(angle).sin_cos();
+        Self::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, cos, -sin, 0.0],
+            [0.0, sin, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    /// Rotation by `angle` degrees about the y axis, the same as `RotateY`.
+    pub fn rotation_y(angle: f64) -> Self {
+        let (sin, cos) = degrees_to_radians(angle).sin_cos();
+        Self::new([
+            [cos, 0.0, sin, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-sin, 0.0, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    /// Rotation by `angle` degrees about the z axis, turning x towards y.
+    pub fn rotation_z(angle: f64) -> Self {
+        let (sin, cos) = degrees_to_radians(angle).sin_cos();
+        Self::new([
+            [cos, -sin, 0.0, 0.0],
+            [sin, cos, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut t = [[0.0; 4]; 4];
+        for (i, row) in t.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = self.m[j][i];
+            }
+        }
+        Self::new(t)
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting, `None` if the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let factor = a[row][col];
+                for j in 0..4 {
+                    a[row][j] -= factor * a[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+        Some(Self::new(inv))
+    }
+
+    /// Whether this is an affine transform that keeps angles: rotations, reflections,
+    /// uniform scales and translations, up to rounding.
+    pub fn is_similarity(&self) -> bool {
+        let m = &self.m;
+        if m[3] != [0.0, 0.0, 0.0, 1.0] {
+            return false;
+        }
+        // in the matrix's own precision, which an `f32` Vec3 would lose
+        let dot = |i: usize, j: usize| (0..3).map(|k| m[k][i] * m[k][j]).sum::<f64>();
+        let scale2 = dot(0, 0);
+        (0..3).all(|i| {
+            (0..3).all(|j| {
+                let expected = if i == j { scale2 } else { 0.0 };
+                (dot(i, j) - expected).abs() <= 1e-9 * scale2
+            })
+        })
+    }
+
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        let v = [p.x(), p.y(), p.z(), 1.0];
+        let row = |i: usize| m[i][0] * v[0] + m[i][1] * v[1] + m[i][2] * v[2] + m[i][3] * v[3];
+        let w = row(3);
+        if w == 1.0 {
+            Point3::new(row(0), row(1), row(2))
+        } else {
+            Point3::new(row(0) / w, row(1) / w, row(2) / w)
+        }
+    }
+
+    /// Transforms a direction, which ignores the translation.
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        let row = |i: usize| m[i][0] * v.x() + m[i][1] * v.y() + m[i][2] * v.z();
+        Vec3::new(row(0), row(1), row(2))
+    }
+}
+
+impl Mul for &Matrix4 {
+    type Output = Matrix4;
+    fn mul(self, other: &Matrix4) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Matrix4::new(m)
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Matrix4;
+    fn mul(self, other: Matrix4) -> Matrix4 {
+        &self * &other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &Vec3, b: &Vec3) {
+        assert!((*a - *b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_inverse() {
+        let m = Matrix4::translation(&Vec3::new(1.0, 2.0, 3.0))
+            * Matrix4::rotation_x(30.0)
+            * Matrix4::scaling(&Vec3::new(2.0, 0.5, -1.0));
+        let product = &m * &m.inverse().unwrap();
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((product.m[i][j] - expected).abs() < 1e-12);
+            }
+        }
+        assert!(Matrix4::scaling(&Vec3::new(1.0, 0.0, 1.0))
+            .inverse()
+            .is_none());
+    }
+
+    #[test]
+    fn test_transforms() {
+        let p = Point3::new(1.0, 0.0, 0.0);
+        assert_close(
+            &Matrix4::rotation_z(90.0).transform_point(&p),
+            &Point3::new(0.0, 1.0, 0.0),
+        );
+        assert_close(
+            &Matrix4::rotation_y(90.0).transform_point(&p),
+            &Point3::new(0.0, 0.0, -1.0),
+        );
+        let m = Matrix4::translation(&Vec3::new(0.0, 5.0, 0.0)) * Matrix4::rotation_x(90.0);
+        assert_close(
+            &m.transform_point(&Point3::new(0.0, 1.0, 0.0)),
+            &Point3::new(0.0, 5.0, 1.0),
+        );
+        assert_close(
+            &m.transform_vector(&Vec3::new(0.0, 1.0, 0.0)),
+            &Vec3::new(0.0, 0.0, 1.0),
+        );
+        let turned = m * Matrix4::rotation_y(20.0) * Matrix4::scaling(&Vec3::new(3.0, 3.0, -3.0));
+        assert!(turned.is_similarity());
+        let squashed = Matrix4::rotation_z(45.0) * Matrix4::scaling(&Vec3::new(1.0, 2.0, 1.0));
+        assert!(!squashed.is_similarity());
+        let mut sheared = Matrix4::identity();
+        sheared.m[0][1] = 0.5;
+        assert!(!sheared.is_similarity());
+    }
+}
@@ -29,47 +29,55 @@ impl ConstantMedium {
     }
 }
 
-impl Hit for ConstantMedium {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+impl ConstantMedium {
+    /// Where the ray scatters inside the medium between `t_min` and `t_max`, drawn at
+    /// random from the distance it travels through it, if it does.
+    fn scatter_t(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
         // let enable_degbug = false;
         // let debugging= enable_degbug && random_f64() < 0.00001;
-        if let Some(mut rec1) = self.boundary.hit(r, -f64::INFINITY, f64::INFINITY) {
-            if let Some(mut rec2) = self.boundary.hit(r, rec1.t + 0.0001, f64::INFINITY) {
-                // if DEBUGGING
-                if rec1.t < t_min {
-                    rec1.t = t_min;
-                }
-                if rec2.t > t_max {
-                    rec2.t = t_max;
-                }
-                if rec1.t >= rec2.t {
-                    return None;
-                }
-                if rec1.t < 0.0 {
-                    rec1.t = 0.0;
-                }
-                let ray_length = r.dir().length();
-                let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-                let hit_distance = self.neg_inv_density * (random_f64().ln());
-                if hit_distance > distance_inside_boundary {
-                    return None;
-                }
-                let tt = rec1.t + hit_distance / ray_length;
-                return Some(HitRecord::new(
-                    r.at(tt),
-                    tt,
-                    0.0,
-                    0.0,
-                    &Vec3::new(1.0, 0.0, 0.0),
-                    r.clone(),
-                    &*self.phase_function,
-                ));
-            } else {
-                None
-            }
-        } else {
-            None
+        let mut rec1 = self.boundary.hit(r, -f64::INFINITY, f64::INFINITY)?;
+        let mut rec2 = self.boundary.hit(r, rec1.t + 0.0001, f64::INFINITY)?;
+        // if DEBUGGING
+        if rec1.t < t_min {
+            rec1.t = t_min;
+        }
+        if rec2.t > t_max {
+            rec2.t = t_max;
+        }
+        if rec1.t >= rec2.t {
+            return None;
+        }
+        if rec1.t < 0.0 {
+            rec1.t = 0.0;
+        }
+        let ray_length = r.dir().length();
+        let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
+        let hit_distance = self.neg_inv_density * (random_f64().ln());
+        if hit_distance > distance_inside_boundary {
+            return None;
         }
+        Some(rec1.t + hit_distance / ray_length)
+    }
+}
+
+impl Hit for ConstantMedium {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let tt = self.scatter_t(r, t_min, t_max)?;
+        Some(HitRecord::new(
+            r.at(tt),
+            tt,
+            0.0,
+            0.0,
+            &Vec3::new(1.0, 0.0, 0.0),
+            r.clone(),
+            &*self.phase_function,
+        ))
+    }
+    /// Draws the scattering distance as `hit` does, so a shadow ray through the medium
+    /// is blocked only some of the time, on average as often as the medium absorbs
+    /// or scatters it away.
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.scatter_t(r, t_min, t_max).is_some()
     }
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AaBb> {
         self.boundary.bounding_box(time0, time1)
@@ -0,0 +1,554 @@
+//! Wavefront OBJ/MTL importer.
+//!
+//! Supports `v`, `vt`, `vn`, `f` (convex polygons are fan-triangulated, negative
+//! indices count from the end), `o`/`g` (every object or group becomes its own
+//! mesh), `mtllib` and `usemtl`. MTL materials are mapped onto the materials of the
+//! renderer:
+//!
+//! * a non-zero `Ke` gives a `DiffuseLight`,
+//! * `d` below 1 or a glass `illum` model (4, 6, 7, 9) gives a `Dielectric` with `Ni`,
+//! * a specular `Ks` stronger than `Kd` (or `illum 3`) gives a `Metal`, with the fuzz
+//!   derived from `Ns`,
+//! * anything else is `Lambertian` with `map_Kd` if present, else `Kd`.
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::texture::ImageTexture;
+use crate::triangle::TriangleMesh;
+use crate::vec3::{Color, Point3, Vec3};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A named part of an OBJ file.
+pub struct ObjObject {
+    pub name: String,
+    pub mesh: TriangleMesh,
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, source } => {
+                write!(f, "cannot read \"{}\": {}", path.display(), source)
+            }
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+fn read(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|source| ObjError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Loads every object of an OBJ file. Faces before any `usemtl` get `default_material`.
+pub fn load<P: AsRef<Path>>(
+    path: P,
+    default_material: Arc<dyn Material>,
+) -> Result<Vec<ObjObject>, ObjError> {
+    let path = path.as_ref();
+    let src = read(path)?;
+    let mut parser = ObjParser::new(path, default_material);
+    for (idx, text) in src.lines().enumerate() {
+        parser.line = idx + 1;
+        parser.parse_line(text)?;
+    }
+    parser.finish_object()?;
+    Ok(parser.objects)
+}
+
+/// Loads the materials of an MTL file by name.
+pub fn load_mtl<P: AsRef<Path>>(path: P) -> Result<HashMap<String, Arc<dyn Material>>, ObjError> {
+    let path = path.as_ref();
+    let src = read(path)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlRecord)> = None;
+    for (idx, text) in src.lines().enumerate() {
+        let err = |message: String| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: idx + 1,
+            message,
+        };
+        let text = strip_comment(text);
+        let mut tokens = text.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+        if keyword == "newmtl" {
+            if let Some((name, record)) = current.take() {
+                materials.insert(name, record.into_material());
+            }
+            if args.is_empty() {
+                return Err(err("`newmtl` needs a name".to_string()));
+            }
+            current = Some((args.join(" "), MtlRecord::default()));
+            continue;
+        }
+        let record = match current.as_mut() {
+            Some((_, record)) => record,
+            None => return Err(err(format!("`{}` before any `newmtl`", keyword))),
+        };
+        match keyword {
+            "Kd" => record.kd = color(&args).map_err(err)?,
+            "Ks" => record.ks = color(&args).map_err(err)?,
+            "Ke" => record.ke = color(&args).map_err(err)?,
+            "Ns" => record.ns = scalar(keyword, &args).map_err(err)?,
+            "Ni" => record.ni = scalar(keyword, &args).map_err(err)?,
+            "d" => record.d = scalar(keyword, &args).map_err(err)?,
+            "Tr" => record.d = 1.0 - scalar(keyword, &args).map_err(err)?,
+            "illum" => record.illum = scalar(keyword, &args).map_err(err)? as i32,
+            "map_Kd" => {
+                // options such as `-s 1 1 1` come first, the file name is last
+                let file = args
+                    .last()
+                    .ok_or_else(|| err("`map_Kd` needs a file name".to_string()))?;
+                let file = base_dir.join(file);
+                let texture = ImageTexture::open(&file).map_err(|e| {
+                    err(format!("cannot load texture \"{}\": {}", file.display(), e))
+                })?;
+                record.map_kd = Some(Arc::new(texture));
+            }
+            // everything else (Ka, map_Bump, ...) has no counterpart in the renderer
+            _ => {}
+        }
+    }
+    if let Some((name, record)) = current.take() {
+        materials.insert(name, record.into_material());
+    }
+    Ok(materials)
+}
+
+fn strip_comment(text: &str) -> &str {
+    match text.find('#') {
+        Some(pos) => &text[..pos],
+        None => text,
+    }
+}
+
+fn number(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(x) if x.is_finite() => Ok(x),
+        _ => Err(format!("expected a number, found `{}`", s)),
+    }
+}
+
+fn scalar(keyword: &str, args: &[&str]) -> Result<f64, String> {
+    match args {
+        [x] => number(x),
+        _ => Err(format!("`{}` takes one number", keyword)),
+    }
+}
+
+fn color(args: &[&str]) -> Result<Color, String> {
+    match args {
+        // `Kd 0.5` means grey
+        [x] => {
+            let x = number(x)?;
+            Ok(Color::new(x, x, x))
+        }
+        [r, g, b] => Ok(Color::new(number(r)?, number(g)?, number(b)?)),
+        _ => Err("expected one or three numbers for a color".to_string()),
+    }
+}
+
+struct MtlRecord {
+    kd: Color,
+    ks: Color,
+    ke: Color,
+    ns: f64,
+    ni: f64,
+    d: f64,
+    illum: i32,
+    map_kd: Option<Arc<ImageTexture>>,
+}
+
+impl Default for MtlRecord {
+    fn default() -> Self {
+        Self {
+            kd: Color::new(0.8, 0.8, 0.8),
+            ks: Color::zero(),
+            ke: Color::zero(),
+            ns: 0.0,
+            ni: 1.5,
+            d: 1.0,
+            illum: 2,
+            map_kd: None,
+        }
+    }
+}
+
+impl MtlRecord {
+    fn into_material(self) -> Arc<dyn Material> {
+        let max = |c: &Color| c.x().max(c.y()).max(c.z());
+        if max(&self.ke) > 0.0 {
+            Arc::new(DiffuseLight::new_color(self.ke))
+        } else if self.d < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            Arc::new(Dielectric::new(self.ni))
+        } else if self.map_kd.is_none()
+            && max(&self.ks) > 0.0
+            && (self.illum == 3 || max(&self.ks) > max(&self.kd))
+        {
+            // the usual conversion from a Phong exponent to a roughness
+            let fuzz = (2.0 / (self.ns.max(0.0) + 2.0)).sqrt();
+            Arc::new(Metal::new(&self.ks, fuzz))
+        } else if let Some(texture) = self.map_kd {
+            Arc::new(Lambertian::new_arc(texture))
+        } else {
+            Arc::new(Lambertian::new_color(self.kd))
+        }
+    }
+}
+
+/// The vertices and faces of the object being read.
+#[derive(Default)]
+struct MeshBuilder {
+    name: String,
+    // (position, texcoord, normal) as indexed in the file -> vertex of the mesh
+    vertex_map: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+    positions: Vec<Point3>,
+    uvs: Vec<Option<(f64, f64)>>,
+    normals: Vec<Option<Vec3>>,
+    indices: Vec<[usize; 3]>,
+    material_ids: Vec<usize>,
+}
+
+struct ObjParser {
+    path: PathBuf,
+    line: usize,
+    positions: Vec<Point3>,
+    texcoords: Vec<(f64, f64)>,
+    normals: Vec<Vec3>,
+    library: HashMap<String, Arc<dyn Material>>,
+    // materials used so far, the default one first
+    materials: Vec<Arc<dyn Material>>,
+    material_index: HashMap<String, usize>,
+    current_material: usize,
+    builder: MeshBuilder,
+    objects: Vec<ObjObject>,
+}
+
+impl ObjParser {
+    fn new(path: &Path, default_material: Arc<dyn Material>) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            line: 0,
+            positions: Vec::new(),
+            texcoords: Vec::new(),
+            normals: Vec::new(),
+            library: HashMap::new(),
+            materials: vec![default_material],
+            material_index: HashMap::new(),
+            current_material: 0,
+            builder: MeshBuilder::default(),
+            objects: Vec::new(),
+        }
+    }
+
+    fn error<S: Into<String>>(&self, message: S) -> ObjError {
+        ObjError::Parse {
+            path: self.path.clone(),
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn parse_line(&mut self, text: &str) -> Result<(), ObjError> {
+        let text = strip_comment(text);
+        let mut tokens = text.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => return Ok(()),
+        };
+        let args: Vec<&str> = tokens.collect();
+        let numbers = |min: usize, max: usize| -> Result<Vec<f64>, ObjError> {
+            if args.len() < min || args.len() > max {
+                return Err(self.error(format!(
+                    "`{}` takes {} to {} numbers, found {}",
+                    keyword,
+                    min,
+                    max,
+                    args.len()
+                )));
+            }
+            args.iter()
+                .map(|s| number(s).map_err(|e| self.error(e)))
+                .collect()
+        };
+        match keyword {
+            "v" => {
+                // an optional w, and some exporters append a vertex color
+                let v = numbers(3, 7)?;
+                self.positions.push(Point3::new(v[0], v[1], v[2]));
+            }
+            "vt" => {
+                let v = numbers(1, 3)?;
+                self.texcoords.push((v[0], *v.get(1).unwrap_or(&0.0)));
+            }
+            "vn" => {
+                let v = numbers(3, 3)?;
+                self.normals.push(Vec3::new(v[0], v[1], v[2]));
+            }
+            "f" => self.face(&args)?,
+            "o" | "g" => {
+                self.finish_object()?;
+                self.builder.name = args.join(" ");
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                self.current_material = match self.material_index.get(&name) {
+                    Some(&id) => id,
+                    None => {
+                        let material =
+                            self.library.get(&name).cloned().ok_or_else(|| {
+                                self.error(format!("unknown material `{}`", name))
+                            })?;
+                        self.materials.push(material);
+                        self.material_index.insert(name, self.materials.len() - 1);
+                        self.materials.len() - 1
+                    }
+                };
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(self.error("`mtllib` needs a file name"));
+                }
+                let base_dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+                for file in args.iter() {
+                    let library = load_mtl(base_dir.join(file))?;
+                    self.library.extend(library);
+                }
+            }
+            // smoothing groups, lines, points and curves are not supported
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Resolves a 1-based (or negative, relative) OBJ index into a buffer of `len` items.
+    fn resolve(&self, token: &str, len: usize, what: &str) -> Result<usize, ObjError> {
+        let index: i64 = token
+            .parse()
+            .map_err(|_| self.error(format!("invalid {} index `{}`", what, token)))?;
+        let resolved = if index > 0 {
+            index - 1
+        } else {
+            len as i64 + index
+        };
+        if index == 0 || resolved < 0 || resolved >= len as i64 {
+            return Err(self.error(format!(
+                "{} index {} out of range, {} defined so far",
+                what, index, len
+            )));
+        }
+        Ok(resolved as usize)
+    }
+
+    fn face(&mut self, args: &[&str]) -> Result<(), ObjError> {
+        if args.len() < 3 {
+            return Err(self.error(format!(
+                "a face needs at least 3 vertices, found {}",
+                args.len()
+            )));
+        }
+        let mut vertices = Vec::with_capacity(args.len());
+        for arg in args {
+            let mut parts = arg.split('/');
+            let v = self.resolve(parts.next().unwrap_or(""), self.positions.len(), "vertex")?;
+            let vt = match parts.next() {
+                Some("") | None => None,
+                Some(t) => Some(self.resolve(t, self.texcoords.len(), "texture coordinate")?),
+            };
+            let vn = match parts.next() {
+                Some("") | None => None,
+                Some(n) => Some(self.resolve(n, self.normals.len(), "normal")?),
+            };
+            if parts.next().is_some() {
+                return Err(self.error(format!("invalid face vertex `{}`", arg)));
+            }
+            vertices.push(self.vertex(v, vt, vn));
+        }
+        for i in 1..vertices.len() - 1 {
+            self.builder
+                .indices
+                .push([vertices[0], vertices[i], vertices[i + 1]]);
+            self.builder.material_ids.push(self.current_material);
+        }
+        Ok(())
+    }
+
+    fn vertex(&mut self, v: usize, vt: Option<usize>, vn: Option<usize>) -> usize {
+        let b = &mut self.builder;
+        if let Some(&index) = b.vertex_map.get(&(v, vt, vn)) {
+            return index;
+        }
+        b.positions.push(self.positions[v]);
+        b.uvs.push(vt.map(|i| self.texcoords[i]));
+        b.normals.push(vn.map(|i| self.normals[i]));
+        b.vertex_map.insert((v, vt, vn), b.positions.len() - 1);
+        b.positions.len() - 1
+    }
+
+    /// Makes a mesh of the faces so far; its errors are reported at the current line.
+    fn finish_object(&mut self) -> Result<(), ObjError> {
+        let b = std::mem::take(&mut self.builder);
+        if b.indices.is_empty() {
+            return Ok(());
+        }
+        let uvs = if b.uvs.iter().any(Option::is_some) {
+            Some(b.uvs.iter().map(|uv| uv.unwrap_or((0.0, 0.0))).collect())
+        } else {
+            None
+        };
+        let normals = if b.normals.iter().any(Option::is_some) {
+            // vertices without a normal of their own get the area-weighted face normals
+            let mut accumulated = vec![Vec3::zero(); b.positions.len()];
+            for &[i0, i1, i2] in b.indices.iter() {
+                let n =
+                    (b.positions[i1] - b.positions[i0]).cross(b.positions[i2] - b.positions[i0]);
+                for i in [i0, i1, i2] {
+                    accumulated[i] += n;
+                }
+            }
+            Some(
+                b.normals
+                    .into_iter()
+                    .zip(accumulated)
+                    .map(|(n, acc)| n.unwrap_or(acc))
+                    .map(|n| {
+                        if n.near_zero() {
+                            Vec3::new(0.0, 1.0, 0.0)
+                        } else {
+                            n.unit_vector()
+                        }
+                    })
+                    .collect(),
+            )
+        } else {
+            None
+        };
+        // only keep the materials this object uses
+        let mut local: HashMap<usize, usize> = HashMap::new();
+        let mut materials = Vec::new();
+        let mut material_ids = Vec::with_capacity(b.material_ids.len());
+        for id in b.material_ids {
+            let local_id = *local.entry(id).or_insert_with(|| {
+                materials.push(self.materials[id].clone());
+                materials.len() - 1
+            });
+            material_ids.push(local_id);
+        }
+        let material_ids = if materials.len() > 1 {
+            Some(material_ids)
+        } else {
+            None
+        };
+        let mesh = TriangleMesh::with_materials(
+            b.positions,
+            normals,
+            uvs,
+            b.indices,
+            materials,
+            material_ids,
+        )
+        .map_err(|e| self.error(e.to_string()))?;
+        self.objects.push(ObjObject { name: b.name, mesh });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hit;
+    use crate::Ray;
+
+    fn write_temp(name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("raytracer-obj-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn default_material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_color(Color::ones()))
+    }
+
+    #[test]
+    fn test_load_obj() {
+        write_temp(
+            "quad.mtl",
+            "newmtl red\nKd 0.8 0.1 0.1\nnewmtl lamp\nKe 10 10 10\n",
+        );
+        let path = write_temp(
+            "quad.obj",
+            "mtllib quad.mtl
+             v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0
+             vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1
+             vn 0 0 1
+             o quad
+             usemtl red
+             f 1/1/1 2/2/1 3/3/1 4/4/1
+             o lamp
+             usemtl lamp
+             f -4 -3 -2
+            ",
+        );
+        let objects = load(&path, default_material()).unwrap();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0].name, "quad");
+        assert_eq!(objects[0].mesh.triangle_count(), 2);
+        assert_eq!(objects[1].mesh.triangle_count(), 1);
+        let r = Ray::new(Point3::new(0.5, 0.75, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = objects[0].mesh.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.u - 0.5).abs() < 1e-12 && (rec.v - 0.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_malformed_obj() {
+        let path = write_temp("bad_index.obj", "v 0 0 0\nv 1 0 0\nf 1 2 3\n");
+        match load(&path, default_material()) {
+            Err(ObjError::Parse { line, message, .. }) => {
+                assert_eq!(line, 3);
+                assert!(message.contains("out of range"));
+            }
+            _ => panic!("expected a parse error"),
+        }
+        let path = write_temp("bad_number.obj", "v 0 zero 0\n");
+        assert!(matches!(
+            load(&path, default_material()),
+            Err(ObjError::Parse { line: 1, .. })
+        ));
+        let path = write_temp("bad_material.obj", "v 0 0 0\nusemtl nothing\n");
+        assert!(matches!(
+            load(&path, default_material()),
+            Err(ObjError::Parse { line: 2, .. })
+        ));
+    }
+}
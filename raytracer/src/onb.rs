@@ -6,17 +6,17 @@ pub struct Onb {
 }
 impl Onb {
     pub fn u(&self) -> Vec3 {
-        self.axis[0].clone()
+        self.axis[0]
     }
     pub fn v(&self) -> Vec3 {
-        self.axis[1].clone()
+        self.axis[1]
     }
     pub fn w(&self) -> Vec3 {
-        self.axis[2].clone()
-    }
-    pub fn local(&self, a: f64, b: f64, c: f64) -> Vec3 {
-        a * self.u() + b * self.v() + c * self.w()
+        self.axis[2]
     }
+    // pub fn local(&self, a: f64, b: f64, c: f64) -> Vec3 {
+    //     a * self.u() + b * self.v() + c * self.w()
+    // }
     pub fn local_vector(&self, a: &Vec3) -> Vec3 {
         a.x() * self.u() + a.y() * self.v() + a.z() * self.w()
     }
@@ -0,0 +1,427 @@
+//! Writing films to image files: PFM, Radiance RGBE and OpenEXR keep their range,
+//! while binary PPM is sRGB-encoded like the formats written through `image`.
+use crate::film::Film;
+use flate2::write::ZlibEncoder;
+use std::io::{self, Write};
+
+/// Writes `film` as a little-endian colour PFM, 32-bit floats from the bottom row up.
+pub fn write_pfm<W: Write>(film: &Film, mut w: W) -> io::Result<()> {
+    write!(w, "PF\n{} {}\n-1.0\n", film.width(), film.height())?;
+    let row = 3 * film.width();
+    let mut bytes = Vec::with_capacity(4 * row);
+    for line in film.data().chunks(row).rev() {
+        bytes.clear();
+        bytes.extend(line.iter().flat_map(|v| v.to_le_bytes()));
+        w.write_all(&bytes)?;
+    }
+    w.flush()
+}
+
+/// Writes `film` as a binary PPM with 8 or 16 bits per channel, sRGB-encoded.
+pub fn write_ppm<W: Write>(film: &Film, mut w: W, bit_depth: u8, dither: bool) -> io::Result<()> {
+    let (width, height) = (film.width(), film.height());
+    let bytes = match bit_depth {
+        8 => film.to_rgb8(dither).into_raw(),
+        // most significant byte first
+        16 => film
+            .to_rgb16(dither)
+            .into_raw()
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect(),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "PPM takes 8 or 16 bits per channel",
+            ))
+        }
+    };
+    let max = (1_u32 << bit_depth) - 1;
+    write!(w, "P6\n{} {}\n{}\n", width, height, max)?;
+    w.write_all(&bytes)?;
+    w.flush()
+}
+
+/// Writes `film` as a Radiance `.hdr` file: a shared 8-bit exponent per pixel (RGBE),
+/// with the scanlines run-length encoded where the format allows it.
+pub fn write_rgbe<W: Write>(film: &Film, mut w: W) -> io::Result<()> {
+    let (width, height) = (film.width(), film.height());
+    write!(
+        w,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        height, width
+    )?;
+    let rle = (8..0x8000).contains(&width);
+    let mut line = Vec::with_capacity(4 * width);
+    let mut bytes = Vec::with_capacity(4 * width + 4);
+    for row in film.data().chunks(3 * width) {
+        line.clear();
+        line.extend(row.chunks(3).flat_map(|c| rgbe(c[0], c[1], c[2])));
+        bytes.clear();
+        if rle {
+            bytes.extend([2, 2, (width >> 8) as u8, width as u8]);
+            // the four components one after the other, each run-length encoded
+            for component in 0..4 {
+                let values: Vec<u8> = line.iter().skip(component).step_by(4).copied().collect();
+                encode_runs(&values, &mut bytes);
+            }
+        } else {
+            bytes.extend_from_slice(&line);
+        }
+        w.write_all(&bytes)?;
+    }
+    w.flush()
+}
+
+/// A colour as three mantissas and their shared exponent. Negative and non-finite
+/// components, which RGBE cannot hold, become 0.
+fn rgbe(r: f32, g: f32, b: f32) -> [u8; 4] {
+    let [r, g, b] = [r, g, b].map(|c| if c.is_finite() { c.max(0.0) } else { 0.0 });
+    let v = r.max(g).max(b);
+    if v < 1e-32 {
+        return [0; 4];
+    }
+    // v = m * 2^e with m in [0.5, 1)
+    let mut e = v.log2().floor() as i32 + 1;
+    if v / 2f32.powi(e) >= 1.0 {
+        e += 1;
+    }
+    let scale = 256.0 / 2f32.powi(e);
+    [
+        (r * scale) as u8,
+        (g * scale) as u8,
+        (b * scale) as u8,
+        (e + 128).clamp(0, 255) as u8,
+    ]
+}
+
+/// Appends `values` with runs of at least four equal bytes as (128 + length, value)
+/// and everything else as (length, values...), after Greg Ward's encoder.
+fn encode_runs(values: &[u8], out: &mut Vec<u8>) {
+    const MIN_RUN: usize = 4;
+    let n = values.len();
+    let mut cur = 0;
+    while cur < n {
+        // find the next run long enough to be worth it
+        let (mut begin, mut run, mut old_run) = (cur, 0, 0);
+        while run < MIN_RUN && begin < n {
+            begin += run;
+            old_run = run;
+            run = 1;
+            while begin + run < n && run < 127 && values[begin] == values[begin + run] {
+                run += 1;
+            }
+        }
+        // a short run right before it is still shorter as a run
+        if old_run > 1 && old_run == begin - cur {
+            out.extend([128 + old_run as u8, values[cur]]);
+            cur = begin;
+        }
+        while cur < begin {
+            let count = (begin - cur).min(128);
+            out.push(count as u8);
+            out.extend_from_slice(&values[cur..cur + count]);
+            cur += count;
+        }
+        if run >= MIN_RUN {
+            out.extend([128 + run as u8, values[begin]]);
+            cur += run;
+        }
+    }
+}
+
+/// How the scanlines of an OpenEXR file are stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExrCompression {
+    None,
+    /// zlib over blocks of 16 scanlines, after the byte reordering and delta
+    /// predictor of the format. Lossless.
+    Zip,
+}
+
+impl ExrCompression {
+    fn code(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Zip => 3,
+        }
+    }
+    fn lines_per_block(self) -> usize {
+        match self {
+            Self::None => 1,
+            Self::Zip => 16,
+        }
+    }
+}
+
+/// Writes `film` as a scanline OpenEXR file with 32-bit float `R`, `G` and `B` channels.
+pub fn write_exr<W: Write>(film: &Film, w: W, compression: ExrCompression) -> io::Result<()> {
+    write_exr_layers(film, &[], w, compression)
+}
+
+/// Like [`write_exr`], with the extra `layers` next to the channels of `film`: channels
+/// named like `normal.X`, of a float per pixel stored row by row from the top.
+pub fn write_exr_layers<W: Write>(
+    film: &Film,
+    layers: &[(String, Vec<f32>)],
+    w: W,
+    compression: ExrCompression,
+) -> io::Result<()> {
+    let (width, height) = (film.width(), film.height());
+    let data = film.data();
+    let channel = |c: usize| data.iter().skip(c).step_by(3).copied().collect::<Vec<_>>();
+    let mut channels = vec![("B", channel(2)), ("G", channel(1)), ("R", channel(0))];
+    for (name, values) in layers {
+        assert_eq!(values.len(), width * height, "layer of a different size");
+        channels.push((name, values.clone()));
+    }
+    // the channels of an OpenEXR file are sorted by name
+    channels.sort_by(|a, b| a.0.cmp(b.0));
+    write_exr_channels(w, width, height, &channels, compression)
+}
+
+/// Writes the named `channels`, `width * height` floats each stored row by row from
+/// the top, as a single-part scanline OpenEXR file. The names must be sorted.
+fn write_exr_channels<W: Write>(
+    mut w: W,
+    width: usize,
+    height: usize,
+    channels: &[(&str, Vec<f32>)],
+    compression: ExrCompression,
+) -> io::Result<()> {
+    debug_assert!(channels.windows(2).all(|c| c[0].0 < c[1].0));
+    let mut header = Vec::new();
+    header.extend(0x0131_2f76_u32.to_le_bytes());
+    header.extend(2_u32.to_le_bytes());
+    let mut list = Vec::new();
+    for (name, _) in channels {
+        list.extend(name.as_bytes());
+        list.push(0);
+        // FLOAT, not perceptually linear, reserved, x and y sampling
+        list.extend(2_i32.to_le_bytes());
+        list.extend([0, 0, 0, 0]);
+        list.extend(1_i32.to_le_bytes());
+        list.extend(1_i32.to_le_bytes());
+    }
+    list.push(0);
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    attribute(&mut header, "channels", "chlist", &list);
+    attribute(
+        &mut header,
+        "compression",
+        "compression",
+        &[compression.code()],
+    );
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    // increasing y
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1_f32.to_le_bytes(),
+    );
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1_f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    let lines = compression.lines_per_block();
+    let mut chunks = Vec::new();
+    for first in (0..height).step_by(lines) {
+        let mut raw = Vec::with_capacity(4 * width * lines * channels.len());
+        for y in first..(first + lines).min(height) {
+            for (_, values) in channels {
+                let row = &values[y * width..(y + 1) * width];
+                raw.extend(row.iter().flat_map(|v| v.to_le_bytes()));
+            }
+        }
+        let data = match compression {
+            ExrCompression::None => raw,
+            ExrCompression::Zip => {
+                // readers take a block that did not shrink as it is
+                let packed = zip(&raw)?;
+                if packed.len() < raw.len() {
+                    packed
+                } else {
+                    raw
+                }
+            }
+        };
+        let mut chunk = Vec::with_capacity(data.len() + 8);
+        chunk.extend((first as i32).to_le_bytes());
+        chunk.extend((data.len() as i32).to_le_bytes());
+        chunk.extend(data);
+        chunks.push(chunk);
+    }
+
+    // the offset table points at every chunk from the start of the file
+    let mut offset = (header.len() + 8 * chunks.len()) as u64;
+    for chunk in &chunks {
+        header.extend(offset.to_le_bytes());
+        offset += chunk.len() as u64;
+    }
+    w.write_all(&header)?;
+    for chunk in &chunks {
+        w.write_all(chunk)?;
+    }
+    w.flush()
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend(name.as_bytes());
+    header.push(0);
+    header.extend(kind.as_bytes());
+    header.push(0);
+    header.extend((value.len() as i32).to_le_bytes());
+    header.extend(value);
+}
+
+/// The ZIP compression of OpenEXR: the even bytes, then the odd ones, as differences
+/// from the byte before, deflated.
+fn zip(raw: &[u8]) -> io::Result<Vec<u8>> {
+    let mut bytes: Vec<u8> = raw
+        .iter()
+        .step_by(2)
+        .chain(raw.iter().skip(1).step_by(2))
+        .copied()
+        .collect();
+    let mut previous = bytes[0];
+    for b in &mut bytes[1..] {
+        let current = *b;
+        *b = current.wrapping_sub(previous).wrapping_add(128);
+        previous = current;
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&bytes)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Color;
+    use image::codecs::hdr::HdrDecoder;
+    use image::ImageFormat;
+
+    /// A film with smooth areas, values far above 1 and a few that RGBE cannot keep.
+    fn test_film() -> Film {
+        let mut film = Film::new(37, 21);
+        for y in 0..21 {
+            for x in 0..37 {
+                let v = if x < 20 { 0.25 } else { (x * y) as f64 * 1.7 };
+                film.set_pixel(x, y, &Color::new(v, 0.5 * v + y as f64, 1e-3 * x as f64));
+            }
+        }
+        film.set_pixel(3, 4, &Color::new(1e5, -2.0, 0.0));
+        film
+    }
+
+    #[test]
+    fn test_exr_reads_back() {
+        let film = test_film();
+        for compression in [ExrCompression::None, ExrCompression::Zip] {
+            let mut bytes = Vec::new();
+            write_exr(&film, &mut bytes, compression).unwrap();
+            let image = image::load_from_memory_with_format(&bytes, ImageFormat::OpenExr)
+                .unwrap()
+                .to_rgb32f();
+            assert_eq!((image.width(), image.height()), (37, 21));
+            assert!(image.as_raw() == film.data(), "{:?} differs", compression);
+        }
+    }
+
+    #[test]
+    fn test_exr_layers() {
+        let film = test_film();
+        let depth: Vec<f32> = (0..37 * 21).map(|i| i as f32 * 0.5).collect();
+        let layers = [
+            ("normal.X".to_string(), vec![-1.0; 37 * 21]),
+            ("depth.Z".to_string(), depth),
+        ];
+        let mut bytes = Vec::new();
+        write_exr_layers(&film, &layers, &mut bytes, ExrCompression::Zip).unwrap();
+        // sorted, with the beauty first
+        let header = String::from_utf8_lossy(&bytes[..200]);
+        let names: Vec<_> = ["B", "G", "R", "depth.Z", "normal.X"]
+            .iter()
+            .map(|name| header.find(&format!("\0{}\0", name)).unwrap())
+            .collect();
+        assert!(names.windows(2).all(|n| n[0] < n[1]), "{:?}", names);
+        // readers that only know RGB still find the beauty
+        let image = image::load_from_memory_with_format(&bytes, ImageFormat::OpenExr)
+            .unwrap()
+            .to_rgb32f();
+        assert!(image.as_raw() == film.data());
+    }
+
+    #[test]
+    fn test_rgbe_reads_back() {
+        let film = test_film();
+        let mut bytes = Vec::new();
+        write_rgbe(&film, &mut bytes).unwrap();
+        // width 37 is run-length encoded, width 5 is not
+        let mut small = Film::new(5, 2);
+        small.set_pixel(1, 1, &Color::new(3.0, 0.1, 7.5));
+        let mut small_bytes = Vec::new();
+        write_rgbe(&small, &mut small_bytes).unwrap();
+        for (film, bytes) in [(film, bytes), (small, small_bytes)] {
+            // as floats, the generic loader maps them to 8 bits
+            let pixels = HdrDecoder::new(&bytes[..])
+                .unwrap()
+                .read_image_hdr()
+                .unwrap();
+            assert_eq!(pixels.len(), film.width() * film.height());
+            for (a, b) in pixels.iter().zip(film.data().chunks(3)) {
+                // 8 bits of mantissa relative to the largest component of the pixel
+                let b = b.iter().map(|c| c.max(0.0)).collect::<Vec<_>>();
+                let largest = b.iter().fold(0.0_f32, |m, &c| m.max(c));
+                for (a, b) in a.0.iter().zip(b) {
+                    assert!((a - b).abs() <= largest / 128.0, "{} for {}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_ppm_reads_back() {
+        let film = test_film();
+        let mut bytes = Vec::new();
+        write_ppm(&film, &mut bytes, 8, false).unwrap();
+        let image = image::load_from_memory_with_format(&bytes, ImageFormat::Pnm)
+            .unwrap()
+            .to_rgb8();
+        assert!(image == film.to_rgb8(false));
+        let mut bytes = Vec::new();
+        write_ppm(&film, &mut bytes, 16, true).unwrap();
+        let image = image::load_from_memory_with_format(&bytes, ImageFormat::Pnm)
+            .unwrap()
+            .to_rgb16();
+        assert!(image == film.to_rgb16(true));
+    }
+
+    #[test]
+    fn test_pfm_layout() {
+        let film = test_film();
+        let mut bytes = Vec::new();
+        write_pfm(&film, &mut bytes).unwrap();
+        let header = b"PF\n37 21\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(bytes.len(), header.len() + 37 * 21 * 12);
+        // the first row in the file is the bottom one
+        let first = f32::from_le_bytes(
+            bytes[header.len() + 4..header.len() + 8]
+                .try_into()
+                .unwrap(),
+        );
+        assert_eq!(first, film.pixel(0, 20).y() as f32);
+    }
+}
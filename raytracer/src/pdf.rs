@@ -1,10 +1,9 @@
 use crate::hittable::Hit;
 use crate::onb::Onb;
-use crate::rtweekend::random_f64;
-use crate::vec3::{random_cosine_direction, Point3};
+use crate::sampler::sample_1d;
+use crate::vec3::{random_cosine_direction, random_unit_vector, Point3};
 use crate::Vec3;
 use std::f64::consts::PI;
-use std::sync::Arc;
 
 pub trait Pdf: Send + Sync {
     fn value(&self, direction: &Vec3) -> f64;
@@ -37,17 +36,31 @@ impl Pdf for CosinePdf {
     }
 }
 
-pub struct HittablePdf {
+/// Uniform over all directions, for isotropic media.
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+    fn generate(&self) -> Vec3 {
+        random_unit_vector()
+    }
+}
+
+/// Directions from `o` towards an object, borrowed so that building one for every
+/// bounce costs no allocation.
+pub struct HittablePdf<'a> {
     o: Point3,
-    ptr: Arc<dyn Hit>,
+    ptr: &'a dyn Hit,
 }
 
-impl HittablePdf {
-    pub fn new(ptr: Arc<dyn Hit>, o: Point3) -> Self {
+impl<'a> HittablePdf<'a> {
+    pub fn new(ptr: &'a dyn Hit, o: Point3) -> Self {
         Self { ptr, o }
     }
 }
-impl Pdf for HittablePdf {
+impl Pdf for HittablePdf<'_> {
     fn value(&self, direction: &Vec3) -> f64 {
         self.ptr.pdf_value(&self.o, direction)
     }
@@ -56,23 +69,23 @@ impl Pdf for HittablePdf {
     }
 }
 
-pub struct MixturePdf {
-    p: Vec<Arc<dyn Pdf>>,
+/// An even mix of two pdfs.
+pub struct MixturePdf<'a> {
+    p: [&'a dyn Pdf; 2],
 }
 
-impl MixturePdf {
-    pub fn new(p0: Arc<dyn Pdf>, p1: Arc<dyn Pdf>) -> Self {
-        let p = vec![p0, p1];
-        Self { p }
+impl<'a> MixturePdf<'a> {
+    pub fn new(p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> Self {
+        Self { p: [p0, p1] }
     }
 }
 
-impl Pdf for MixturePdf {
+impl Pdf for MixturePdf<'_> {
     fn value(&self, direction: &Vec3) -> f64 {
         0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
     }
     fn generate(&self) -> Vec3 {
-        if random_f64() < 0.5 {
+        if sample_1d() < 0.5 {
             self.p[0].generate()
         } else {
             self.p[1].generate()
@@ -12,6 +12,12 @@ pub struct Perlin {
     perm_z: Vec<i32>,
 }
 
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Perlin {
     pub fn new() -> Self {
         // let mut ranfloat = Vec::new();
@@ -52,8 +58,7 @@ impl Perlin {
                     *value3 = self.ranvec[(self.perm_x[((i + di as i32) & 255) as usize]
                         ^ self.perm_y[((j + dj as i32) & 255) as usize]
                         ^ self.perm_z[((k + dk as i32) & 255) as usize])
-                        as usize]
-                        .clone();
+                        as usize];
                 }
             }
         }
@@ -109,7 +114,7 @@ impl Perlin {
     }
     pub fn turb(&self, p: &Point3, depth: i32) -> f64 {
         let mut accum = 0.0;
-        let mut tmp_p = p.clone();
+        let mut tmp_p = *p;
         let mut weight = 1.0;
         for _ in 0..depth {
             accum += weight * self.noise(&tmp_p);
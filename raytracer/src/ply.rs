@@ -0,0 +1,551 @@
+//! Stanford PLY importer, for scanned meshes such as the bunny, the dragon or Lucy.
+//!
+//! Reads `ascii`, `binary_little_endian` and `binary_big_endian` bodies. From the
+//! `vertex` element it uses `x`, `y`, `z`, the normals `nx`, `ny`, `nz`, the texture
+//! coordinates `u`, `v` (or `s`, `t`, `texture_u`, `texture_v`) and the colors `red`,
+//! `green`, `blue`; from the `face` element the list `vertex_indices` (or
+//! `vertex_index`), fan-triangulated. Other properties and elements are skipped.
+use crate::material::{Lambertian, Material};
+use crate::triangle::{MeshError, TriangleMesh};
+use crate::vec3::{Color, Point3, Vec3};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum PlyError {
+    Io { path: PathBuf, source: io::Error },
+    Invalid { path: PathBuf, message: String },
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlyError::Io { path, source } => {
+                write!(f, "cannot read \"{}\": {}", path.display(), source)
+            }
+            PlyError::Invalid { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for PlyError {}
+
+/// The vertex attributes and triangles of a PLY file.
+pub struct PlyMesh {
+    pub positions: Vec<Point3>,
+    pub normals: Option<Vec<Vec3>>,
+    pub uvs: Option<Vec<(f64, f64)>>,
+    /// Vertex colors scaled to `0..=1`.
+    pub colors: Option<Vec<Color>>,
+    pub indices: Vec<[usize; 3]>,
+    /// The file, for the errors of the meshes made of it.
+    path: PathBuf,
+}
+
+impl PlyMesh {
+    /// A mesh made of `mp`, ignoring the vertex colors.
+    pub fn into_mesh(self, mp: Arc<dyn Material>) -> Result<TriangleMesh, PlyError> {
+        let path = self.path;
+        TriangleMesh::new(self.positions, self.normals, self.uvs, self.indices, mp)
+            .map_err(|e| mesh_error(path, e))
+    }
+
+    /// A white `Lambertian` mesh colored by its vertex colors, blended across every
+    /// triangle, or `None` if the file has none.
+    pub fn into_vertex_color_mesh(self) -> Result<Option<TriangleMesh>, PlyError> {
+        let colors = match self.colors {
+            Some(colors) => colors,
+            None => return Ok(None),
+        };
+        let path = self.path;
+        TriangleMesh::with_vertex_colors(
+            self.positions,
+            self.normals,
+            self.uvs,
+            colors,
+            self.indices,
+            Arc::new(Lambertian::new_color(Color::ones())),
+        )
+        .map(Some)
+        .map_err(|e| mesh_error(path, e))
+    }
+}
+
+fn mesh_error(path: PathBuf, e: MeshError) -> PlyError {
+    PlyError::Invalid {
+        path,
+        message: e.to_string(),
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<PlyMesh, PlyError> {
+    let path = path.as_ref();
+    let data = fs::read(path).map_err(|source| PlyError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut mesh = parse(&data).map_err(|message| PlyError::Invalid {
+        path: path.to_path_buf(),
+        message,
+    })?;
+    mesh.path = path.to_path_buf();
+    Ok(mesh)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(format!("unknown property type `{}`", name)),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    /// The value that means "full intensity" for a color of this type.
+    fn color_scale(self) -> f64 {
+        match self {
+            Scalar::U8 => 255.0,
+            Scalar::U16 => 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+enum PropertyKind {
+    Scalar(Scalar),
+    // (type of the count, type of the items)
+    List(Scalar, Scalar),
+}
+
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+}
+
+/// Splits off the header, returning it and the offset of the body.
+fn parse_header(data: &[u8]) -> Result<(Header, usize), String> {
+    let mut pos = 0;
+    let mut next_line = || -> Option<&str> {
+        if pos >= data.len() {
+            return None;
+        }
+        let end = data[pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(data.len(), |i| pos + i + 1);
+        let line = std::str::from_utf8(&data[pos..end]).unwrap_or("");
+        pos = end;
+        Some(line.trim())
+    };
+    match next_line() {
+        Some("ply") => {}
+        _ => return Err("not a PLY file, it does not start with `ply`".to_string()),
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line_number in 2.. {
+        let line = next_line().ok_or_else(|| "the header has no `end_header`".to_string())?;
+        let err = |message: String| format!("header line {}: {}", line_number, message);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["end_header"] => break,
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    other => return Err(err(format!("unknown format `{}`", other))),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| err(format!("invalid element count `{}`", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_type, item_type, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| err("`property` before any `element`".to_string()))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind: PropertyKind::List(
+                        Scalar::parse(count_type).map_err(err)?,
+                        Scalar::parse(item_type).map_err(err)?,
+                    ),
+                });
+            }
+            ["property", ty, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| err("`property` before any `element`".to_string()))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind: PropertyKind::Scalar(Scalar::parse(ty).map_err(err)?),
+                });
+            }
+            _ => return Err(err(format!("cannot parse `{}`", line))),
+        }
+    }
+    let format = format.ok_or_else(|| "the header has no `format`".to_string())?;
+    Ok((Header { format, elements }, pos))
+}
+
+/// Reads the values of the body one at a time, whatever its encoding.
+struct Body<'a> {
+    data: &'a [u8],
+    pos: usize,
+    format: Format,
+}
+
+impl Body<'_> {
+    fn value(&mut self, ty: Scalar) -> Result<f64, String> {
+        if self.format == Format::Ascii {
+            return self.ascii_value();
+        }
+        let size = ty.size();
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + size)
+            .ok_or_else(|| "the file ends in the middle of the data".to_string())?;
+        self.pos += size;
+        let mut buf = [0u8; 8];
+        buf[..size].copy_from_slice(bytes);
+        if self.format == Format::BinaryBigEndian {
+            buf[..size].reverse();
+        }
+        // `buf` is little endian from here on
+        let x = match ty {
+            Scalar::I8 => buf[0] as i8 as f64,
+            Scalar::U8 => buf[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(buf),
+        };
+        if !x.is_finite() {
+            return Err(format!("invalid value {}", x));
+        }
+        Ok(x)
+    }
+
+    fn ascii_value(&mut self) -> Result<f64, String> {
+        while self.pos < self.data.len() && self.data[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        let start = self.pos;
+        while self.pos < self.data.len() && !self.data[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err("the file ends in the middle of the data".to_string());
+        }
+        let token = String::from_utf8_lossy(&self.data[start..self.pos]);
+        match token.parse::<f64>() {
+            Ok(x) if x.is_finite() => Ok(x),
+            _ => Err(format!("expected a number, found `{}`", token)),
+        }
+    }
+
+    fn index(&mut self, ty: Scalar) -> Result<usize, String> {
+        let x = self.value(ty)?;
+        if x < 0.0 || x.fract() != 0.0 {
+            return Err(format!("invalid index or count {}", x));
+        }
+        Ok(x as usize)
+    }
+}
+
+fn find(element: &Element, names: &[&str]) -> Option<usize> {
+    element
+        .properties
+        .iter()
+        .position(|p| names.contains(&p.name.as_str()))
+}
+
+fn parse(data: &[u8]) -> Result<PlyMesh, String> {
+    let (header, start) = parse_header(data)?;
+    let mut body = Body {
+        data,
+        pos: start,
+        format: header.format,
+    };
+    let mut mesh = PlyMesh {
+        positions: Vec::new(),
+        normals: None,
+        uvs: None,
+        colors: None,
+        indices: Vec::new(),
+        path: PathBuf::new(),
+    };
+    let mut values = Vec::new();
+    let mut list = Vec::new();
+    for element in header.elements.iter() {
+        let is_vertex = element.name == "vertex";
+        let is_face = element.name == "face";
+        // property slots of the attributes we use
+        let slot = |names: &[&str]| find(element, names);
+        let position = [slot(&["x"]), slot(&["y"]), slot(&["z"])];
+        let normal = [slot(&["nx"]), slot(&["ny"]), slot(&["nz"])];
+        let uv = [
+            slot(&["u", "s", "texture_u", "texture_s"]),
+            slot(&["v", "t", "texture_v", "texture_t"]),
+        ];
+        let color = [
+            slot(&["red", "diffuse_red", "r"]),
+            slot(&["green", "diffuse_green", "g"]),
+            slot(&["blue", "diffuse_blue", "b"]),
+        ];
+        let face_list = slot(&["vertex_indices", "vertex_index"]);
+        if is_vertex {
+            if position.iter().any(Option::is_none) {
+                return Err("the vertices have no `x`, `y` and `z`".to_string());
+            }
+            // every vertex takes at least a byte, so a count beyond the bytes left is a
+            // broken header, which the reads below report
+            let capacity = element.count.min(body.data.len() - body.pos);
+            mesh.positions.reserve(capacity);
+            if normal.iter().all(Option::is_some) {
+                mesh.normals = Some(Vec::with_capacity(capacity));
+            }
+            if uv.iter().all(Option::is_some) {
+                mesh.uvs = Some(Vec::with_capacity(capacity));
+            }
+            if color.iter().all(Option::is_some) {
+                mesh.colors = Some(Vec::with_capacity(capacity));
+            }
+        }
+        if is_face && face_list.is_none() {
+            return Err("the faces have no `vertex_indices`".to_string());
+        }
+        for item in 0..element.count {
+            values.clear();
+            for (slot, property) in element.properties.iter().enumerate() {
+                match property.kind {
+                    PropertyKind::Scalar(ty) => values.push(body.value(ty)?),
+                    PropertyKind::List(count_type, item_type) => {
+                        values.push(0.0);
+                        let count = body.index(count_type)?;
+                        if Some(slot) != face_list || !is_face {
+                            for _ in 0..count {
+                                body.value(item_type)?;
+                            }
+                            continue;
+                        }
+                        list.clear();
+                        for _ in 0..count {
+                            list.push(body.index(item_type)?);
+                        }
+                    }
+                }
+            }
+            let get = |slot: Option<usize>| values[slot.unwrap()];
+            if is_vertex {
+                mesh.positions.push(Point3::new(
+                    get(position[0]),
+                    get(position[1]),
+                    get(position[2]),
+                ));
+                if let Some(normals) = mesh.normals.as_mut() {
+                    let n = Vec3::new(get(normal[0]), get(normal[1]), get(normal[2]));
+                    normals.push(if n.near_zero() { n } else { n.unit_vector() });
+                }
+                if let Some(uvs) = mesh.uvs.as_mut() {
+                    uvs.push((get(uv[0]), get(uv[1])));
+                }
+                if let Some(colors) = mesh.colors.as_mut() {
+                    let channel = |i: usize| {
+                        let scale = match element.properties[color[i].unwrap()].kind {
+                            PropertyKind::Scalar(ty) => ty.color_scale(),
+                            PropertyKind::List(..) => 1.0,
+                        };
+                        get(color[i]) / scale
+                    };
+                    colors.push(Color::new(channel(0), channel(1), channel(2)));
+                }
+            } else if is_face {
+                if list.len() < 3 {
+                    return Err(format!(
+                        "face {} has {} vertices, at least 3 are needed",
+                        item,
+                        list.len()
+                    ));
+                }
+                for i in 1..list.len() - 1 {
+                    mesh.indices.push([list[0], list[i], list[i + 1]]);
+                }
+            }
+        }
+    }
+    let n = mesh.positions.len();
+    if let Some(i) = mesh.indices.iter().flatten().find(|&&i| i >= n) {
+        return Err(format!(
+            "vertex index {} out of range, the file has {} vertices",
+            i, n
+        ));
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "ply
+format FORMAT 1.0
+comment a unit quad
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian {
+            "binary_big_endian"
+        } else {
+            "binary_little_endian"
+        };
+        let mut data = HEADER.replace("FORMAT", format).into_bytes();
+        let corners = [[0.0f32, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        for (i, [x, y]) in corners.iter().enumerate() {
+            for c in [*x, *y, 0.0] {
+                data.extend(if big_endian {
+                    c.to_be_bytes()
+                } else {
+                    c.to_le_bytes()
+                });
+            }
+            data.extend([255, 0, i as u8 * 85]);
+        }
+        data.push(4);
+        for i in 0..4i32 {
+            data.extend(if big_endian {
+                i.to_be_bytes()
+            } else {
+                i.to_le_bytes()
+            });
+        }
+        data
+    }
+
+    #[test]
+    fn test_formats_agree() {
+        let ascii = HEADER.replace("FORMAT", "ascii")
+            + "0 0 0 255 0 0\n1 0 0 255 0 85\n1 1 0 255 0 170\n0 1 0 255 0 255\n4 0 1 2 3\n";
+        for data in [ascii.into_bytes(), binary(false), binary(true)] {
+            let mesh = parse(&data).unwrap();
+            assert_eq!(mesh.positions[2], Point3::new(1.0, 1.0, 0.0));
+            assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+            assert!(mesh.normals.is_none() && mesh.uvs.is_none());
+            assert_eq!(mesh.colors.as_ref().unwrap()[3], Color::new(1.0, 0.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn test_vertex_colors() {
+        use crate::hittable::Hit;
+        use crate::Ray;
+        let data = HEADER.replace("FORMAT", "ascii")
+            + "0 0 0 255 0 0\n1 0 0 255 0 85\n1 1 0 255 0 170\n0 1 0 255 0 255\n4 0 1 2 3\n";
+        let mesh = parse(data.as_bytes())
+            .unwrap()
+            .into_vertex_color_mesh()
+            .unwrap()
+            .unwrap();
+        let hit = |x, y| {
+            let r = Ray::new(Point3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            let rec = mesh.hit(&r, 0.001, f64::INFINITY).unwrap();
+            (rec.color.unwrap(), rec.material.address())
+        };
+        // one in each triangle, blue blended from the corners
+        let (a, first) = hit(0.5, 0.25);
+        let (b, second) = hit(0.25, 0.5);
+        assert!((a.z() - 0.25).abs() < 1e-6 && (b.z() - 5.0 / 12.0).abs() < 1e-6);
+        assert!((a.x() - 1.0).abs() < 1e-6);
+        assert_eq!(first, second);
+    }
+
+    fn error(data: &[u8]) -> String {
+        parse(data).err().expect("expected an error")
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert!(parse(b"solid cube\n").is_err());
+        let truncated = binary(false);
+        assert!(error(&truncated[..truncated.len() - 2]).contains("ends"));
+        let bad_index = HEADER.replace("FORMAT", "ascii")
+            + "0 0 0 0 0 0\n1 0 0 0 0 0\n1 1 0 0 0 0\n0 1 0 0 0 0\n3 0 1 7\n";
+        assert!(error(bad_index.as_bytes()).contains("out of range"));
+        let huge = HEADER
+            .replace("FORMAT", "ascii")
+            .replace("element vertex 4", "element vertex 4000000000000");
+        assert!(error(huge.as_bytes()).contains("ends"));
+        // read, then broken, so that it is not a mesh
+        let mut mesh = parse(&binary(false)).unwrap();
+        mesh.positions[1] = Point3::new(f64::NAN, 0.0, 0.0);
+        match mesh.into_vertex_color_mesh() {
+            Err(PlyError::Invalid { message, .. }) => assert!(message.contains("vertex 1")),
+            _ => panic!("expected an invalid mesh"),
+        }
+    }
+}
@@ -10,15 +10,15 @@ impl Ray {
         Ray { orig, dir, tm }
     }
     pub fn orig(&self) -> Vec3 {
-        self.orig.clone()
+        self.orig
     }
     pub fn dir(&self) -> Vec3 {
-        self.dir.clone()
+        self.dir
     }
     pub fn time(&self) -> f64 {
         self.tm
     }
     pub fn at(&self, t: f64) -> Vec3 {
-        self.orig.clone() + t * self.dir.clone()
+        self.orig + t * self.dir
     }
 }
@@ -0,0 +1,696 @@
+use crate::aov::{AovSamples, Aovs};
+use crate::film::{Film, SampleFilm, Splats};
+use crate::filter::Filter;
+use crate::hittable::Hit;
+use crate::hittable_list::HittableList;
+use crate::pdf::{HittablePdf, MixturePdf, Pdf};
+use crate::rtweekend::*;
+use crate::sampler::{sample_2d, set_thread_sampler, start_pixel_sample, SamplerKind};
+use crate::scene::Scene;
+use crate::vec3::{Color, Vec3};
+use crate::Ray;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn ray_color(
+    r: Ray,
+    background: &Color,
+    world: &dyn Hit,
+    lights: &HittableList,
+    depth: i32,
+) -> Color {
+    if depth <= 0 {
+        return Vec3::zero();
+    }
+    let rec = match world.hit(&r, 0.001, f64::INFINITY) {
+        Some(rec) => rec,
+        None => return *background,
+    };
+    let emitted = rec.material.emitted(&r, &rec, rec.u, rec.v, &rec.p);
+    let srec = match rec.material.scatter(&r, &rec) {
+        Some(srec) => srec,
+        None => return emitted,
+    };
+    let surface_pdf = match (srec.specular_ray, srec.pdf) {
+        // delta BSDFs cannot be light sampled, follow their one direction
+        (Some(specular_ray), _) => {
+            return emitted
+                + srec.attenuation * ray_color(specular_ray, background, world, lights, depth - 1)
+        }
+        (None, Some(pdf)) => pdf,
+        (None, None) => return emitted,
+    };
+    // half of the directions go towards the lights, if the scene has any
+    let (light_pdf, mixture);
+    let mix_pdf: &dyn Pdf = if lights.objects.is_empty() {
+        &*surface_pdf
+    } else {
+        light_pdf = HittablePdf::new(lights, rec.p);
+        mixture = MixturePdf::new(&light_pdf, &*surface_pdf);
+        &mixture
+    };
+    let scattered = Ray::new(rec.p, mix_pdf.generate(), r.time());
+    let pdf = mix_pdf.value(&scattered.dir());
+    // a NaN would poison the pixel and its variance for good
+    if pdf.is_nan() || pdf <= 0.0 {
+        return emitted;
+    }
+    emitted
+        + srec.attenuation
+            * rec.material.scattering_pdf(&r, &rec, &scattered)
+            * ray_color(scattered, background, world, lights, depth - 1)
+            / pdf
+}
+
+/// How big and how good a render should be.
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    /// Samples of every pixel, or with a `noise_threshold` the most that a pixel gets.
+    pub samples_per_pixel: usize,
+    pub max_depth: usize,
+    /// Render threads, the number of cores by default.
+    pub threads: usize,
+    /// Width and height of the square tiles that the threads take turns on.
+    pub tile_size: usize,
+    /// Seeds the random numbers of every sample, so that renders with the same seed
+    /// are identical whatever the thread count. `None` picks a seed at random.
+    pub seed: Option<u64>,
+    /// Where the samples of a pixel go in each of their dimensions.
+    pub sampler: SamplerKind,
+    /// Turns on adaptive sampling: pixels stop getting samples once their
+    /// [error](crate::film::PixelStats::error), and that of their neighbours, is below
+    /// this.
+    pub noise_threshold: Option<f64>,
+    /// With a `noise_threshold`, the samples that every pixel gets before its noise is
+    /// measured.
+    pub min_samples: usize,
+    /// The samples that a pixel gets in each pass. Between passes the render can stop
+    /// with a complete image, or be checkpointed. The stratified sampler stratifies
+    /// every pass on its own, best with a `min_samples` that is a multiple of this.
+    pub pass_samples: usize,
+    /// Stops the render once this much time has gone by, with whatever samples it has.
+    pub time_limit: Option<Duration>,
+    /// How the samples around a pixel make up its color.
+    pub filter: Filter,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 400,
+            height: 225,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            tile_size: 16,
+            seed: None,
+            sampler: SamplerKind::Sobol,
+            noise_threshold: None,
+            min_samples: 16,
+            pass_samples: 16,
+            time_limit: None,
+            filter: Filter::default(),
+        }
+    }
+}
+
+pub struct Renderer {
+    settings: RenderSettings,
+}
+
+impl Renderer {
+    pub fn new(settings: RenderSettings) -> Self {
+        Self { settings }
+    }
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+    pub fn render(&self, scene: &Scene) -> Film {
+        self.render_with(scene, |_| {}, &AtomicBool::new(false))
+            .to_film()
+    }
+    /// Renders `scene` in passes of `pass_samples`, calling `progress` with the number
+    /// of samples taken so far as tiles complete. With a noise threshold, the passes
+    /// after `min_samples` only go to the pixels whose noise is still above it. Setting
+    /// `cancel`, or running out of time, stops the render after the tiles in progress,
+    /// leaving the pixels that the first pass did not reach black.
+    pub fn render_with<F>(&self, scene: &Scene, progress: F, cancel: &AtomicBool) -> SampleFilm
+    where
+        F: Fn(usize) + Sync,
+    {
+        let film = SampleFilm::new(self.settings.width, self.settings.height);
+        self.resume(scene, film, progress, |_| {}, cancel)
+    }
+    /// Carries on with `film`, the samples of an earlier render of `scene` with the same
+    /// size, seed, sampler and filter, until it has what the settings ask for. Sample `s`
+    /// of a pixel is the same whenever it is taken, so stopping and resuming gives the
+    /// image of an uninterrupted render, up to rounding. `on_pass` is called with the
+    /// film after every pass.
+    pub fn resume<F, P>(
+        &self,
+        scene: &Scene,
+        mut film: SampleFilm,
+        progress: F,
+        mut on_pass: P,
+        cancel: &AtomicBool,
+    ) -> SampleFilm
+    where
+        F: Fn(usize) + Sync,
+        P: FnMut(&SampleFilm),
+    {
+        let RenderSettings {
+            width,
+            height,
+            samples_per_pixel,
+            max_depth,
+            threads,
+            tile_size,
+            seed,
+            sampler,
+            noise_threshold,
+            min_samples,
+            pass_samples,
+            time_limit,
+            filter,
+        } = self.settings;
+        let start = Instant::now();
+        let stopped =
+            || cancel.load(Ordering::Relaxed) || time_limit.is_some_and(|t| start.elapsed() >= t);
+        assert_eq!(
+            (film.width(), film.height()),
+            (width, height),
+            "film of a different size"
+        );
+        let seed = seed.unwrap_or_else(entropy_seed);
+        let min_samples = match noise_threshold {
+            Some(_) => min_samples,
+            None => 0,
+        };
+        let tiles = Tiles::new(width, height, tile_size);
+        let done = AtomicUsize::new(film.total_count());
+        while !stopped() {
+            // how many samples every pixel has after this pass, 0 for those left alone
+            let goals = pass_goals(
+                &film,
+                min_samples,
+                pass_samples.max(1),
+                samples_per_pixel,
+                noise_threshold,
+            );
+            let active: Vec<usize> = (0..tiles.len())
+                .filter(|&tile| {
+                    let (xs, ys) = tiles.pixels(tile);
+                    ys.flat_map(|j| xs.clone().map(move |i| (i, height - j - 1)))
+                        .any(|(x, y)| goals[y * width + x] > 0)
+                })
+                .collect();
+            if active.is_empty() {
+                break;
+            }
+            let queues = TileQueues::new(active.len(), threads);
+            // the threads render their tiles from the film as the pass found it, and
+            // hand back the new statistics of their pixels with the filtered samples
+            let mut rendered = thread::scope(|s| {
+                let handles: Vec<_> = (0..threads)
+                    .map(|t| {
+                        let (film, goals) = (&film, &goals);
+                        let (tiles, active, queues) = (&tiles, &active, &queues);
+                        let (done, progress, stopped) = (&done, &progress, &stopped);
+                        s.spawn(move || {
+                            set_thread_sampler(Some(sampler.build(pass_samples.max(1), seed)));
+                            let mut rendered = Vec::new();
+                            while let Some(k) = queues.next(t) {
+                                if stopped() {
+                                    break;
+                                }
+                                let (xs, ys) = tiles.pixels(active[k]);
+                                let rows = (height - ys.end)..(height - ys.start);
+                                let mut splats =
+                                    Splats::new(filter, xs.clone(), rows, width, height);
+                                let mut updates = Vec::new();
+                                let mut samples = 0;
+                                for j in ys {
+                                    for i in xs.clone() {
+                                        let y = height - j - 1;
+                                        let mut stats = *film.pixel(i, y);
+                                        let goal = goals[y * width + i];
+                                        if goal <= stats.count() {
+                                            continue;
+                                        }
+                                        for s in stats.count()..goal {
+                                            // every sample draws from its own stream
+                                            seed_thread_rng(derive_seed(
+                                                seed,
+                                                &[i as u64, j as u64, s as u64],
+                                            ));
+                                            start_pixel_sample(i, j, s);
+                                            let (du, dv) = sample_2d();
+                                            let u = (i as f64 + du) / (width - 1) as f64;
+                                            let v = (j as f64 + dv) / (height - 1) as f64;
+                                            let r = scene.camera.get_ray(u, v);
+                                            let color = ray_color(
+                                                r,
+                                                &scene.background,
+                                                &*scene.world,
+                                                &scene.lights,
+                                                max_depth as i32,
+                                            );
+                                            stats.add(&color);
+                                            // the film counts its rows from the top
+                                            let (fx, fy) = (i as f64 + du, (y + 1) as f64 - dv);
+                                            splats.add(fx, fy, &color);
+                                            samples += 1;
+                                        }
+                                        updates.push((i, y, stats));
+                                    }
+                                }
+                                rendered.push((active[k], updates, splats));
+                                progress(done.fetch_add(samples, Ordering::Relaxed) + samples);
+                            }
+                            rendered
+                        })
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .flat_map(|h| h.join().unwrap())
+                    .collect::<Vec<_>>()
+            });
+            // filtered samples cross the edges of the tiles, and are added up in the
+            // same order whatever thread rendered them
+            rendered.sort_unstable_by_key(|&(tile, _, _)| tile);
+            for (_, updates, splats) in rendered {
+                for (x, y, stats) in updates {
+                    *film.pixel_mut(x, y) = stats;
+                }
+                film.add_splats(&splats);
+            }
+            on_pass(&film);
+        }
+        film
+    }
+    /// Renders the auxiliary passes of `scene` from the first hits of the camera rays of
+    /// the first `samples` samples of every pixel. These are the rays of the image, so
+    /// the passes line up with it along the edges of objects. Every pixel keeps to its
+    /// own rays, whatever the filter.
+    pub fn render_aovs(&self, scene: &Scene, samples: usize) -> Aovs {
+        let RenderSettings {
+            width,
+            height,
+            pass_samples,
+            threads,
+            tile_size,
+            seed,
+            sampler,
+            ..
+        } = self.settings;
+        let seed = seed.unwrap_or_else(entropy_seed);
+        let tiles = Tiles::new(width, height, tile_size);
+        let queues = TileQueues::new(tiles.len(), threads);
+        let rendered = thread::scope(|s| {
+            let handles: Vec<_> = (0..threads)
+                .map(|t| {
+                    let (tiles, queues) = (&tiles, &queues);
+                    s.spawn(move || {
+                        set_thread_sampler(Some(sampler.build(pass_samples.max(1), seed)));
+                        let mut rendered = Vec::new();
+                        while let Some(tile) = queues.next(t) {
+                            let (xs, ys) = tiles.pixels(tile);
+                            for j in ys {
+                                for i in xs.clone() {
+                                    let mut aov = AovSamples::new();
+                                    for s in 0..samples.max(1) {
+                                        // the same draws as sample `s` of the image
+                                        seed_thread_rng(derive_seed(
+                                            seed,
+                                            &[i as u64, j as u64, s as u64],
+                                        ));
+                                        start_pixel_sample(i, j, s);
+                                        let (du, dv) = sample_2d();
+                                        let u = (i as f64 + du) / (width - 1) as f64;
+                                        let v = (j as f64 + dv) / (height - 1) as f64;
+                                        let r = scene.camera.get_ray(u, v);
+                                        let rec = scene.world.hit(&r, 0.001, f64::INFINITY);
+                                        aov.add(&r, rec.as_ref(), &scene.background);
+                                    }
+                                    rendered.push((i, height - j - 1, aov.finish()));
+                                }
+                            }
+                        }
+                        rendered
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        let mut aovs = Aovs::new(width, height);
+        let mut addresses = vec![0; width * height];
+        for (x, y, (pixel, address)) in rendered {
+            *aovs.pixel_mut(x, y) = pixel;
+            addresses[y * width + x] = address;
+        }
+        aovs.number_materials(&addresses);
+        aovs
+    }
+}
+
+/// The sample count that every pixel of `film` should reach in the next pass, or 0 if
+/// it is done. Pixels first get `min_samples`, then `pass_samples` more in each pass as
+/// long as the noise of any pixel around them is above `threshold`, if there is one.
+fn pass_goals(
+    film: &SampleFilm,
+    min_samples: usize,
+    pass_samples: usize,
+    max_samples: usize,
+    threshold: Option<f64>,
+) -> Vec<usize> {
+    let (width, height) = (film.width(), film.height());
+    let errors: Vec<f64> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| film.pixel(x, y).error()))
+        .collect();
+    let mut goals = vec![0; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let count = film.pixel(x, y).count();
+            let goal = if count < min_samples {
+                min_samples
+            } else {
+                // the estimate of a single pixel is noisy itself, so a pixel is only
+                // done once its neighbourhood is
+                let noisy = threshold.is_none_or(|threshold| {
+                    (y.saturating_sub(1)..(y + 2).min(height)).any(|ny| {
+                        (x.saturating_sub(1)..(x + 2).min(width))
+                            .any(|nx| errors[ny * width + nx] > threshold)
+                    })
+                });
+                if !noisy {
+                    continue;
+                }
+                count + pass_samples
+            };
+            if count < max_samples {
+                goals[y * width + x] = goal.min(max_samples);
+            }
+        }
+    }
+    goals
+}
+
+/// The image cut into square tiles, numbered row by row from the bottom.
+struct Tiles {
+    width: usize,
+    height: usize,
+    size: usize,
+    columns: usize,
+    rows: usize,
+}
+
+impl Tiles {
+    fn new(width: usize, height: usize, size: usize) -> Self {
+        let size = size.max(1);
+        Self {
+            width,
+            height,
+            size,
+            columns: width.div_ceil(size),
+            rows: height.div_ceil(size),
+        }
+    }
+    fn len(&self) -> usize {
+        self.columns * self.rows
+    }
+    /// The pixel columns and rows (counted from the bottom) of `tile`.
+    fn pixels(&self, tile: usize) -> (Range<usize>, Range<usize>) {
+        let (x, y) = (
+            tile % self.columns * self.size,
+            tile / self.columns * self.size,
+        );
+        (
+            x..(x + self.size).min(self.width),
+            y..(y + self.size).min(self.height),
+        )
+    }
+}
+
+/// The tiles left to every thread. A thread takes its tiles from the front of its own
+/// range, and once that is empty steals the back half of the longest other one, so
+/// that threads which drew cheap tiles help with the expensive ones.
+struct TileQueues {
+    queues: Vec<Mutex<Range<usize>>>,
+}
+
+impl TileQueues {
+    fn new(tiles: usize, threads: usize) -> Self {
+        let queues = (0..threads)
+            .map(|t| Mutex::new((t * tiles / threads)..((t + 1) * tiles / threads)))
+            .collect();
+        Self { queues }
+    }
+    fn next(&self, thread: usize) -> Option<usize> {
+        if let Some(tile) = self.queues[thread].lock().unwrap().next() {
+            return Some(tile);
+        }
+        loop {
+            let (victim, left) = (0..self.queues.len())
+                .map(|t| (t, self.queues[t].lock().unwrap().len()))
+                .max_by_key(|&(_, left)| left)?;
+            // tiles in the middle of a steal are not seen, but their thief renders them
+            if left == 0 {
+                return None;
+            }
+            let mut stolen = {
+                let mut queue = self.queues[victim].lock().unwrap();
+                let mid = queue.end - queue.len().div_ceil(2);
+                let stolen = mid..queue.end;
+                queue.end = mid;
+                stolen
+            };
+            // the victim may have taken its last tiles in the meantime
+            if let Some(tile) = stolen.next() {
+                *self.queues[thread].lock().unwrap() = stolen;
+                return Some(tile);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenes;
+
+    #[test]
+    fn test_tiles_are_handed_out_once() {
+        // alone, thread 0 steals everything that the others never take
+        let queues = TileQueues::new(100, 4);
+        let tiles: Vec<_> = std::iter::from_fn(|| queues.next(0)).collect();
+        assert_eq!(tiles.len(), 100);
+        let mut sorted = tiles.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..100).collect::<Vec<_>>());
+        // all together, with threads that have nothing to start with
+        let queues = TileQueues::new(1000, 12);
+        let mut tiles: Vec<_> = thread::scope(|s| {
+            let handles: Vec<_> = (0..12)
+                .map(|t| {
+                    let queues = &queues;
+                    s.spawn(move || std::iter::from_fn(|| queues.next(t)).collect::<Vec<_>>())
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+        tiles.sort_unstable();
+        assert_eq!(tiles, (0..1000).collect::<Vec<_>>());
+        let tiles = Tiles::new(10, 7, 4);
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles.pixels(5), (8..10, 4..7));
+    }
+
+    #[test]
+    fn test_seeded_renders_repeat() {
+        // random placement, noise textures, motion blur and defocus
+        for name in ["random", "two_perlin_spheres"] {
+            let render = |threads, tile_size| {
+                let desc = scenes::builtin(name).unwrap();
+                Renderer::new(RenderSettings {
+                    width: 16,
+                    height: 9,
+                    samples_per_pixel: 2,
+                    threads,
+                    tile_size,
+                    seed: Some(7),
+                    ..RenderSettings::default()
+                })
+                .render(&desc.scene)
+            };
+            let one = render(1, 16);
+            assert!(one.data() == render(3, 4).data(), "{} differs", name);
+            assert!(one.data() == render(4, 1).data(), "{} differs", name);
+            assert!(one.data() == render(1, 5).data(), "{} differs", name);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_sampling() {
+        let desc = scenes::builtin("two_spheres").unwrap();
+        let render = |threads| {
+            Renderer::new(RenderSettings {
+                width: 24,
+                height: 16,
+                samples_per_pixel: 64,
+                threads,
+                tile_size: 5,
+                seed: Some(3),
+                noise_threshold: Some(0.02),
+                min_samples: 8,
+                pass_samples: 8,
+                ..RenderSettings::default()
+            })
+            .render_with(&desc.scene, |_| {}, &AtomicBool::new(false))
+        };
+        let film = render(1);
+        let counts: Vec<_> = (0..film.height())
+            .flat_map(|y| (0..film.width()).map(move |x| (x, y)))
+            .map(|(x, y)| film.pixel(x, y).count())
+            .collect();
+        // the flat sky converges early, the textured spheres need more
+        assert!(counts.iter().all(|&n| (8..=64).contains(&n)));
+        assert!(counts.contains(&8) && counts.contains(&64), "{:?}", counts);
+        assert!(counts.iter().all(|n| n % 8 == 0));
+        assert!(film.to_film().data() == render(3).to_film().data());
+    }
+
+    #[test]
+    fn test_resumed_render_matches() {
+        let desc = scenes::builtin("two_perlin_spheres").unwrap();
+        let settings = |samples_per_pixel, pass_samples| RenderSettings {
+            width: 12,
+            height: 8,
+            samples_per_pixel,
+            pass_samples,
+            threads: 2,
+            tile_size: 4,
+            seed: Some(11),
+            filter: Filter::from_name("mitchell").unwrap(),
+            ..RenderSettings::default()
+        };
+        let cancel = AtomicBool::new(false);
+        let whole = Renderer::new(settings(12, 12)).render_with(&desc.scene, |_| {}, &cancel);
+        // samples that spill over into other tiles add up the same with any thread count
+        let alone = Renderer::new(RenderSettings {
+            threads: 1,
+            ..settings(12, 12)
+        });
+        assert!(alone.render_with(&desc.scene, |_| {}, &cancel) == whole);
+        // stopped after 4 samples, then extended to 12 in passes of another size
+        let mut passes = 0;
+        let start = Renderer::new(settings(4, 3)).resume(
+            &desc.scene,
+            SampleFilm::new(12, 8),
+            |_| {},
+            |_| passes += 1,
+            &cancel,
+        );
+        assert_eq!(passes, 2);
+        assert_eq!(start.total_count(), 12 * 8 * 4);
+        let resumed =
+            Renderer::new(settings(12, 5)).resume(&desc.scene, start, |_| {}, |_| {}, &cancel);
+        // the same samples, added up in other groups
+        for y in 0..8 {
+            for x in 0..12 {
+                assert_eq!(resumed.pixel(x, y), whole.pixel(x, y));
+                let (a, b) = (resumed.filtered(x, y), whole.filtered(x, y));
+                for (a, b) in a.iter().zip(&b) {
+                    assert!((a - b).abs() <= 1e-9 * b.abs().max(1.0), "{} {}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_time_limit_stops_the_render() {
+        let desc = scenes::builtin("cornell_box").unwrap();
+        let film = Renderer::new(RenderSettings {
+            width: 40,
+            height: 40,
+            samples_per_pixel: usize::MAX,
+            pass_samples: 1,
+            threads: 2,
+            seed: Some(1),
+            time_limit: Some(Duration::from_millis(300)),
+            ..RenderSettings::default()
+        })
+        .render_with(&desc.scene, |_| {}, &AtomicBool::new(false));
+        // without a bound on the samples, only the time ends the render
+        assert!(film.total_count() > 0);
+        // cancelled before it starts, nothing is rendered
+        let film = Renderer::new(RenderSettings {
+            width: 8,
+            height: 8,
+            ..RenderSettings::default()
+        })
+        .render_with(&desc.scene, |_| {}, &AtomicBool::new(true));
+        assert_eq!(film.total_count(), 0);
+    }
+
+    #[test]
+    fn test_aovs() {
+        use crate::aov::Aov;
+        let desc = scenes::builtin("cornell_box").unwrap();
+        let render = |threads, tile_size| {
+            Renderer::new(RenderSettings {
+                width: 20,
+                height: 20,
+                threads,
+                tile_size,
+                seed: Some(5),
+                ..RenderSettings::default()
+            })
+            .render_aovs(&desc.scene, 4)
+        };
+        let aovs = render(1, 16);
+        let other = render(3, 3);
+        for aov in Aov::ALL {
+            assert!(
+                aovs.layer(aov) == other.layer(aov),
+                "{} differs",
+                aov.name()
+            );
+        }
+        let eps = if cfg!(feature = "f32") { 1e-6 } else { 1e-9 };
+        let (mut objects, mut materials) = (Vec::new(), 0);
+        for y in 0..20 {
+            for x in 0..20 {
+                let p = aovs.pixel(x, y);
+                if p.depth.is_finite() {
+                    assert!(p.depth > 0.0 && (p.normal.length() - 1.0).abs() < eps);
+                } else {
+                    assert_eq!((p.object, p.material), (0, 0));
+                }
+                // mostly missed, at the edges of the open front of the box
+                assert_eq!(p.object > 0, p.material > 0);
+                assert!((0.0..=1.0).contains(&p.albedo.x()));
+                if !objects.contains(&p.object) {
+                    objects.push(p.object);
+                }
+                // numbered as they first appear, row by row
+                assert!(p.material <= materials + 1);
+                materials = materials.max(p.material);
+            }
+        }
+        // the walls, the light and the boxes, moved and turned
+        assert!(objects.len() >= 5, "{:?}", objects);
+        assert!(materials >= 3);
+    }
+}
@@ -1,10 +1,51 @@
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use std::cell::RefCell;
 use std::f64::consts::PI;
+
+// PCG is cheap to seed, which matters because the renderer reseeds it for every
+// sample, and its output is fixed across platforms and versions.
+thread_local! {
+    static RNG: RefCell<Pcg64> = RefCell::new(Pcg64::from_entropy());
+}
+
 pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * PI / 180.0
 }
+/// Reseeds the random numbers of the calling thread, so that what it draws afterwards is repeatable.
+pub fn seed_thread_rng(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = Pcg64::seed_from_u64(seed));
+}
+/// What the scene builders seed the random numbers with.
+pub const SCENE_SEED: u64 = 0x5EED;
+/// Runs `f` with the random numbers of the calling thread seeded with `seed`, and
+/// puts the previous state back afterwards. The scene builders use it, so that a
+/// scene comes out the same on every run whatever was drawn before.
+pub fn with_thread_seed<T>(seed: u64, f: impl FnOnce() -> T) -> T {
+    let saved = RNG.with(|rng| rng.replace(Pcg64::seed_from_u64(seed)));
+    let result = f();
+    RNG.with(|rng| *rng.borrow_mut() = saved);
+    result
+}
+/// Hashes `values` into `seed`, giving for example every pixel and sample a stream
+/// of its own that does not depend on which thread renders it.
+pub fn derive_seed(seed: u64, values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(splitmix64(seed), |hash, &v| splitmix64(hash ^ v))
+}
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+/// A seed from the operating system, for renders that were not given one.
+pub fn entropy_seed() -> u64 {
+    rand::random()
+}
 pub fn random_f64() -> f64 {
-    rand::thread_rng().gen_range(0.0..1.0)
+    RNG.with(|rng| rng.borrow_mut().gen_range(0.0..1.0))
 }
 pub fn random(min: f64, max: f64) -> f64 {
     min + (max - min) * random_f64()
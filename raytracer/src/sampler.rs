@@ -0,0 +1,475 @@
+//! Sample sequences for the pixel, lens, time, BSDF and light dimensions of a path.
+//!
+//! Every sample of a pixel asks its [`Sampler`] for one dimension after another:
+//! the position in the pixel and on the lens (2D each), the time (1D), then for every
+//! bounce the choice between light and BSDF sampling (1D) and the direction (2D).
+//! Independent random numbers clump, while the other samplers spread the samples of
+//! a pixel evenly over each dimension, which lowers the noise at the same sample count.
+//!
+//! The renderer installs a sampler for each of its threads with [`set_thread_sampler`].
+//! The sampling routines ([`random_cosine_direction`](crate::vec3::random_cosine_direction),
+//! [`Camera::get_ray`](crate::camera::Camera::get_ray), the `random` of the lights, ...)
+//! then draw their dimensions with [`sample_1d`] and [`sample_2d`]. Without a sampler,
+//! for example while a scene is built, those fall back to independent random numbers.
+use crate::rtweekend::{derive_seed, random_f64};
+use std::cell::RefCell;
+use std::fmt;
+
+/// A sequence of sample vectors for every pixel, handed out one dimension at a time.
+pub trait Sampler {
+    /// Starts sample `index` of pixel (`x`, `y`), going back to the first dimension.
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize);
+    /// The next dimension, in `[0, 1)`.
+    fn get_1d(&mut self) -> f64;
+    /// The next two dimensions, in `[0, 1)²`.
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+/// Which [`Sampler`] a render uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplerKind {
+    /// Independent uniform random numbers.
+    Independent,
+    /// One jittered sample in every stratum of each dimension for every pass of
+    /// samples, the strata shuffled independently per dimension and pass.
+    Stratified,
+    /// The Halton sequence, Owen-scrambled per pixel. Dimensions past the first
+    /// [`HALTON_DIMENSIONS`] are independent. Scrambling digit by digit makes it the
+    /// slowest of the four.
+    Halton,
+    /// The first two dimensions of the Sobol sequence for every pair of dimensions,
+    /// Owen-scrambled and shuffled per pixel. Best with a power of two samples per pixel.
+    Sobol,
+}
+
+impl SamplerKind {
+    pub const NAMES: [&'static str; 4] = ["independent", "stratified", "halton", "sobol"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "independent" => Some(Self::Independent),
+            "stratified" => Some(Self::Stratified),
+            "halton" => Some(Self::Halton),
+            "sobol" => Some(Self::Sobol),
+            _ => None,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Independent => "independent",
+            Self::Stratified => "stratified",
+            Self::Halton => "halton",
+            Self::Sobol => "sobol",
+        }
+    }
+    /// A sampler for renders that take their samples in passes of `pass_samples`,
+    /// scrambled by `seed`. The sequences do not depend on how many samples there will
+    /// be in all, so a render can stop after any pass or be resumed for more.
+    pub fn build(self, pass_samples: usize, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            Self::Independent => Box::new(IndependentSampler),
+            Self::Stratified => Box::new(StratifiedSampler::new(pass_samples, seed)),
+            Self::Halton => Box::new(HaltonSampler::new(seed)),
+            Self::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+impl fmt::Display for SamplerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+thread_local! {
+    static SAMPLER: RefCell<Option<Box<dyn Sampler>>> = RefCell::new(None);
+}
+
+/// Installs `sampler` for the calling thread and returns the previous one.
+pub fn set_thread_sampler(sampler: Option<Box<dyn Sampler>>) -> Option<Box<dyn Sampler>> {
+    SAMPLER.with(|s| s.replace(sampler))
+}
+/// Starts sample `index` of pixel (`x`, `y`) on the sampler of the calling thread.
+pub fn start_pixel_sample(x: usize, y: usize, index: usize) {
+    SAMPLER.with(|s| {
+        if let Some(sampler) = s.borrow_mut().as_mut() {
+            sampler.start_pixel_sample(x, y, index);
+        }
+    })
+}
+/// The next dimension from the sampler of the calling thread.
+pub fn sample_1d() -> f64 {
+    SAMPLER.with(|s| match s.borrow_mut().as_mut() {
+        Some(sampler) => sampler.get_1d(),
+        None => random_f64(),
+    })
+}
+/// The next two dimensions from the sampler of the calling thread.
+pub fn sample_2d() -> (f64, f64) {
+    SAMPLER.with(|s| match s.borrow_mut().as_mut() {
+        Some(sampler) => sampler.get_2d(),
+        None => (random_f64(), random_f64()),
+    })
+}
+
+/// Uniform random numbers from the thread's generator, which the renderer seeds per sample.
+pub struct IndependentSampler;
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, _x: usize, _y: usize, _index: usize) {}
+    fn get_1d(&mut self) -> f64 {
+        random_f64()
+    }
+    fn get_2d(&mut self) -> (f64, f64) {
+        (random_f64(), random_f64())
+    }
+}
+
+/// Splits each dimension into `block` strata (a grid as close to square as possible in
+/// 2D) and gives each of `block` consecutive samples of a pixel its own stratum, with a
+/// random position inside it. Every block is stratified on its own, so that the
+/// samples of a render stopped after a whole number of blocks cover every stratum
+/// equally.
+pub struct StratifiedSampler {
+    block: u32,
+    x_strata: u32,
+    seed: u64,
+    pixel_seed: u64,
+    index: u64,
+    dimension: u64,
+}
+
+impl StratifiedSampler {
+    pub fn new(block: usize, seed: u64) -> Self {
+        let block = block.clamp(1, u32::MAX as usize) as u32;
+        let mut x_strata = (block as f64).sqrt() as u32;
+        while !block.is_multiple_of(x_strata) {
+            x_strata -= 1;
+        }
+        Self {
+            block,
+            x_strata,
+            seed,
+            pixel_seed: seed,
+            index: 0,
+            dimension: 0,
+        }
+    }
+    /// The stratum of the current sample in the next dimension.
+    fn next_stratum(&mut self) -> u32 {
+        let block = self.block as u64;
+        let hash = derive_seed(self.pixel_seed, &[self.dimension, self.index / block]);
+        self.dimension += 1;
+        permutation_element((self.index % block) as u32, self.block, hash as u32)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel_seed = derive_seed(self.seed, &[x as u64, y as u64]);
+        self.index = index as u64;
+        self.dimension = 0;
+    }
+    fn get_1d(&mut self) -> f64 {
+        let stratum = self.next_stratum();
+        (stratum as f64 + random_f64()) / self.block as f64
+    }
+    fn get_2d(&mut self) -> (f64, f64) {
+        let stratum = self.next_stratum();
+        let y_strata = self.block / self.x_strata;
+        let (sx, sy) = (stratum % self.x_strata, stratum / self.x_strata);
+        (
+            (sx as f64 + random_f64()) / self.x_strata as f64,
+            (sy as f64 + random_f64()) / y_strata as f64,
+        )
+    }
+}
+
+/// How many dimensions the [`HaltonSampler`] takes from the sequence.
+pub const HALTON_DIMENSIONS: usize = 64;
+
+/// The Halton sequence with the digits of every dimension Owen-scrambled per pixel.
+/// A plain shift would keep the points of neighbouring large bases on a few lines.
+pub struct HaltonSampler {
+    primes: Vec<u32>,
+    seed: u64,
+    pixel_seed: u64,
+    index: u64,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        let mut primes = Vec::with_capacity(HALTON_DIMENSIONS);
+        let mut n = 2;
+        while primes.len() < HALTON_DIMENSIONS {
+            if primes.iter().all(|p| n % p != 0) {
+                primes.push(n);
+            }
+            n += 1;
+        }
+        Self {
+            primes,
+            seed,
+            pixel_seed: seed,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel_seed = derive_seed(self.seed, &[x as u64, y as u64]);
+        self.index = index as u64;
+        self.dimension = 0;
+    }
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match self.primes.get(dimension) {
+            Some(&base) => {
+                let seed = derive_seed(self.pixel_seed, &[dimension as u64]);
+                radical_inverse(base as u64, self.index, seed)
+            }
+            None => random_f64(),
+        }
+    }
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+/// `index` written in `base` and mirrored around the radix point, with every digit
+/// permuted by `seed` and the digits before it (Owen scrambling).
+fn radical_inverse(base: u64, mut index: u64, seed: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let (mut x, mut inv_base_n, mut prefix) = (0.0, inv_base, seed);
+    // enough digits to tell 2^20 samples apart, the same ones for every sample so
+    // that their zero digits are scrambled alike
+    while index > 0 || base as f64 * inv_base_n > 1.0 / (1 << 20) as f64 {
+        let digit = permutation_element((index % base) as u32, base as u32, prefix as u32);
+        x += digit as f64 * inv_base_n;
+        prefix = derive_seed(prefix, &[digit as u64]);
+        inv_base_n *= inv_base;
+        index /= base;
+    }
+    // past those, the scrambled digits are as good as uniform
+    x += base as f64 * inv_base_n * to_unit((prefix >> 32) as u32);
+    // stay below 1 where the sum rounds up
+    x.min(1.0 - f64::EPSILON / 2.0)
+}
+
+/// The first two Sobol dimensions for every one or two dimensions asked for, each
+/// pair with its own Owen scrambling and its own order of the samples. Padding the
+/// 2D sequence this way needs no table of direction numbers and has no limit on the
+/// number of dimensions.
+pub struct SobolSampler {
+    seed: u64,
+    pixel_seed: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_seed: seed,
+            index: 0,
+            dimension: 0,
+        }
+    }
+    /// The shuffled sample index and the scrambling seeds of the next dimension.
+    fn next_dimension(&mut self) -> (u32, u64) {
+        let hash = derive_seed(self.pixel_seed, &[self.dimension]);
+        self.dimension += 1;
+        (nested_uniform_scramble(self.index, hash as u32), hash)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel_seed = derive_seed(self.seed, &[x as u64, y as u64]);
+        self.index = index as u32;
+        self.dimension = 0;
+    }
+    fn get_1d(&mut self) -> f64 {
+        let (index, hash) = self.next_dimension();
+        to_unit(nested_uniform_scramble(
+            index.reverse_bits(),
+            (hash >> 32) as u32,
+        ))
+    }
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (index, hash) = self.next_dimension();
+        let (x, y) = sobol_2d(index);
+        (
+            to_unit(nested_uniform_scramble(x, (hash >> 32) as u32)),
+            to_unit(nested_uniform_scramble(y, derive_seed(hash, &[1]) as u32)),
+        )
+    }
+}
+
+/// Point `index` of the first two Sobol dimensions, as 32-bit fractions.
+fn sobol_2d(index: u32) -> (u32, u32) {
+    // the first dimension is the van der Corput sequence, the second one has the
+    // direction numbers of the polynomial x + 1, v_{k+1} = v_k ^ (v_k >> 1)
+    let (mut y, mut v, mut i) = (0, 1 << 31, index);
+    while i != 0 {
+        if i & 1 != 0 {
+            y ^= v;
+        }
+        i >>= 1;
+        v ^= v >> 1;
+    }
+    (index.reverse_bits(), y)
+}
+
+/// Owen scrambling of a 32-bit fraction: every digit is flipped depending on the
+/// digits before it, with the hash of Laine and Karras as improved by Burley
+/// ("Practical Hash-based Owen Scrambling", 2020).
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    // reversed, the digits before a digit are the bits below it, which is all that
+    // additions and multiplications carry into it
+    let mut x = x.reverse_bits().wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+/// Element `i` of a random permutation of `0..n` chosen by `seed`, after Kensler
+/// ("Correlated Multi-Jittered Sampling", 2013).
+fn permutation_element(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    // a bijection on 0..=w, applied again until the element falls below n
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+    (i.wrapping_add(seed)) % n
+}
+
+/// A 32-bit fraction as a float in `[0, 1)`.
+fn to_unit(x: u32) -> f64 {
+    x as f64 / 4_294_967_296.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether the 1D and 2D dimensions of `n` samples of a pixel, from sample `first`
+    /// on, cover every stratum once, in `[0, 1)`.
+    fn assert_stratified(kind: SamplerKind, n: usize, strata: (usize, usize)) {
+        assert_stratified_from(kind, n, 0, strata);
+    }
+
+    fn assert_stratified_from(kind: SamplerKind, n: usize, first: usize, strata: (usize, usize)) {
+        let mut sampler = kind.build(n, 3);
+        let mut samples = Vec::new();
+        for index in first..first + n {
+            sampler.start_pixel_sample(5, 8, index);
+            samples.push((sampler.get_1d(), sampler.get_2d(), sampler.get_2d()));
+        }
+        let mut ones = vec![0; n];
+        let mut twos = vec![vec![0; strata.0 * strata.1]; 2];
+        for (a, b, c) in samples {
+            for x in [a, b.0, b.1, c.0, c.1] {
+                assert!((0.0..1.0).contains(&x), "{:?} gave {}", kind, x);
+            }
+            ones[(a * n as f64) as usize] += 1;
+            for (count, (x, y)) in twos.iter_mut().zip([b, c]) {
+                let (sx, sy) = (
+                    (x * strata.0 as f64) as usize,
+                    (y * strata.1 as f64) as usize,
+                );
+                count[sy * strata.0 + sx] += 1;
+            }
+        }
+        assert!(ones.iter().all(|&c| c == 1), "{:?}: {:?}", kind, ones);
+        for count in twos {
+            assert!(count.iter().all(|&c| c == 1), "{:?}: {:?}", kind, count);
+        }
+    }
+
+    #[test]
+    fn test_samples_are_stratified() {
+        assert_stratified(SamplerKind::Stratified, 16, (4, 4));
+        assert_stratified(SamplerKind::Stratified, 12, (3, 4));
+        // every pass on its own, however many there are
+        assert_stratified_from(SamplerKind::Stratified, 16, 48, (4, 4));
+        assert_stratified_from(SamplerKind::Stratified, 16, (1 << 40) + 16, (4, 4));
+        // a scrambled (0, m, 2)-net has one point in each of these boxes
+        assert_stratified(SamplerKind::Sobol, 16, (4, 4));
+        assert_stratified(SamplerKind::Sobol, 32, (8, 4));
+        // the first 6 Halton points, in bases 2 and 3, fall in different 1/2 x 1/3 cells
+        let mut sampler = SamplerKind::Halton.build(6, 3);
+        let points: Vec<_> = (0..6)
+            .map(|index| {
+                sampler.start_pixel_sample(5, 8, index);
+                sampler.get_2d()
+            })
+            .collect();
+        let mut cells: Vec<_> = points
+            .iter()
+            .map(|(x, y)| ((x * 2.0) as usize, (y * 3.0) as usize))
+            .collect();
+        cells.sort_unstable();
+        cells.dedup();
+        assert_eq!(cells.len(), 6);
+    }
+
+    #[test]
+    fn test_samplers_repeat_and_are_uniform() {
+        for name in SamplerKind::NAMES {
+            let kind = SamplerKind::from_name(name).unwrap();
+            assert_eq!(kind.name(), name);
+            let draw = || {
+                crate::rtweekend::seed_thread_rng(1);
+                let mut sampler = kind.build(64, 9);
+                let mut sum = 0.0;
+                for x in 0..8 {
+                    for index in 0..64 {
+                        sampler.start_pixel_sample(x, 2, index);
+                        // past the Halton dimensions as well
+                        for _ in 0..40 {
+                            let (a, b) = sampler.get_2d();
+                            sum += a + b;
+                        }
+                    }
+                }
+                sum / (8 * 64 * 80) as f64
+            };
+            let mean = draw();
+            assert_eq!(mean, draw());
+            assert!((mean - 0.5).abs() < 0.01, "{} has mean {}", name, mean);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use crate::bvh::{BvhError, BvhNode};
+use crate::camera::Camera;
+use crate::hittable::{Hit, ObjectId};
+use crate::hittable_list::HittableList;
+use crate::vec3::Color;
+use std::sync::Arc;
+
+/// What the renderer needs to know about a scene: the objects, the emitters to
+/// sample, the camera and the color of rays that escape.
+pub struct Scene {
+    pub world: Arc<dyn Hit>,
+    /// The emitters that are sampled directly. They must also be part of `world`,
+    /// which is what actually gets hit; without any, only the materials are sampled.
+    pub lights: Arc<HittableList>,
+    pub camera: Camera,
+    pub background: Color,
+}
+
+impl Scene {
+    /// Builds the acceleration structure over `objects`, which are numbered from 1
+    /// in order for the object ID pass.
+    pub fn new(
+        objects: HittableList,
+        lights: HittableList,
+        camera: Camera,
+        background: Color,
+    ) -> Result<Self, BvhError> {
+        let mut numbered = HittableList::new();
+        for (i, object) in objects.objects.into_iter().enumerate() {
+            numbered.add(Arc::new(ObjectId::new(object, i as u32 + 1)));
+        }
+        Ok(Self {
+            world: BvhNode::newnew(numbered, 0.0, 1.0)?,
+            lights: Arc::new(lights),
+            camera,
+            background,
+        })
+    }
+}
+
+/// A scene together with the render settings it was authored for.
+pub struct SceneDescription {
+    pub scene: Scene,
+    pub image_width: usize,
+    pub aspect_ratio: f64,
+    pub samples_per_pixel: usize,
+    pub max_depth: usize,
+}
@@ -0,0 +1,878 @@
+//! A small line-based text format for describing scenes, so that a scene can be
+//! edited and re-rendered without touching the Rust code.
+//!
+//! Every non-empty line is one directive: a keyword, some positional words and
+//! `key=value` attributes. Vectors are written as `x,y,z`, ranges as `a,b`, and
+//! everything after `#` is a comment.
+//!
+//! ```text
+//! render width=600 aspect_ratio=1.0 samples=1000 max_depth=50
+//! background 0,0,0
+//! camera lookfrom=278,278,-800 lookat=278,278,0 vfov=40
+//!
+//! texture marble noise scale=4
+//! material white lambertian color=0.73,0.73,0.73
+//! material light diffuse_light color=15,15,15
+//!
+//! xz_rect x=213,343 z=227,332 k=554 material=light flip light
+//! triangle v0=0,0,0 v1=100,0,0 v2=0,100,0 material=white
+//! obj file=bunny.obj material=white rotate_y=180 translate=278,0,278
+//! box min=0,0,0 max=165,330,165 material=white rotate_y=15 translate=265,0,295
+//!
+//! group spheres
+//!   sphere center=0,0,0 radius=10 material=white
+//! end
+//! instance spheres rotate_y=15 translate=-100,270,395
+//! ```
+//!
+//! Transforms (`rotate_x`, `rotate_y`, `rotate_z` in degrees, `scale` as one factor or
+//! `x,y,z`, `translate`, and `matrix` as the 12 or 16 numbers of the matrix rows) are
+//! applied in the order they are written. The flag `flip` turns a shape's normals
+//! around, and `light` marks an emitter that the renderer should sample directly
+//! (spheres, rects, triangles and boxes outside of groups, transformed without uneven
+//! scales or shears).
+//! Giving a shape a `density` turns it into the boundary of a constant medium whose
+//! phase function has the color `medium_color`.
+//!
+//! `obj` loads a Wavefront OBJ file (see [`crate::obj`]); its MTL materials are used
+//! where the file has them, `material` (or a grey `lambertian`) everywhere else.
+//! `ply` loads a PLY mesh (see [`crate::ply`]) made of `material`, or colored by its
+//! vertex colors when it has some and no `material` is given.
+use crate::aarect::{XYRect, XZRect, YZRect};
+use crate::bvh::BvhNode;
+use crate::camera::Camera;
+use crate::hittable::{FlipFace, Hit, Instance};
+use crate::hittable_list::HittableList;
+use crate::material::{Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal};
+use crate::matrix::Matrix4;
+use crate::medium::ConstantMedium;
+use crate::obj;
+use crate::ply;
+use crate::r#box::Bbox;
+use crate::rtweekend::{with_thread_seed, SCENE_SEED};
+use crate::scene::{Scene, SceneDescription};
+use crate::sphere::{MovingSphere, Sphere};
+use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture};
+use crate::triangle::Triangle;
+use crate::vec3::{Color, Point3, Vec3};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io { path: PathBuf, source: io::Error },
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { path, source } => {
+                write!(f, "cannot read \"{}\": {}", path.display(), source)
+            }
+            SceneError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+/// Reads and parses a scene file. Relative paths inside the file (image textures)
+/// are resolved against the directory containing the scene file.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<SceneDescription, SceneError> {
+    let path = path.as_ref();
+    let src = fs::read_to_string(path).map_err(|source| SceneError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse(&src, path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+/// Parses a scene. Anything random in it (noise textures) is the same on every run.
+pub fn parse(src: &str, base_dir: &Path) -> Result<SceneDescription, SceneError> {
+    with_thread_seed(SCENE_SEED, || parse_seeded(src, base_dir))
+}
+
+fn parse_seeded(src: &str, base_dir: &Path) -> Result<SceneDescription, SceneError> {
+    let mut parser = Parser::new(base_dir);
+    let mut last_line = 0;
+    for (idx, text) in src.lines().enumerate() {
+        last_line = idx + 1;
+        if let Some(line) = Line::tokenize(idx + 1, text) {
+            parser.directive(&line)?;
+        }
+    }
+    parser.finish(last_line)
+}
+
+// attributes that place an object, applied in the order they are written
+const TRANSFORMS: [&str; 6] = [
+    "rotate_x",
+    "rotate_y",
+    "rotate_z",
+    "scale",
+    "translate",
+    "matrix",
+];
+
+struct CameraParams {
+    lookfrom: Point3,
+    lookat: Point3,
+    vup: Vec3,
+    vfov: f64,
+    aperture: f64,
+    focus_dist: f64,
+    time: (f64, f64),
+}
+
+struct Parser {
+    base_dir: PathBuf,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    groups: HashMap<String, Arc<dyn Hit>>,
+    // groups that are still open, innermost last: (name, objects, line of `group`)
+    open_groups: Vec<(String, HittableList, usize)>,
+    world: HittableList,
+    lights: HittableList,
+    background: Color,
+    camera: CameraParams,
+    image_width: usize,
+    aspect_ratio: f64,
+    samples_per_pixel: usize,
+    max_depth: usize,
+}
+
+impl Parser {
+    fn new(base_dir: &Path) -> Self {
+        Self {
+            base_dir: base_dir.to_path_buf(),
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            groups: HashMap::new(),
+            open_groups: Vec::new(),
+            world: HittableList::new(),
+            lights: HittableList::new(),
+            background: Color::zero(),
+            camera: CameraParams {
+                lookfrom: Point3::new(13.0, 2.0, 3.0),
+                lookat: Point3::zero(),
+                vup: Vec3::new(0.0, 1.0, 0.0),
+                vfov: 20.0,
+                aperture: 0.0,
+                focus_dist: 10.0,
+                time: (0.0, 1.0),
+            },
+            image_width: 400,
+            aspect_ratio: 16.0 / 9.0,
+            samples_per_pixel: 100,
+            max_depth: 50,
+        }
+    }
+
+    fn directive(&mut self, line: &Line) -> Result<(), SceneError> {
+        match line.keyword {
+            "render" => self.render(line),
+            "background" => {
+                line.expect_positional(1)?;
+                line.check_keys(&[])?;
+                self.background = line.parse_vec3("background", line.positional[0])?;
+                Ok(())
+            }
+            "camera" => self.camera(line),
+            "texture" => self.texture(line),
+            "material" => self.material(line),
+            "group" => {
+                line.expect_positional(1)?;
+                line.check_keys(&[])?;
+                let name = line.positional[0].to_string();
+                self.open_groups
+                    .push((name, HittableList::new(), line.number));
+                Ok(())
+            }
+            "end" => {
+                line.expect_positional(0)?;
+                line.check_keys(&[])?;
+                let (name, objects, _) = self
+                    .open_groups
+                    .pop()
+                    .ok_or_else(|| line.error("`end` without a matching `group`"))?;
+                if objects.objects.is_empty() {
+                    return Err(line.error(format!("group `{}` is empty", name)));
+                }
+                let group =
+                    BvhNode::newnew(objects, 0.0, 1.0).map_err(|e| line.error(e.to_string()))?;
+                self.groups.insert(name, group);
+                Ok(())
+            }
+            "instance" => {
+                line.expect_positional_with_flags(1)?;
+                let mut keys = vec!["density", "medium_color"];
+                keys.extend(TRANSFORMS);
+                line.check_keys(&keys)?;
+                let name = line.positional[0];
+                let object = self
+                    .groups
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| line.error(format!("unknown group `{}`", name)))?;
+                let object = self.finish_object(line, object)?;
+                self.add(line, object)
+            }
+            _ => {
+                let object = self.shape(line)?;
+                let object = self.finish_object(line, object)?;
+                self.add(line, object)
+            }
+        }
+    }
+
+    fn finish(self, last_line: usize) -> Result<SceneDescription, SceneError> {
+        if let Some((name, _, line)) = self.open_groups.last() {
+            return Err(SceneError::Parse {
+                line: *line,
+                message: format!("group `{}` is never closed with `end`", name),
+            });
+        }
+        if self.world.objects.is_empty() {
+            return Err(SceneError::Parse {
+                line: last_line,
+                message: "the scene does not contain any objects".to_string(),
+            });
+        }
+        let camera = Camera::new(
+            self.camera.lookfrom,
+            self.camera.lookat,
+            self.camera.vup,
+            self.camera.vfov,
+            self.aspect_ratio,
+            self.camera.aperture,
+            self.camera.focus_dist,
+            self.camera.time.0,
+            self.camera.time.1,
+        );
+        let scene = Scene::new(self.world, self.lights, camera, self.background).map_err(|e| {
+            SceneError::Parse {
+                line: last_line,
+                message: e.to_string(),
+            }
+        })?;
+        Ok(SceneDescription {
+            scene,
+            image_width: self.image_width,
+            aspect_ratio: self.aspect_ratio,
+            samples_per_pixel: self.samples_per_pixel,
+            max_depth: self.max_depth,
+        })
+    }
+
+    fn add(&mut self, line: &Line, object: Arc<dyn Hit>) -> Result<(), SceneError> {
+        if line.flag("light") {
+            // only these shapes know how to sample directions towards themselves
+            const SAMPLED: [&str; 6] =
+                ["sphere", "xy_rect", "xz_rect", "yz_rect", "triangle", "box"];
+            if !SAMPLED.contains(&line.keyword) || line.get("density").is_some() {
+                return Err(line.error(format!(
+                    "`light` is only supported for {}",
+                    SAMPLED.join(", ")
+                )));
+            }
+            if !self.open_groups.is_empty() {
+                return Err(line.error("`light` is not allowed inside a group"));
+            }
+            self.lights.add(object.clone());
+        }
+        match self.open_groups.last_mut() {
+            Some((_, objects, _)) => objects.add(object),
+            None => self.world.add(object),
+        }
+        Ok(())
+    }
+
+    fn render(&mut self, line: &Line) -> Result<(), SceneError> {
+        line.expect_positional(0)?;
+        line.check_keys(&["width", "aspect_ratio", "samples", "max_depth"])?;
+        if line.get("width").is_some() {
+            self.image_width = line.positive_usize("width")?;
+        }
+        if line.get("aspect_ratio").is_some() {
+            self.aspect_ratio = line.number("aspect_ratio")?;
+            if self.aspect_ratio <= 0.0 {
+                return Err(line.error("`aspect_ratio` must be positive"));
+            }
+        }
+        if line.get("samples").is_some() {
+            self.samples_per_pixel = line.positive_usize("samples")?;
+        }
+        if line.get("max_depth").is_some() {
+            self.max_depth = line.positive_usize("max_depth")?;
+        }
+        Ok(())
+    }
+
+    fn camera(&mut self, line: &Line) -> Result<(), SceneError> {
+        line.expect_positional(0)?;
+        line.check_keys(&[
+            "lookfrom",
+            "lookat",
+            "vup",
+            "vfov",
+            "aperture",
+            "focus_dist",
+            "time",
+        ])?;
+        let cam = &mut self.camera;
+        cam.lookfrom = line.vec3_or("lookfrom", cam.lookfrom)?;
+        cam.lookat = line.vec3_or("lookat", cam.lookat)?;
+        cam.vup = line.vec3_or("vup", cam.vup)?;
+        cam.vfov = line.number_or("vfov", cam.vfov)?;
+        cam.aperture = line.number_or("aperture", cam.aperture)?;
+        cam.focus_dist = line.number_or("focus_dist", cam.focus_dist)?;
+        if line.get("time").is_some() {
+            cam.time = line.range("time")?;
+        }
+        Ok(())
+    }
+
+    fn texture(&mut self, line: &Line) -> Result<(), SceneError> {
+        line.expect_positional(2)?;
+        let name = line.positional[0];
+        let texture: Arc<dyn Texture> = match line.positional[1] {
+            "solid" => {
+                line.check_keys(&["color"])?;
+                Arc::new(SolidColor::new(line.vec3("color")?))
+            }
+            "checker" => {
+                line.check_keys(&["odd", "even"])?;
+                Arc::new(CheckerTexture::new_arc(
+                    self.texture_or_color(line, "odd")?,
+                    self.texture_or_color(line, "even")?,
+                ))
+            }
+            "noise" => {
+                line.check_keys(&["scale"])?;
+                Arc::new(NoiseTexture::new(line.number_or("scale", 1.0)?))
+            }
+            "image" => {
+                line.check_keys(&["file"])?;
+                let file = self.base_dir.join(line.require("file")?);
+                Arc::new(ImageTexture::open(&file).map_err(|e| {
+                    line.error(format!("cannot load image \"{}\": {}", file.display(), e))
+                })?)
+            }
+            other => return Err(line.error(format!("unknown texture type `{}`", other))),
+        };
+        self.textures.insert(name.to_string(), texture);
+        Ok(())
+    }
+
+    fn material(&mut self, line: &Line) -> Result<(), SceneError> {
+        line.expect_positional(2)?;
+        let name = line.positional[0];
+        let material: Arc<dyn Material> = match line.positional[1] {
+            "lambertian" => {
+                line.check_keys(&["color", "texture"])?;
+                Arc::new(Lambertian::new_arc(self.albedo(line)?))
+            }
+            "metal" => {
+                line.check_keys(&["color", "fuzz"])?;
+                Arc::new(Metal::new(
+                    &line.vec3("color")?,
+                    line.number_or("fuzz", 0.0)?,
+                ))
+            }
+            "dielectric" => {
+                line.check_keys(&["ior"])?;
+                Arc::new(Dielectric::new(line.number("ior")?))
+            }
+            "diffuse_light" => {
+                line.check_keys(&["color", "texture"])?;
+                Arc::new(DiffuseLight::new_arc(self.albedo(line)?))
+            }
+            "isotropic" => {
+                line.check_keys(&["color"])?;
+                Arc::new(Isotropic::new_color(line.vec3("color")?))
+            }
+            other => return Err(line.error(format!("unknown material type `{}`", other))),
+        };
+        self.materials.insert(name.to_string(), material);
+        Ok(())
+    }
+
+    fn shape(&self, line: &Line) -> Result<Arc<dyn Hit>, SceneError> {
+        const COMMON: [&str; 3] = ["material", "density", "medium_color"];
+        let keys = |own: &[&'static str]| -> Vec<&'static str> {
+            own.iter()
+                .chain(COMMON.iter())
+                .chain(TRANSFORMS.iter())
+                .copied()
+                .collect()
+        };
+        line.expect_positional_with_flags(0)?;
+        let object: Arc<dyn Hit> = match line.keyword {
+            "sphere" => {
+                line.check_keys(&keys(&["center", "radius"]))?;
+                Arc::new(Sphere::new(
+                    line.vec3("center")?,
+                    line.number("radius")?,
+                    self.shape_material(line)?,
+                ))
+            }
+            "moving_sphere" => {
+                line.check_keys(&keys(&["center0", "center1", "time", "radius"]))?;
+                let (time0, time1) = line.range("time")?;
+                Arc::new(MovingSphere::new(
+                    line.vec3("center0")?,
+                    line.vec3("center1")?,
+                    time0,
+                    time1,
+                    line.number("radius")?,
+                    self.shape_material(line)?,
+                ))
+            }
+            "xy_rect" => {
+                line.check_keys(&keys(&["x", "y", "k"]))?;
+                let (x0, x1) = line.range("x")?;
+                let (y0, y1) = line.range("y")?;
+                Arc::new(XYRect::new(
+                    self.shape_material(line)?,
+                    x0,
+                    x1,
+                    y0,
+                    y1,
+                    line.number("k")?,
+                ))
+            }
+            "xz_rect" => {
+                line.check_keys(&keys(&["x", "z", "k"]))?;
+                let (x0, x1) = line.range("x")?;
+                let (z0, z1) = line.range("z")?;
+                Arc::new(XZRect::new(
+                    self.shape_material(line)?,
+                    x0,
+                    x1,
+                    z0,
+                    z1,
+                    line.number("k")?,
+                ))
+            }
+            "yz_rect" => {
+                line.check_keys(&keys(&["y", "z", "k"]))?;
+                let (y0, y1) = line.range("y")?;
+                let (z0, z1) = line.range("z")?;
+                Arc::new(YZRect::new(
+                    self.shape_material(line)?,
+                    y0,
+                    y1,
+                    z0,
+                    z1,
+                    line.number("k")?,
+                ))
+            }
+            "triangle" => {
+                line.check_keys(&keys(&["v0", "v1", "v2"]))?;
+                Arc::new(Triangle::new(
+                    line.vec3("v0")?,
+                    line.vec3("v1")?,
+                    line.vec3("v2")?,
+                    self.shape_material(line)?,
+                ))
+            }
+            "obj" => {
+                line.check_keys(&keys(&["file"]))?;
+                // faces without `usemtl` get `material`, or the MTL default grey
+                let default_material = match line.get("material") {
+                    Some(_) => self.shape_material(line)?,
+                    None => Arc::new(Lambertian::new_color(Color::new(0.8, 0.8, 0.8))),
+                };
+                let file = self.base_dir.join(line.require("file")?);
+                let objects =
+                    obj::load(&file, default_material).map_err(|e| line.error(e.to_string()))?;
+                let mut meshes = HittableList::new();
+                for object in objects {
+                    meshes.add(Arc::new(object.mesh));
+                }
+                if meshes.objects.is_empty() {
+                    return Err(line.error(format!("\"{}\" has no faces", file.display())));
+                }
+                BvhNode::newnew(meshes, 0.0, 1.0).map_err(|e| line.error(e.to_string()))?
+            }
+            "ply" => {
+                line.check_keys(&keys(&["file"]))?;
+                let file = self.base_dir.join(line.require("file")?);
+                let mesh = ply::load(&file).map_err(|e| line.error(e.to_string()))?;
+                // without a `material` the vertex colors are used if there are any
+                let mesh = match line.get("material") {
+                    Some(_) => mesh.into_mesh(self.shape_material(line)?),
+                    None if mesh.colors.is_some() => {
+                        mesh.into_vertex_color_mesh().map(Option::unwrap)
+                    }
+                    None => {
+                        mesh.into_mesh(Arc::new(Lambertian::new_color(Color::new(0.8, 0.8, 0.8))))
+                    }
+                };
+                Arc::new(mesh.map_err(|e| line.error(e.to_string()))?)
+            }
+            "box" => {
+                line.check_keys(&keys(&["min", "max"]))?;
+                Arc::new(Bbox::new(
+                    &line.vec3("min")?,
+                    &line.vec3("max")?,
+                    self.shape_material(line)?,
+                ))
+            }
+            other => return Err(line.error(format!("unknown directive `{}`", other))),
+        };
+        Ok(object)
+    }
+
+    /// Applies the transforms, `flip` and `density` attributes of a line to an object.
+    fn finish_object(
+        &self,
+        line: &Line,
+        mut object: Arc<dyn Hit>,
+    ) -> Result<Arc<dyn Hit>, SceneError> {
+        // consecutive transforms are folded into one matrix
+        let mut transform: Option<Matrix4> = None;
+        for (key, value) in line.attrs.iter() {
+            let step = match *key {
+                "rotate_x" => Matrix4::rotation_x(line.parse_number(key, value)?),
+                "rotate_y" => Matrix4::rotation_y(line.parse_number(key, value)?),
+                "rotate_z" => Matrix4::rotation_z(line.parse_number(key, value)?),
+                "scale" => {
+                    let factors = if value.contains(',') {
+                        line.parse_vec3(key, value)?
+                    } else {
+                        line.parse_number(key, value)? * Vec3::ones()
+                    };
+                    Matrix4::scaling(&factors)
+                }
+                "translate" => Matrix4::translation(&line.parse_vec3(key, value)?),
+                "matrix" => {
+                    // the rows of the 3x4 affine part, or the whole 4x4 matrix
+                    let count = value.split(',').count();
+                    let v = line.parse_list(key, value, if count == 16 { 16 } else { 12 })?;
+                    let mut m = Matrix4::identity();
+                    for (i, x) in v.into_iter().enumerate() {
+                        m.m[i / 4][i % 4] = x;
+                    }
+                    m
+                }
+                _ => continue,
+            };
+            transform = Some(match transform {
+                Some(previous) => step * previous,
+                None => step,
+            });
+        }
+        if let Some(transform) = transform {
+            if transform.inverse().is_none() {
+                return Err(line.error("the transform is not invertible"));
+            }
+            // the light is sampled in object space, which only keeps solid angles
+            // under a similarity
+            if line.flag("light") && !transform.is_similarity() {
+                return Err(line.error(
+                    "`light` needs a transform that keeps angles: no uneven `scale`, \
+                     shear or projection",
+                ));
+            }
+            object = Arc::new(Instance::new(object, transform));
+        }
+        if line.flag("flip") {
+            object = Arc::new(FlipFace::new(object));
+        }
+        if line.get("density").is_some() {
+            let density = line.number("density")?;
+            if density <= 0.0 {
+                return Err(line.error("`density` must be positive"));
+            }
+            let color = line.vec3_or("medium_color", Color::ones())?;
+            object = Arc::new(ConstantMedium::new_color(object, density, color));
+        }
+        Ok(object)
+    }
+
+    fn shape_material(&self, line: &Line) -> Result<Arc<dyn Material>, SceneError> {
+        match line.get("material") {
+            Some(name) => self
+                .materials
+                .get(name)
+                .cloned()
+                .ok_or_else(|| line.error(format!("unknown material `{}`", name))),
+            // the boundary of a medium is never shaded, any material will do
+            None if line.get("density").is_some() => Ok(Arc::new(Isotropic::new_color(
+                line.vec3_or("medium_color", Color::ones())?,
+            ))),
+            None => Err(line.error("missing attribute `material`")),
+        }
+    }
+
+    fn albedo(&self, line: &Line) -> Result<Arc<dyn Texture>, SceneError> {
+        match (line.get("color"), line.get("texture")) {
+            (Some(_), Some(_)) => Err(line.error("give either `color` or `texture`, not both")),
+            (Some(color), None) => Ok(Arc::new(SolidColor::new(line.parse_vec3("color", color)?))),
+            (None, Some(name)) => self.named_texture(line, name),
+            (None, None) => Err(line.error("missing attribute `color` or `texture`")),
+        }
+    }
+
+    fn texture_or_color(&self, line: &Line, key: &str) -> Result<Arc<dyn Texture>, SceneError> {
+        let value = line.require(key)?;
+        if value.contains(',') {
+            Ok(Arc::new(SolidColor::new(line.parse_vec3(key, value)?)))
+        } else {
+            self.named_texture(line, value)
+        }
+    }
+
+    fn named_texture(&self, line: &Line, name: &str) -> Result<Arc<dyn Texture>, SceneError> {
+        self.textures
+            .get(name)
+            .cloned()
+            .ok_or_else(|| line.error(format!("unknown texture `{}`", name)))
+    }
+}
+
+/// One tokenized directive.
+struct Line<'a> {
+    number: usize,
+    keyword: &'a str,
+    positional: Vec<&'a str>,
+    attrs: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Line<'a> {
+    fn tokenize(number: usize, text: &'a str) -> Option<Self> {
+        let text = match text.find('#') {
+            Some(pos) => &text[..pos],
+            None => text,
+        };
+        let mut tokens = text.split_whitespace();
+        let keyword = tokens.next()?;
+        let mut positional = Vec::new();
+        let mut attrs = Vec::new();
+        for token in tokens {
+            match token.split_once('=') {
+                Some((key, value)) => attrs.push((key, value)),
+                None => positional.push(token),
+            }
+        }
+        Some(Self {
+            number,
+            keyword,
+            positional,
+            attrs,
+        })
+    }
+
+    fn error<S: Into<String>>(&self, message: S) -> SceneError {
+        SceneError::Parse {
+            line: self.number,
+            message: message.into(),
+        }
+    }
+
+    fn expect_positional(&self, count: usize) -> Result<(), SceneError> {
+        if self.positional.len() != count {
+            return Err(self.error(format!(
+                "`{}` takes {} positional argument(s), found {}",
+                self.keyword,
+                count,
+                self.positional.len()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Like `expect_positional`, but words past the first `count` are taken as flags.
+    fn expect_positional_with_flags(&self, count: usize) -> Result<(), SceneError> {
+        if self.positional.len() < count {
+            return self.expect_positional(count);
+        }
+        for flag in self.positional[count..].iter() {
+            if *flag != "flip" && *flag != "light" {
+                return Err(self.error(format!("unknown flag `{}`", flag)));
+            }
+        }
+        Ok(())
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.positional.contains(&name)
+    }
+
+    fn check_keys(&self, allowed: &[&str]) -> Result<(), SceneError> {
+        for (key, _) in self.attrs.iter() {
+            if !allowed.contains(key) {
+                return Err(self.error(format!(
+                    "unknown attribute `{}` for `{}`",
+                    key, self.keyword
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Option<&'a str> {
+        self.attrs
+            .iter()
+            .rev()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| *v)
+    }
+
+    fn require(&self, key: &str) -> Result<&'a str, SceneError> {
+        self.get(key)
+            .ok_or_else(|| self.error(format!("missing attribute `{}`", key)))
+    }
+
+    fn parse_number(&self, key: &str, value: &str) -> Result<f64, SceneError> {
+        match value.parse::<f64>() {
+            Ok(x) if x.is_finite() => Ok(x),
+            _ => Err(self.error(format!(
+                "expected a number for `{}`, found `{}`",
+                key, value
+            ))),
+        }
+    }
+
+    fn parse_list(&self, key: &str, value: &str, len: usize) -> Result<Vec<f64>, SceneError> {
+        let parts: Vec<&str> = value.split(',').collect();
+        if parts.len() != len {
+            return Err(self.error(format!(
+                "expected {} comma-separated numbers for `{}`, found `{}`",
+                len, key, value
+            )));
+        }
+        parts.iter().map(|p| self.parse_number(key, p)).collect()
+    }
+
+    fn parse_vec3(&self, key: &str, value: &str) -> Result<Vec3, SceneError> {
+        let v = self.parse_list(key, value, 3)?;
+        Ok(Vec3::new(v[0], v[1], v[2]))
+    }
+
+    fn number(&self, key: &str) -> Result<f64, SceneError> {
+        self.parse_number(key, self.require(key)?)
+    }
+
+    fn number_or(&self, key: &str, default: f64) -> Result<f64, SceneError> {
+        match self.get(key) {
+            Some(value) => self.parse_number(key, value),
+            None => Ok(default),
+        }
+    }
+
+    fn positive_usize(&self, key: &str) -> Result<usize, SceneError> {
+        let value = self.require(key)?;
+        match value.parse::<usize>() {
+            Ok(x) if x > 0 => Ok(x),
+            _ => Err(self.error(format!(
+                "expected a positive integer for `{}`, found `{}`",
+                key, value
+            ))),
+        }
+    }
+
+    fn vec3(&self, key: &str) -> Result<Vec3, SceneError> {
+        self.parse_vec3(key, self.require(key)?)
+    }
+
+    fn vec3_or(&self, key: &str, default: Vec3) -> Result<Vec3, SceneError> {
+        match self.get(key) {
+            Some(value) => self.parse_vec3(key, value),
+            None => Ok(default),
+        }
+    }
+
+    fn range(&self, key: &str) -> Result<(f64, f64), SceneError> {
+        let v = self.parse_list(key, self.require(key)?, 2)?;
+        Ok((v[0], v[1]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ray;
+
+    fn parse_err(src: &str) -> (usize, String) {
+        match parse(src, Path::new("")) {
+            Err(SceneError::Parse { line, message }) => (line, message),
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn test_parse_scene() {
+        let src = "
+            # a comment
+            render width=200 aspect_ratio=1.0 samples=8 max_depth=5
+            background 0.1,0.2,0.3
+            camera lookfrom=0,0,-5 lookat=0,0,0 vfov=40
+            material white lambertian color=0.73,0.73,0.73
+            material light diffuse_light color=4,4,4
+            sphere center=0,0,0 radius=1 material=white   # trailing comment
+            xz_rect x=-1,1 z=-1,1 k=3 material=light flip light
+            group boxes
+              box min=0,0,0 max=1,1,1 material=white
+            end
+            instance boxes rotate_y=15 translate=2,0,0
+            instance boxes scale=0.5,2,0.5 rotate_z=30 translate=0,5,0
+        ";
+        let desc = parse(src, Path::new("")).unwrap();
+        assert_eq!(desc.image_width, 200);
+        assert_eq!(desc.samples_per_pixel, 8);
+        assert_eq!(desc.max_depth, 5);
+        assert_eq!(desc.scene.background, Color::new(0.1, 0.2, 0.3));
+        assert_eq!(desc.scene.lights.objects.len(), 1);
+        let r = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let rec = desc.scene.world.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_bundled_scenes_parse() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../scenes");
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if let Err(e) = load(&path) {
+                panic!("{}: {}", path.display(), e);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_errors_report_line() {
+        let (line, message) =
+            parse_err("material m lambertian color=1,1,1\nsphere center=0,0 radius=1 material=m");
+        assert_eq!(line, 2);
+        assert!(message.contains("center"));
+        let (line, message) = parse_err("\n\nsphere center=0,0,0 radius=1 material=nope");
+        assert_eq!(line, 3);
+        assert!(message.contains("unknown material"));
+        let (line, message) = parse_err("group g\nend");
+        assert_eq!(line, 2);
+        assert!(message.contains("empty"));
+        let (line, message) =
+            parse_err("material m metal color=1,1,1\nsphere center=0,0,0 radius=1 material=m\nend");
+        assert_eq!(line, 3);
+        assert!(message.contains("without a matching"));
+        let (line, message) = parse_err(
+            "material m metal color=1,1,1\ngroup g\nsphere center=0,0,0 radius=1 material=m",
+        );
+        assert_eq!(line, 2);
+        assert!(message.contains("never closed"));
+        let (line, message) = parse_err(
+            "material l diffuse_light color=4,4,4\n\
+             sphere center=0,0,0 radius=1 material=l light scale=1,2,1",
+        );
+        assert_eq!(line, 2);
+        assert!(message.contains("keeps angles"));
+    }
+}
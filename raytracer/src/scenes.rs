@@ -0,0 +1,542 @@
+//! The scenes that come with the renderer.
+use crate::aarect::{XYRect, XZRect, YZRect};
+use crate::bvh::BvhNode;
+use crate::camera::Camera;
+use crate::hittable::{FlipFace, RotateY, Translate};
+use crate::hittable_list::HittableList;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal};
+use crate::medium::ConstantMedium;
+use crate::r#box::Bbox;
+use crate::rtweekend::*;
+use crate::scene::{Scene, SceneDescription};
+use crate::sphere::{MovingSphere, Sphere};
+use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture};
+use crate::vec3::{Color, Point3, Vec3};
+use std::sync::Arc;
+
+/// Names accepted by [`builtin`].
+pub const NAMES: [&str; 8] = [
+    "random",
+    "two_spheres",
+    "two_perlin_spheres",
+    "earth",
+    "simple_light",
+    "cornell_box",
+    "cornell_smoke",
+    "final",
+];
+
+/// The cover of the first book. Its spheres are placed at random, the same way on
+/// every call.
+pub fn random_scene() -> HittableList {
+    with_thread_seed(SCENE_SEED, build_random_scene)
+}
+fn build_random_scene() -> HittableList {
+    let mut world = HittableList::new();
+    let checker = Arc::new(CheckerTexture::new_color(
+        Color::new(0.9, 0.9, 0.9),
+        Color::new(0.2, 0.3, 0.1),
+    ));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Lambertian::new_arc(checker),
+    )));
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = random_f64();
+            let center = Vec3::new(
+                a as f64 + 0.9 * random_f64(),
+                0.2,
+                b as f64 + 0.9 * random_f64(),
+            );
+            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                if choose_mat < 0.8 {
+                    let albedo = Vec3::random_f64() * Vec3::random_f64();
+                    let sphere_material = Lambertian::new_color(albedo);
+                    let center2 = center + Vec3::new(0.0, random(0.0, 0.5), 0.0);
+                    world.add(Arc::new(MovingSphere::new(
+                        center,
+                        center2,
+                        0.0,
+                        1.0,
+                        0.2,
+                        sphere_material,
+                    )));
+                } else if choose_mat < 0.95 {
+                    let albedo = Vec3::random(0.5, 1.0);
+                    let fuzz = random(0.0, 0.5);
+                    let sphere_material = Metal::new(&albedo, fuzz);
+                    world.add(Arc::new(Sphere::new(center, 0.2, sphere_material)));
+                } else {
+                    let sphere_material = Dielectric::new(1.5);
+                    world.add(Arc::new(Sphere::new(center, 0.2, sphere_material)));
+                }
+            }
+        }
+    }
+    let material1 = Dielectric::new(1.5);
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(0.0, 1.0, 0.0),
+        1.0,
+        material1,
+    )));
+    let material2 = Lambertian::new_color(Color::new(0.4, 0.2, 0.1));
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(-4.0, 1.0, 0.0),
+        1.0,
+        material2,
+    )));
+    let material3 = Metal::new(&Vec3::new(0.7, 0.6, 0.5), 0.0);
+    world.add(Arc::new(Sphere::new(
+        Vec3::new(4.0, 1.0, 0.0),
+        1.0,
+        material3,
+    )));
+    world
+}
+
+pub fn two_spheres() -> HittableList {
+    let checker = Arc::new(CheckerTexture::new_color(
+        Color::new(0.9, 0.9, 0.9),
+        Color::new(0.2, 0.3, 0.1),
+    ));
+    let mut objects = HittableList::new();
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -10.0, 0.0),
+        10.0,
+        Lambertian::new_arc(checker.clone()),
+    )));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 10.0, 0.0),
+        10.0,
+        Lambertian::new_arc(checker),
+    )));
+    objects
+}
+pub fn two_perlin_spheres() -> HittableList {
+    let mut obj = HittableList::new();
+    let pertext = Arc::new(NoiseTexture::new(4.0));
+    obj.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Lambertian::new_arc(pertext.clone()),
+    )));
+    obj.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 2.0, 0.0),
+        2.0,
+        Lambertian::new_arc(pertext),
+    )));
+    obj
+}
+pub fn earth() -> HittableList {
+    let earth_texture = ImageTexture::new();
+    let mut obj = HittableList::new();
+    let earth_surface = Lambertian::new_arc(Arc::new(earth_texture));
+    obj.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 0.0, 0.0),
+        2.0,
+        earth_surface,
+    )));
+    obj
+}
+pub fn simple_light() -> HittableList {
+    let mut obj = HittableList::new();
+    let pertext = Arc::new(NoiseTexture::new(4.0));
+    obj.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Lambertian::new_arc(pertext.clone()),
+    )));
+    obj.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 2.0, 0.0),
+        2.0,
+        Lambertian::new_arc(pertext),
+    )));
+    let difflight = Arc::new(DiffuseLight::new_color(Color::new(4.0, 4.0, 4.0)));
+    obj.add(Arc::new(XYRect::new(difflight, 3.0, 5.0, 1.0, 3.0, -2.0)));
+    obj.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 7.0, 0.0),
+        2.0,
+        DiffuseLight::new_color(Color::new(4.0, 4.0, 4.0)),
+    )));
+    obj
+}
+pub fn cornell_box() -> HittableList {
+    let mut obj = HittableList::new();
+    let red = Arc::new(Lambertian::new_color(Color::new(0.65, 0.05, 0.05)));
+    let white = Arc::new(Lambertian::new_color(Color::new(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::new_color(Color::new(0.12, 0.45, 0.15)));
+    let light = Arc::new(DiffuseLight::new_color(Color::new(15.0, 15.0, 15.0)));
+    obj.add(Arc::new(YZRect::new(green, 0.0, 555.0, 0.0, 555.0, 555.0)));
+    obj.add(Arc::new(YZRect::new(red, 0.0, 555.0, 0.0, 555.0, 0.0)));
+    obj.add(Arc::new(FlipFace::new(Arc::new(XZRect::new(
+        light, 213.0, 343.0, 227.0, 332.0, 554.0,
+    )))));
+    obj.add(Arc::new(XZRect::new(
+        white.clone(),
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        0.0,
+    )));
+    obj.add(Arc::new(XZRect::new(
+        white.clone(),
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+    )));
+    obj.add(Arc::new(XYRect::new(
+        white.clone(),
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+    )));
+    let box1 = Arc::new(Translate::new(
+        Arc::new(RotateY::new(
+            Arc::new(Bbox::new(
+                &Point3::zero(),
+                &Point3::new(165.0, 330.0, 165.0),
+                white.clone(),
+            )),
+            15.0,
+        )),
+        Vec3::new(265.0, 0.0, 295.0),
+    ));
+    obj.add(box1);
+
+    let box2 = Arc::new(Bbox::new(
+        &Point3::zero(),
+        &Point3::new(165.0, 165.0, 165.0),
+        white,
+    ));
+    let box2 = Arc::new(RotateY::new(box2, -18.0));
+    let box2 = Arc::new(Translate::new(box2, Vec3::new(130.0, 0.0, 65.0)));
+    obj.add(box2);
+    obj
+}
+pub fn cornell_smoke() -> HittableList {
+    let mut obj = HittableList::new();
+    let red = Arc::new(Lambertian::new_color(Color::new(0.65, 0.05, 0.05)));
+    let white = Arc::new(Lambertian::new_color(Color::new(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::new_color(Color::new(0.12, 0.45, 0.15)));
+    let light = Arc::new(DiffuseLight::new_color(Color::new(15.0, 15.0, 15.0)));
+    obj.add(Arc::new(YZRect::new(green, 0.0, 555.0, 0.0, 555.0, 555.0)));
+    obj.add(Arc::new(YZRect::new(red, 0.0, 555.0, 0.0, 555.0, 0.0)));
+    obj.add(Arc::new(XZRect::new(
+        light, 213.0, 343.0, 227.0, 332.0, 554.0,
+    )));
+    obj.add(Arc::new(XZRect::new(
+        white.clone(),
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        0.0,
+    )));
+    obj.add(Arc::new(XZRect::new(
+        white.clone(),
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+    )));
+    obj.add(Arc::new(XYRect::new(
+        white.clone(),
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+    )));
+    let box1 = Arc::new(Translate::new(
+        Arc::new(RotateY::new(
+            Arc::new(Bbox::new(
+                &Point3::zero(),
+                &Point3::new(165.0, 330.0, 165.0),
+                white.clone(),
+            )),
+            15.0,
+        )),
+        Vec3::new(265.0, 0.0, 295.0),
+    ));
+
+    let box2 = Arc::new(Bbox::new(
+        &Point3::zero(),
+        &Point3::new(165.0, 165.0, 165.0),
+        white,
+    ));
+    let box2 = Arc::new(RotateY::new(box2, -18.0));
+    let box2 = Arc::new(Translate::new(box2, Vec3::new(130.0, 0.0, 65.0)));
+    obj.add(Arc::new(ConstantMedium::new_color(
+        box1,
+        0.01,
+        Color::zero(),
+    )));
+    obj.add(Arc::new(ConstantMedium::new_color(
+        box2,
+        0.01,
+        Color::ones(),
+    )));
+    obj
+}
+/// The cover of the second book, the same on every call.
+pub fn final_scene() -> HittableList {
+    with_thread_seed(SCENE_SEED, build_final_scene)
+}
+fn build_final_scene() -> HittableList {
+    let mut boxes1 = HittableList::new();
+    let ground = Arc::new(Lambertian::new_color(Color::new(0.48, 0.83, 0.53)));
+    let boxes_per_side = 20;
+    for i in 0..boxes_per_side {
+        for j in 0..boxes_per_side {
+            let w = 100.0;
+            let x0 = -1000.0 + i as f64 * w;
+            let z0 = -1000.0 + j as f64 * w;
+            let y0 = 0.0;
+            let x1 = x0 + w;
+            let y1 = random(1.0, 101.0);
+            let z1 = z0 + w;
+            boxes1.add(Arc::new(Bbox::new(
+                &Point3::new(x0, y0, z0),
+                &Point3::new(x1, y1, z1),
+                ground.clone(),
+            )));
+        }
+    }
+    let mut obj = HittableList::new();
+    obj.add(BvhNode::newnew(boxes1, 0.0, 1.0).expect("the boxes are bounded"));
+    let light = Arc::new(DiffuseLight::new_color(Color::new(7.0, 7.0, 7.0)));
+    obj.add(Arc::new(XZRect::new(
+        light, 123.0, 423.0, 147.0, 412.0, 554.0,
+    )));
+    let center1 = Point3::new(400.0, 400.0, 400.0);
+    let center2 = center1 + Vec3::new(30.0, 0.0, 0.0);
+    let moving_sphere_material = Lambertian::new_color(Color::new(0.7, 0.3, 0.1));
+    obj.add(Arc::new(MovingSphere::new(
+        center1,
+        center2,
+        0.0,
+        1.0,
+        50.0,
+        moving_sphere_material,
+    )));
+    obj.add(Arc::new(Sphere::new(
+        Point3::new(260.0, 150.0, 45.0),
+        50.0,
+        Dielectric::new(1.5),
+    )));
+    obj.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 150.0, 145.0),
+        50.0,
+        Metal::new(&Color::new(0.8, 0.8, 0.8), 1.0),
+    )));
+    let mut boundary = Arc::new(Sphere::new(
+        Point3::new(360.0, 150.0, 145.0),
+        70.0,
+        Dielectric::new(1.05),
+    ));
+    obj.add(boundary.clone());
+    obj.add(Arc::new(ConstantMedium::new_color(
+        boundary,
+        0.2,
+        Color::new(0.2, 0.4, 0.9),
+    )));
+    boundary = Arc::new(Sphere::new(Point3::zero(), 5000.0, Dielectric::new(1.5)));
+    obj.add(Arc::new(ConstantMedium::new_color(
+        boundary,
+        0.0001,
+        Color::ones(),
+    )));
+    let emat = Lambertian::new_arc(Arc::new(ImageTexture::new()));
+    obj.add(Arc::new(Sphere::new(
+        Point3::new(400.0, 200.0, 400.0),
+        100.0,
+        emat,
+    )));
+    let pertext = Arc::new(NoiseTexture::new(0.1));
+    obj.add(Arc::new(Sphere::new(
+        Point3::new(220.0, 280.0, 300.0),
+        80.0,
+        Lambertian::new_arc(pertext),
+    )));
+    let mut boxes2 = HittableList::new();
+    let ns = 1000;
+    for _ in 0..ns {
+        boxes2.add(Arc::new(Sphere::new(
+            Point3::random(0.0, 165.0),
+            10.0,
+            Lambertian::new_color(Color::new(0.73, 0.73, 0.73)),
+        )));
+    }
+    obj.add(Arc::new(Translate::new(
+        Arc::new(RotateY::new(
+            BvhNode::newnew(boxes2, 0.0, 1.0).expect("the spheres are bounded"),
+            15.0,
+        )),
+        Vec3::new(-100.0, 270.0, 395.0),
+    )));
+    obj
+}
+/// The scenes that come with the renderer, selected by name (see [`NAMES`]).
+pub fn builtin(name: &str) -> Option<SceneDescription> {
+    with_thread_seed(SCENE_SEED, || build_builtin(name))
+}
+fn build_builtin(name: &str) -> Option<SceneDescription> {
+    //image
+    const ASPECT_RATIO: f64 = 16.0 / 9.0;
+    const IMAGE_WIDTH: usize = 400;
+    // const IMAGE_HEIGHT: usize = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as usize;
+    let mut samples_per_pixel: usize = 100;
+    let max_depth: usize = 50;
+    let mut width = IMAGE_WIDTH;
+    let mut aspect_ratio = ASPECT_RATIO;
+
+    //world
+    let obj;
+    // emitters to sample directly, their materials are never shaded
+    let mut lights = HittableList::new();
+    let lookfrom;
+    let lookat;
+    let vfov;
+    let mut aperture = 0.0;
+    let background;
+    match name {
+        "random" => {
+            obj = random_scene();
+            lookfrom = Point3::new(13.0, 2.0, 3.0);
+            lookat = Point3::new(0.0, 0.0, 0.0);
+            background = Color::new(0.7, 0.8, 1.0);
+            vfov = 20.0;
+            aperture = 0.1;
+        }
+        "two_spheres" => {
+            obj = two_spheres();
+            lookfrom = Point3::new(13.0, 2.0, 3.0);
+            lookat = Point3::new(0.0, 0.0, 0.0);
+            background = Color::new(0.7, 0.8, 1.0);
+            vfov = 20.0;
+        }
+        "two_perlin_spheres" => {
+            obj = two_perlin_spheres();
+            lookfrom = Point3::new(13.0, 2.0, 3.0);
+            lookat = Point3::new(0.0, 0.0, 0.0);
+            background = Color::new(0.7, 0.8, 1.0);
+            vfov = 20.0;
+        }
+        "earth" => {
+            obj = earth();
+            lookfrom = Point3::new(13.0, 2.0, 3.0);
+            lookat = Point3::new(0.0, 0.0, 0.0);
+            background = Color::new(0.7, 0.8, 1.0);
+            vfov = 20.0;
+        }
+        "simple_light" => {
+            obj = simple_light();
+            let light = Arc::new(DiffuseLight::new_color(Color::new(4.0, 4.0, 4.0)));
+            lights.add(Arc::new(XYRect::new(
+                light.clone(),
+                3.0,
+                5.0,
+                1.0,
+                3.0,
+                -2.0,
+            )));
+            lights.add(Arc::new(Sphere::new(
+                Point3::new(0.0, 7.0, 0.0),
+                2.0,
+                light,
+            )));
+            samples_per_pixel = 400;
+            background = Color::zero();
+            lookfrom = Point3::new(26.0, 3.0, 6.0);
+            lookat = Point3::new(0.0, 2.0, 0.0);
+            vfov = 20.0;
+        }
+        "cornell_box" => {
+            obj = cornell_box();
+            lights.add(Arc::new(XZRect::new(
+                Arc::new(DiffuseLight::new_color(Color::new(15.0, 15.0, 15.0))),
+                213.0,
+                343.0,
+                227.0,
+                332.0,
+                554.0,
+            )));
+            aspect_ratio = 1.0;
+            width = 600;
+            samples_per_pixel = 1000;
+            background = Color::zero();
+            lookfrom = Point3::new(278.0, 278.0, -800.0);
+            lookat = Point3::new(278.0, 278.0, 0.0);
+            vfov = 40.0;
+        }
+        "cornell_smoke" => {
+            obj = cornell_smoke();
+            lights.add(Arc::new(XZRect::new(
+                Arc::new(DiffuseLight::new_color(Color::new(15.0, 15.0, 15.0))),
+                213.0,
+                343.0,
+                227.0,
+                332.0,
+                554.0,
+            )));
+            aspect_ratio = 1.0;
+            width = 600;
+            samples_per_pixel = 200;
+            background = Color::zero();
+            lookfrom = Point3::new(278.0, 278.0, -800.0);
+            lookat = Point3::new(278.0, 278.0, 0.0);
+            vfov = 40.0;
+        }
+        "final" => {
+            obj = final_scene();
+            lights.add(Arc::new(XZRect::new(
+                Arc::new(DiffuseLight::new_color(Color::new(7.0, 7.0, 7.0))),
+                123.0,
+                423.0,
+                147.0,
+                412.0,
+                554.0,
+            )));
+            aspect_ratio = 1.0;
+            width = 800;
+            // width = 300;
+            // samples_per_pixel = 10000;
+            samples_per_pixel = 100;
+            background = Color::zero();
+            lookfrom = Point3::new(478.0, 278.0, -600.0);
+            lookat = Point3::new(278.0, 278.0, 0.0);
+            vfov = 40.0;
+        }
+        _ => return None,
+    }
+    //camera
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus = 10.0;
+    let camera = Camera::new(
+        lookfrom,
+        lookat,
+        vup,
+        vfov,
+        aspect_ratio,
+        aperture,
+        dist_to_focus,
+        0.0,
+        1.0,
+    );
+    Some(SceneDescription {
+        scene: Scene::new(obj, lights, camera, background)
+            .expect("the built-in scenes have valid bounds"),
+        image_width: width,
+        aspect_ratio,
+        samples_per_pixel,
+        max_depth,
+    })
+}
@@ -1,5 +1,6 @@
 use crate::aabb::*;
 use crate::material::Material;
+use crate::onb::Onb;
 use crate::{hittable::*, vec3::*, Ray};
 use std::f64::consts::PI;
 
@@ -23,26 +24,31 @@ pub fn get_sphere_uv(p: &Point3) -> (f64, f64) {
     let phi = (-p.z()).atan2(p.x()) + PI;
     (phi / (2.0 * PI), theta / PI)
 }
-impl<M: Material> Hit for Sphere<M> {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = r.orig() - self.center.clone();
-        let a = r.dir().squared_length();
-        let half_b = oc.dot(r.dir());
-        let c = oc.squared_length() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0.0 {
-            return None;
-        }
-        let sqrtd = discriminant.sqrt();
-        let mut root = (-half_b - sqrtd) / a;
+/// The nearest `t` in `t_min..t_max` where `r` meets the sphere.
+fn sphere_root(center: &Point3, radius: f64, r: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+    let oc = r.orig() - *center;
+    let a = r.dir().squared_length();
+    let half_b = oc.dot(r.dir());
+    let c = oc.squared_length() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    let mut root = (-half_b - sqrtd) / a;
+    if root < t_min || t_max < root {
+        root = (-half_b + sqrtd) / a;
         if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
-            if root < t_min || t_max < root {
-                return None;
-            }
+            return None;
         }
+    }
+    Some(root)
+}
+impl<M: Material> Hit for Sphere<M> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let root = sphere_root(&self.center, self.radius, r, t_min, t_max)?;
         let p = r.at(root);
-        let outward_normal = (p.clone() - self.center.clone()) / self.radius;
+        let outward_normal = (p - self.center) / self.radius;
         let (u, v) = get_sphere_uv(&outward_normal);
         Some(HitRecord::new(
             p,
@@ -54,12 +60,40 @@ impl<M: Material> Hit for Sphere<M> {
             &self.material,
         ))
     }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        sphere_root(&self.center, self.radius, r, t_min, t_max).is_some()
+    }
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AaBb> {
         Some(AaBb::new(
-            self.center.clone() - Vec3::new(self.radius, self.radius, self.radius),
-            self.center.clone() + Vec3::new(self.radius, self.radius, self.radius),
+            self.center - Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs()),
+            self.center + Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs()),
         ))
     }
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        let distance_squared = (self.center - *o).squared_length();
+        // from inside or on the sphere there is no cone of directions to sample
+        if distance_squared <= self.radius * self.radius {
+            return 0.0;
+        }
+        if !self.occluded(&Ray::new(*o, *v, 0.0), 0.001, f64::INFINITY) {
+            return 0.0;
+        }
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
+    fn random(&self, o: &Vec3) -> Vec3 {
+        let direction = self.center - *o;
+        let distance_squared = direction.squared_length();
+        if distance_squared <= self.radius * self.radius {
+            return random_unit_vector();
+        }
+        let mut uvw = Onb {
+            axis: vec![Vec3::zero(), Vec3::zero(), Vec3::zero()],
+        };
+        uvw.build_from_w(&direction);
+        uvw.local_vector(&random_to_sphere(self.radius, distance_squared))
+    }
 }
 
 pub struct MovingSphere<M: Material> {
@@ -89,31 +123,15 @@ impl<M: Material> MovingSphere<M> {
         }
     }
     pub fn center(&self, time: f64) -> Vec3 {
-        self.center0.clone()
-            + ((time - self.time0) / (self.time1 - self.time0))
-                * (self.center1.clone() - self.center0.clone())
+        self.center0
+            + ((time - self.time0) / (self.time1 - self.time0)) * (self.center1 - self.center0)
     }
 }
 impl<M: Material> Hit for MovingSphere<M> {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = r.orig() - self.center(r.time());
-        let a = r.dir().squared_length();
-        let half_b = oc.dot(r.dir());
-        let c = oc.squared_length() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0.0 {
-            return None;
-        }
-        let sqrtd = discriminant.sqrt();
-        let mut root = (-half_b - sqrtd) / a;
-        if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
-            if root < t_min || t_max < root {
-                return None;
-            }
-        }
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let root = sphere_root(&self.center(r.time()), self.radius, r, t_min, t_max)?;
         let p = r.at(root);
-        let outward_normal = (p.clone() - self.center(r.time())) / self.radius;
+        let outward_normal = (p - self.center(r.time())) / self.radius;
         let (u, v) = get_sphere_uv(&outward_normal);
         Some(HitRecord::new(
             p,
@@ -125,15 +143,41 @@ impl<M: Material> Hit for MovingSphere<M> {
             &self.material,
         ))
     }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        sphere_root(&self.center(r.time()), self.radius, r, t_min, t_max).is_some()
+    }
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AaBb> {
         let box0 = AaBb::new(
-            self.center(_time0) - Vec3::new(self.radius, self.radius, self.radius),
-            self.center(_time0) + Vec3::new(self.radius, self.radius, self.radius),
+            self.center(_time0)
+                - Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs()),
+            self.center(_time0)
+                + Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs()),
         );
         let box1 = AaBb::new(
-            self.center(_time1) - Vec3::new(self.radius, self.radius, self.radius),
-            self.center(_time1) + Vec3::new(self.radius, self.radius, self.radius),
+            self.center(_time1)
+                - Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs()),
+            self.center(_time1)
+                + Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs()),
         );
         Some(surrounding_box(box0, box1))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_sampling_from_inside() {
+        let light = Sphere::new(Vec3::zero(), 1.0, Lambertian::new_color(Color::ones()));
+        let outside = Point3::new(0.0, 0.0, -3.0);
+        let towards = light.random(&outside);
+        assert!(light.pdf_value(&outside, &towards) > 0.0);
+        for o in [Point3::zero(), Point3::new(0.0, 1.0, 0.0)] {
+            let v = light.random(&o);
+            assert!((v.length() - 1.0).abs() < 1e-6);
+            assert_eq!(light.pdf_value(&o, &v), 0.0);
+        }
+    }
+}
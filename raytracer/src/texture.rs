@@ -3,6 +3,7 @@ use crate::vec3::{Color, Point3};
 // extern crate stb_image;
 // use stb_image::image::*;
 use crate::rtweekend::clamp;
+use std::path::Path;
 use std::sync::Arc;
 
 pub trait Texture: Send + Sync {
@@ -23,7 +24,7 @@ impl SolidColor {
 }
 impl Texture for SolidColor {
     fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
-        self.color_value.clone()
+        self.color_value
     }
 }
 
@@ -40,9 +41,9 @@ impl CheckerTexture {
             even: Arc::new(SolidColor::new(c2)),
         }
     }
-    // pub fn new_arc(odd: Arc<dyn Texture>, even: Arc<dyn Texture>) -> Self {
-    //     Self { odd, even }
-    // }
+    pub fn new_arc(odd: Arc<dyn Texture>, even: Arc<dyn Texture>) -> Self {
+        Self { odd, even }
+    }
 }
 impl Texture for CheckerTexture {
     fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
@@ -78,20 +79,26 @@ pub struct ImageTexture {
     height: usize,
     bytes_per_scanline: usize,
 }
+impl Default for ImageTexture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl ImageTexture {
     const BYTES_PER_PIXEL: usize = 3;
     pub fn new() -> Self {
-        let img = image::open("earthmap.jpg")
-            .expect("Could not find the image")
-            .to_rgb8();
+        ImageTexture::open("earthmap.jpg").expect("Could not find the image")
+    }
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, image::ImageError> {
+        let img = image::open(path)?.to_rgb8();
         let (width, height) = img.dimensions();
         let data = img.as_raw();
-        Self {
+        Ok(Self {
             data: data.clone(),
             width: width as usize,
             height: height as usize,
             bytes_per_scanline: ImageTexture::BYTES_PER_PIXEL * width as usize,
-        }
+        })
     }
 }
 
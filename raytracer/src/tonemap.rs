@@ -0,0 +1,178 @@
+//! Tone mapping: bringing the unbounded radiance of a film into `[0, 1]` for display,
+//! before it is sRGB-encoded.
+use crate::film::Film;
+use crate::vec3::Color;
+
+/// How radiance above 1 is brought down to the display range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMap {
+    /// Clips every channel to `[0, 1]`, so bright areas burn out to white.
+    Clamp,
+    /// Reinhard's `L / (1 + L)` on the luminance, which keeps the hue. Nothing
+    /// reaches white.
+    Reinhard,
+    /// Reinhard with a white point: luminance `white` maps to 1. `None` takes the
+    /// brightest pixel of the film.
+    ReinhardExtended { white: Option<f64> },
+    /// John Hable's filmic curve from Uncharted 2, per channel, with its white point
+    /// of 11.2 and exposure bias of 2.
+    Hable,
+    /// The ACES reference rendering and sRGB output transforms, as fitted by Stephen
+    /// Hill. Highlights desaturate towards white.
+    Aces,
+}
+
+impl ToneMap {
+    pub const NAMES: [&'static str; 5] =
+        ["clamp", "reinhard", "reinhard-extended", "hable", "aces"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clamp" => Some(Self::Clamp),
+            "reinhard" => Some(Self::Reinhard),
+            "reinhard-extended" => Some(Self::ReinhardExtended { white: None }),
+            "hable" => Some(Self::Hable),
+            "aces" => Some(Self::Aces),
+            _ => None,
+        }
+    }
+
+    /// The film scaled by `2^exposure` and tone mapped.
+    pub fn apply(self, film: &Film, exposure: f64) -> Film {
+        let scale = exposure.exp2();
+        let white = match self {
+            Self::ReinhardExtended { white: Some(white) } => white,
+            Self::ReinhardExtended { white: None } => {
+                let brightest = (0..film.height())
+                    .flat_map(|y| (0..film.width()).map(move |x| (x, y)))
+                    .map(|(x, y)| luminance(&film.pixel(x, y)))
+                    .filter(|l| l.is_finite())
+                    .fold(0.0, f64::max);
+                scale * brightest
+            }
+            _ => 1.0,
+        };
+        let mut mapped = Film::new(film.width(), film.height());
+        for y in 0..film.height() {
+            for x in 0..film.width() {
+                let color = self.map(film.pixel(x, y) * scale, white);
+                mapped.set_pixel(x, y, &color);
+            }
+        }
+        mapped
+    }
+
+    fn map(self, color: Color, white: f64) -> Color {
+        match self {
+            Self::Clamp => color.max(Color::zero()).min(Color::ones()),
+            Self::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+            Self::ReinhardExtended { .. } => {
+                // a white point of 0 would be a black film anyway
+                let white2 = (white * white).max(f64::MIN_POSITIVE);
+                scale_luminance(color, |l| (l * (1.0 + l / white2) / (1.0 + l)).min(1.0))
+            }
+            Self::Hable => {
+                const WHITE: f64 = 11.2;
+                let curve = |x: f64| {
+                    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+                    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+                };
+                let color = color.max(Color::zero());
+                let mapped = Color::new(
+                    curve(2.0 * color.x()),
+                    curve(2.0 * color.y()),
+                    curve(2.0 * color.z()),
+                ) / curve(WHITE);
+                mapped.min(Color::ones())
+            }
+            Self::Aces => aces_fitted(color),
+        }
+    }
+}
+
+/// Rec. 709 luminance of a linear color.
+pub fn luminance(color: &Color) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
+/// Scales `color` so that its luminance goes from `l` to `curve(l)`.
+fn scale_luminance(color: Color, curve: impl Fn(f64) -> f64) -> Color {
+    let color = color.max(Color::zero());
+    let l = luminance(&color);
+    if l <= 0.0 {
+        return Color::zero();
+    }
+    (color * (curve(l) / l)).min(Color::ones())
+}
+
+fn aces_fitted(color: Color) -> Color {
+    // sRGB to the ACES rendering space, with the RRT saturation folded in
+    const INPUT: [[f64; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    // back from the ODT output to linear sRGB
+    const OUTPUT: [[f64; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+    let multiply = |m: &[[f64; 3]; 3], c: Color| {
+        let row = |r: &[f64; 3]| r[0] * c.x() + r[1] * c.y() + r[2] * c.z();
+        Color::new(row(&m[0]), row(&m[1]), row(&m[2]))
+    };
+    let rrt_and_odt = |v: f64| {
+        (v * (v + 0.024_578_6) - 0.000_090_537) / (v * (0.983_729 * v + 0.432_951) + 0.238_081)
+    };
+    let c = multiply(&INPUT, color.max(Color::zero()));
+    let c = Color::new(rrt_and_odt(c.x()), rrt_and_odt(c.y()), rrt_and_odt(c.z()));
+    multiply(&OUTPUT, c).max(Color::zero()).min(Color::ones())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operators() {
+        let grey = |v: f64| Color::new(v, v, v);
+        let eps = if cfg!(feature = "f32") { 1e-6 } else { 1e-12 };
+        for name in ToneMap::NAMES {
+            let op = ToneMap::from_name(name).unwrap();
+            // black stays black, the output is in range and grows with the input
+            assert!(op.map(Color::zero(), 4.0).near_zero(), "{}", name);
+            let mut last = -1.0;
+            for i in 0..200 {
+                let v = op.map(grey(i as f64 * 0.1), 4.0).y();
+                assert!((0.0..=1.0).contains(&v), "{} gives {}", name, v);
+                assert!(v >= last - eps, "{} decreases at {}", name, i);
+                last = v;
+            }
+        }
+        assert!((ToneMap::Reinhard.map(grey(1.0), 1.0).x() - 0.5).abs() < eps);
+        let extended = ToneMap::ReinhardExtended { white: None };
+        assert!((extended.map(grey(4.0), 4.0).x() - 1.0).abs() < eps);
+        assert!((ToneMap::Hable.map(grey(5.6), 1.0).x() - 1.0).abs() < eps);
+        // mid grey stays around mid grey
+        let aces = ToneMap::Aces.map(grey(0.18), 1.0);
+        assert!(aces.x() > 0.1 && aces.x() < 0.3, "{:?}", aces);
+        // Reinhard keeps the ratios between the channels
+        let c = ToneMap::Reinhard.map(Color::new(2.0, 1.0, 0.5), 1.0);
+        assert!((c.x() / c.y() - 2.0).abs() < eps);
+    }
+
+    #[test]
+    fn test_exposure_and_white_point() {
+        let mut film = Film::new(2, 1);
+        film.set_pixel(0, 0, &Color::new(0.25, 0.25, 0.25));
+        film.set_pixel(1, 0, &Color::new(3.0, 3.0, 3.0));
+        let doubled = ToneMap::Clamp.apply(&film, 1.0);
+        assert_eq!(doubled.pixel(0, 0), Color::new(0.5, 0.5, 0.5));
+        assert_eq!(doubled.pixel(1, 0), Color::ones());
+        // the brightest pixel, after exposure, becomes white
+        let mapped = ToneMap::ReinhardExtended { white: None }.apply(&film, -1.0);
+        assert!((mapped.pixel(1, 0).x() - 1.0).abs() < 1e-6);
+        assert!(mapped.pixel(0, 0).x() < 0.125);
+    }
+}
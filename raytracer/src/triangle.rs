@@ -0,0 +1,499 @@
+use crate::aabb::AaBb;
+use crate::bvh::{BvhError, BvhNode};
+use crate::hittable::{Hit, HitRecord};
+use crate::hittable_list::HittableList;
+use crate::material::Material;
+use crate::sampler::sample_2d;
+use crate::vec3::{Color, Point3, Vec3};
+use crate::Ray;
+use std::fmt;
+use std::sync::Arc;
+
+// triangles are flat, so their boxes get the same padding as the axis-aligned rects
+const BOX_PADDING: f64 = 0.0001;
+
+/// Möller–Trumbore intersection, returning `(t, b1, b2)` where `b1`, `b2` are the
+/// barycentric weights of `p1` and `p2`.
+fn intersect(
+    r: &Ray,
+    p0: &Point3,
+    p1: &Point3,
+    p2: &Point3,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64, f64)> {
+    let e1 = *p1 - *p0;
+    let e2 = *p2 - *p0;
+    let pvec = r.dir().cross(e2);
+    let det = e1.dot(pvec);
+    // relative to |e1| |e2| |dir|, which bounds it, so that the scale does not matter
+    let scale = e1.squared_length() * e2.squared_length() * r.dir().squared_length();
+    if det * det <= 1e-24 * scale {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let tvec = r.orig() - *p0;
+    let b1 = tvec.dot(pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = tvec.cross(e1);
+    let b2 = r.dir().dot(qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = e2.dot(qvec) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+fn triangle_box(p0: &Point3, p1: &Point3, p2: &Point3) -> AaBb {
+    let min = p0.min(*p1).min(*p2);
+    let max = p0.max(*p1).max(*p2);
+    AaBb::new(min - BOX_PADDING, max + BOX_PADDING)
+}
+
+/// A single flat-shaded triangle. Its texture coordinates are the barycentric
+/// weights of `v1` and `v2`.
+pub struct Triangle {
+    v0: Point3,
+    v1: Point3,
+    v2: Point3,
+    normal: Vec3,
+    mp: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, mp: Arc<dyn Material>) -> Self {
+        let normal = (v1 - v0).cross(v2 - v0).unit_vector();
+        Self {
+            v0,
+            v1,
+            v2,
+            normal,
+            mp,
+        }
+    }
+}
+
+impl Hit for Triangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t, b1, b2) = intersect(r, &self.v0, &self.v1, &self.v2, t_min, t_max)?;
+        Some(HitRecord::new(
+            r.at(t),
+            t,
+            b1,
+            b2,
+            &self.normal,
+            r.clone(),
+            &*self.mp,
+        ))
+    }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        intersect(r, &self.v0, &self.v1, &self.v2, t_min, t_max).is_some()
+    }
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AaBb> {
+        Some(triangle_box(&self.v0, &self.v1, &self.v2))
+    }
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> f64 {
+        let r = Ray::new(*o, *v, 0.0);
+        match intersect(&r, &self.v0, &self.v1, &self.v2, 0.001, f64::INFINITY) {
+            Some((t, _, _)) => {
+                let area = 0.5 * (self.v1 - self.v0).cross(self.v2 - self.v0).length();
+                let distance_squared = t * t * v.squared_length();
+                let cosine = (v.dot(self.normal) / v.length()).abs();
+                distance_squared / (cosine * area)
+            }
+            None => 0.0,
+        }
+    }
+    fn random(&self, o: &Vec3) -> Vec3 {
+        // uniform over the triangle by folding the unit square
+        let (mut b1, mut b2) = sample_2d();
+        if b1 + b2 > 1.0 {
+            b1 = 1.0 - b1;
+            b2 = 1.0 - b2;
+        }
+        let p = self.v0 + b1 * (self.v1 - self.v0) + b2 * (self.v2 - self.v0);
+        p - *o
+    }
+}
+
+/// The vertex and index buffers of a mesh, shared by all of its triangles.
+struct MeshData {
+    positions: Vec<Point3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<(f64, f64)>>,
+    colors: Option<Vec<Color>>,
+    indices: Vec<[usize; 3]>,
+    materials: Vec<Arc<dyn Material>>,
+    // index into `materials` for every triangle, all triangles use the first one if absent
+    material_ids: Option<Vec<usize>>,
+}
+
+impl MeshData {
+    fn vertices(&self, index: usize) -> (&Point3, &Point3, &Point3) {
+        let [a, b, c] = self.indices[index];
+        (&self.positions[a], &self.positions[b], &self.positions[c])
+    }
+    fn material(&self, index: usize) -> &dyn Material {
+        match &self.material_ids {
+            Some(ids) => &*self.materials[ids[index]],
+            None => &*self.materials[0],
+        }
+    }
+}
+
+/// One triangle of a mesh, only an index into the shared buffers.
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    index: usize,
+}
+
+impl Hit for MeshTriangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let mesh = &*self.mesh;
+        let (p0, p1, p2) = mesh.vertices(self.index);
+        let (t, b1, b2) = intersect(r, p0, p1, p2, t_min, t_max)?;
+        let b0 = 1.0 - b1 - b2;
+        let [i0, i1, i2] = mesh.indices[self.index];
+        let (u, v) = match &mesh.uvs {
+            Some(uvs) => (
+                b0 * uvs[i0].0 + b1 * uvs[i1].0 + b2 * uvs[i2].0,
+                b0 * uvs[i0].1 + b1 * uvs[i1].1 + b2 * uvs[i2].1,
+            ),
+            None => (b1, b2),
+        };
+        let color = mesh
+            .colors
+            .as_ref()
+            .map(|c| b0 * c[i0] + b1 * c[i1] + b2 * c[i2]);
+        let geometric_normal = (*p1 - *p0).cross(*p2 - *p0).unit_vector();
+        let mut rec = HitRecord::new(
+            r.at(t),
+            t,
+            u,
+            v,
+            &geometric_normal,
+            r.clone(),
+            mesh.material(self.index),
+        );
+        rec.color = color;
+        if let Some(normals) = &mesh.normals {
+            // front_face stays decided by the real surface, only the shading normal changes
+            let shading_normal =
+                (b0 * normals[i0] + b1 * normals[i1] + b2 * normals[i2]).unit_vector();
+            rec.normal = if rec.front_face {
+                shading_normal
+            } else {
+                -shading_normal
+            };
+        }
+        Some(rec)
+    }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        let (p0, p1, p2) = self.mesh.vertices(self.index);
+        intersect(r, p0, p1, p2, t_min, t_max).is_some()
+    }
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AaBb> {
+        let (p0, p1, p2) = self.mesh.vertices(self.index);
+        Some(triangle_box(p0, p1, p2))
+    }
+}
+
+/// Why the buffers given to a [`TriangleMesh`] do not make a mesh.
+#[derive(Debug)]
+pub enum MeshError {
+    NoMaterial,
+    /// The material ids are not one per triangle, or one is out of range.
+    MaterialIds,
+    /// A triangle refers to a vertex past the `vertices` that there are.
+    IndexOutOfRange {
+        vertices: usize,
+    },
+    /// A vertex attribute, such as `"normal"`, does not have one entry per vertex.
+    AttributeCount(&'static str),
+    /// The vertex position at `index` is infinite or NaN.
+    NonFinitePosition {
+        index: usize,
+    },
+    Bvh(BvhError),
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::NoMaterial => write!(f, "the mesh needs a material"),
+            MeshError::MaterialIds => {
+                write!(f, "the mesh needs one valid material id per triangle")
+            }
+            MeshError::IndexOutOfRange { vertices } => write!(
+                f,
+                "triangle index out of range, the mesh has {} vertices",
+                vertices
+            ),
+            MeshError::AttributeCount(attribute) => {
+                write!(f, "the mesh needs exactly one {} per vertex", attribute)
+            }
+            MeshError::NonFinitePosition { index } => {
+                write!(f, "vertex {} has a position that is not finite", index)
+            }
+            MeshError::Bvh(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+/// An indexed triangle mesh with optional per-vertex normals (smooth shading),
+/// texture coordinates and colors. The triangles are kept in their own BVH.
+pub struct TriangleMesh {
+    bvh: Arc<dyn Hit>,
+    triangle_count: usize,
+}
+
+impl TriangleMesh {
+    /// `normals` and `uvs`, when given, must have one entry per position.
+    ///
+    /// # Errors
+    ///
+    /// If an index is out of range, the attribute buffers have the wrong length or a
+    /// position is not finite.
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Option<Vec<Vec3>>,
+        uvs: Option<Vec<(f64, f64)>>,
+        indices: Vec<[usize; 3]>,
+        mp: Arc<dyn Material>,
+    ) -> Result<Self, MeshError> {
+        TriangleMesh::with_materials(positions, normals, uvs, indices, vec![mp], None)
+    }
+    /// Like `new`, but triangle `i` uses `materials[material_ids[i]]`.
+    ///
+    /// # Errors
+    ///
+    /// Also if there are no materials, or a material id is out of range or there is
+    /// not one per triangle.
+    pub fn with_materials(
+        positions: Vec<Point3>,
+        normals: Option<Vec<Vec3>>,
+        uvs: Option<Vec<(f64, f64)>>,
+        indices: Vec<[usize; 3]>,
+        materials: Vec<Arc<dyn Material>>,
+        material_ids: Option<Vec<usize>>,
+    ) -> Result<Self, MeshError> {
+        TriangleMesh::from_data(MeshData {
+            positions,
+            normals,
+            uvs,
+            colors: None,
+            indices,
+            materials,
+            material_ids,
+        })
+    }
+    /// Like `new`, with a color for every vertex that tints the material where it
+    /// [takes them](crate::hittable::HitRecord::color). The material is shared by all
+    /// triangles however many colors there are.
+    ///
+    /// # Errors
+    ///
+    /// Also if there is not one color per vertex.
+    pub fn with_vertex_colors(
+        positions: Vec<Point3>,
+        normals: Option<Vec<Vec3>>,
+        uvs: Option<Vec<(f64, f64)>>,
+        colors: Vec<Color>,
+        indices: Vec<[usize; 3]>,
+        mp: Arc<dyn Material>,
+    ) -> Result<Self, MeshError> {
+        TriangleMesh::from_data(MeshData {
+            positions,
+            normals,
+            uvs,
+            colors: Some(colors),
+            indices,
+            materials: vec![mp],
+            material_ids: None,
+        })
+    }
+    fn from_data(mesh: MeshData) -> Result<Self, MeshError> {
+        let MeshData {
+            positions,
+            normals,
+            uvs,
+            colors,
+            indices,
+            materials,
+            material_ids,
+        } = &mesh;
+        if materials.is_empty() {
+            return Err(MeshError::NoMaterial);
+        }
+        if !material_ids.as_ref().is_none_or(|ids| {
+            ids.len() == indices.len() && ids.iter().all(|&id| id < materials.len())
+        }) {
+            return Err(MeshError::MaterialIds);
+        }
+        let n = positions.len();
+        if !indices.iter().flatten().all(|&i| i < n) {
+            return Err(MeshError::IndexOutOfRange { vertices: n });
+        }
+        let counts = [
+            ("normal", normals.as_ref().map(Vec::len)),
+            ("texture coordinate", uvs.as_ref().map(Vec::len)),
+            ("color", colors.as_ref().map(Vec::len)),
+        ];
+        if let Some((attribute, _)) = counts.iter().find(|(_, len)| len.is_some_and(|l| l != n)) {
+            return Err(MeshError::AttributeCount(attribute));
+        }
+        let finite = |p: &Point3| p.x().is_finite() && p.y().is_finite() && p.z().is_finite();
+        if let Some(index) = positions.iter().position(|p| !finite(p)) {
+            return Err(MeshError::NonFinitePosition { index });
+        }
+        let triangle_count = indices.len();
+        let mesh = Arc::new(mesh);
+        let mut triangles = HittableList::new();
+        for index in 0..triangle_count {
+            triangles.add(Arc::new(MeshTriangle {
+                mesh: mesh.clone(),
+                index,
+            }));
+        }
+        let bvh = BvhNode::newnew(triangles, 0.0, 1.0).map_err(MeshError::Bvh)?;
+        Ok(Self {
+            bvh,
+            triangle_count,
+        })
+    }
+    pub fn triangle_count(&self) -> usize {
+        self.triangle_count
+    }
+}
+
+impl Hit for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.bvh.hit(r, t_min, t_max)
+    }
+    fn occluded(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.bvh.occluded(r, t_min, t_max)
+    }
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AaBb> {
+        self.bvh.bounding_box(time0, time1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::Color;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_color(Color::ones()))
+    }
+
+    #[test]
+    fn test_triangle_hit() {
+        let tri = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            material(),
+        );
+        let r = Ray::new(Point3::new(0.25, 0.25, -1.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let rec = tri.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, -1.0));
+        let miss = Ray::new(Point3::new(0.75, 0.75, -1.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!(tri.hit(&miss, 0.001, f64::INFINITY).is_none());
+        assert!(tri.occluded(&r, 0.001, f64::INFINITY) && !tri.occluded(&miss, 0.001, 10.0));
+        // a tenth of a micrometre across, in metres, and seen edge-on
+        let s = 1e-7;
+        let tiny = Triangle::new(
+            Point3::zero(),
+            Point3::new(s, 0.0, 0.0),
+            Point3::new(0.0, s, 0.0),
+            material(),
+        );
+        let r = Ray::new(
+            Point3::new(s / 4.0, s / 4.0, -1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            0.0,
+        );
+        assert!(tiny.hit(&r, 0.001, f64::INFINITY).is_some());
+        let edge_on = Ray::new(
+            Point3::new(-1.0, s / 4.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            0.0,
+        );
+        assert!(tri.hit(&edge_on, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_mesh_interpolates_attributes() {
+        // a unit quad made of two triangles, normals tilted towards +x on the right edge
+        let positions = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ];
+        let tilted = Vec3::new(1.0, 0.0, 1.0).unit_vector();
+        let normals = vec![
+            Vec3::new(0.0, 0.0, 1.0),
+            tilted,
+            tilted,
+            Vec3::new(0.0, 0.0, 1.0),
+        ];
+        let uvs = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let mesh = TriangleMesh::new(
+            positions.clone(),
+            Some(normals),
+            Some(uvs),
+            vec![[0, 1, 2], [0, 2, 3]],
+            material(),
+        )
+        .unwrap();
+        assert_eq!(mesh.triangle_count(), 2);
+        let r = Ray::new(Point3::new(0.5, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = mesh.hit(&r, 0.001, f64::INFINITY).unwrap();
+        let eps = if cfg!(feature = "f32") { 1e-6 } else { 1e-12 };
+        assert!(rec.front_face);
+        assert!((rec.u - 0.5).abs() < eps && (rec.v - 0.25).abs() < eps);
+        assert!(rec.normal.x() > 0.0 && rec.normal.z() > 0.0);
+        assert!((rec.normal.length() - 1.0).abs() < eps);
+        // shadow rays agree with the hits, and stop at t_max
+        assert!(mesh.occluded(&r, 0.001, f64::INFINITY));
+        assert!(!mesh.occluded(&r, 0.001, 0.5));
+        let beside = Ray::new(Point3::new(1.5, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(!mesh.occluded(&beside, 0.001, f64::INFINITY));
+    }
+
+    #[test]
+    fn test_mesh_errors() {
+        let positions = vec![Point3::zero(), Point3::new(1.0, 0.0, 0.0), Point3::ones()];
+        let mesh = |positions: Vec<Point3>, normals: Option<Vec<Vec3>>, indices| {
+            TriangleMesh::new(positions, normals, None, indices, material()).err()
+        };
+        assert!(matches!(
+            mesh(positions.clone(), None, vec![[0, 1, 3]]),
+            Some(MeshError::IndexOutOfRange { vertices: 3 })
+        ));
+        assert!(matches!(
+            mesh(positions.clone(), Some(vec![Vec3::ones()]), vec![[0, 1, 2]]),
+            Some(MeshError::AttributeCount("normal"))
+        ));
+        let mut broken = positions.clone();
+        broken[1] = Point3::new(f64::NAN, 0.0, 0.0);
+        assert!(matches!(
+            mesh(broken, None, vec![[0, 1, 2]]),
+            Some(MeshError::NonFinitePosition { index: 1 })
+        ));
+        let unlit = TriangleMesh::with_materials(positions, None, None, vec![], vec![], None);
+        assert!(matches!(unlit, Err(MeshError::NoMaterial)));
+    }
+}
@@ -1,30 +1,78 @@
+//! Three-component vectors for points, directions and colors.
+//!
+//! `Vec3` is `Copy` and its operators take values and references alike. The
+//! storage is chosen at compile time, while the interface is `f64` either way:
+//!
+//! - by default, three `f64`s;
+//! - with the `padded-vec3` feature, four `f64`s aligned to 32 bytes. The padding
+//!   lane and the alignment only give the compiler the chance to vectorize the
+//!   element-wise operations, with no explicit SIMD instructions;
+//! - with the `f32` feature (which implies `padded-vec3`), four `f32`s aligned to 16
+//!   bytes, which halves the memory traffic at the cost of precision.
+//!
+//! The README has the rays per second of each on the built-in scenes.
 use crate::rtweekend::*;
+use crate::sampler::sample_2d;
 use std::f64;
 use std::f64::consts::PI;
-use std::ops::{
-    Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
-};
-
-#[derive(Clone, Debug, PartialEq)]
+use std::fmt;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+#[cfg(not(feature = "f32"))]
+type Scalar = f64;
+#[cfg(feature = "f32")]
+type Scalar = f32;
+
+#[cfg(not(feature = "padded-vec3"))]
+const LANES: usize = 3;
+#[cfg(feature = "padded-vec3")]
+const LANES: usize = 4;
+
+// a no-op unless the `f32` feature is on
+#[allow(clippy::unnecessary_cast)]
+#[inline]
+fn widen(x: Scalar) -> f64 {
+    x as f64
+}
 
+#[derive(Clone, Copy)]
+#[cfg_attr(all(feature = "padded-vec3", not(feature = "f32")), repr(C, align(32)))]
+#[cfg_attr(feature = "f32", repr(C, align(16)))]
 pub struct Vec3 {
-    x: f64,
-    y: f64,
-    z: f64,
+    // with four lanes the last one only pads, it is carried along but never read
+    e: [Scalar; LANES],
 }
 
 impl Vec3 {
+    #[inline]
     pub fn x(&self) -> f64 {
-        self.x
+        widen(self.e[0])
     }
+    #[inline]
     pub fn y(&self) -> f64 {
-        self.y
+        widen(self.e[1])
     }
+    #[inline]
     pub fn z(&self) -> f64 {
-        self.z
+        widen(self.e[2])
+    }
+    /// Component `axis`, 0 for x, 1 for y and 2 for z.
+    #[inline]
+    pub fn axis(&self, axis: usize) -> f64 {
+        assert!(axis < 3, "Index out of bound!");
+        widen(self.e[axis])
     }
+    #[inline]
     pub fn new(x: f64, y: f64, z: f64) -> Self {
-        Self { x, y, z }
+        Self::from_scalars(x as Scalar, y as Scalar, z as Scalar)
+    }
+    #[inline]
+    fn from_scalars(x: Scalar, y: Scalar, z: Scalar) -> Self {
+        let mut e = [0.0; LANES];
+        e[0] = x;
+        e[1] = y;
+        e[2] = z;
+        Self { e }
     }
 
     pub fn ones() -> Self {
@@ -35,28 +83,60 @@ impl Vec3 {
         Self::new(0.0, 0.0, 0.0)
     }
 
+    // the lane-wise helpers are plain loops over the array, so that they vectorize
+    #[inline]
+    fn map(self, f: impl Fn(Scalar) -> Scalar) -> Self {
+        Self { e: self.e.map(f) }
+    }
+    #[inline]
+    fn zip(self, other: Self, f: impl Fn(Scalar, Scalar) -> Scalar) -> Self {
+        let mut e = self.e;
+        for (a, b) in e.iter_mut().zip(other.e) {
+            *a = f(*a, b);
+        }
+        Self { e }
+    }
+
+    /// The component-wise minimum.
+    #[inline]
+    pub fn min(&self, other: Vec3) -> Vec3 {
+        self.zip(other, Scalar::min)
+    }
+    /// The component-wise maximum.
+    #[inline]
+    pub fn max(&self, other: Vec3) -> Vec3 {
+        self.zip(other, Scalar::max)
+    }
+
+    #[inline]
     pub fn squared_length(&self) -> f64 {
-        self.x * self.x + self.y * self.y + self.z * self.z
+        self.dot(*self)
     }
 
+    #[inline]
     pub fn length(&self) -> f64 {
         self.squared_length().sqrt()
     }
 
+    #[inline]
     pub fn dot(&self, v: Vec3) -> f64 {
-        self.x * v.x + self.y * v.y + self.z * v.z
+        let p = *self * v;
+        widen(p.e[0] + p.e[1] + p.e[2])
     }
 
+    #[inline]
     pub fn cross(&self, v: Vec3) -> Vec3 {
-        Vec3 {
-            x: self.y * v.z - self.z * v.y,
-            y: self.z * v.x - self.x * v.z,
-            z: self.x * v.y - self.y * v.x,
-        }
+        let (a, b) = (&self.e, &v.e);
+        Vec3::from_scalars(
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        )
     }
 
+    #[inline]
     pub fn unit_vector(&self) -> Vec3 {
-        self.clone() / self.length()
+        *self / self.length()
     }
 
     pub fn random_f64() -> Vec3 {
@@ -69,7 +149,24 @@ impl Vec3 {
 
     pub fn near_zero(&self) -> bool {
         let s = 1e-8;
-        self.x.abs() < s && self.y.abs() < s && self.z.abs() < s
+        self.x().abs() < s && self.y().abs() < s && self.z().abs() < s
+    }
+}
+
+// the padding lane is left out of comparisons and output
+impl PartialEq for Vec3 {
+    fn eq(&self, other: &Self) -> bool {
+        self.e[..3] == other.e[..3]
+    }
+}
+
+impl fmt::Debug for Vec3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Vec3")
+            .field("x", &self.x())
+            .field("y", &self.y())
+            .field("z", &self.z())
+            .finish()
     }
 }
 
@@ -83,43 +180,56 @@ pub fn random_in_unit_sphere() -> Vec3 {
     }
 }
 
-pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
-    let in_unit_sphere = random_in_unit_sphere();
-    if in_unit_sphere.dot(normal) > 0.0 {
-        in_unit_sphere
-    } else {
-        -in_unit_sphere
-    }
-}
-
-pub fn random_unit_vector() -> Vec3 {
-    random_in_unit_sphere().unit_vector()
-}
+// pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
+//     let in_unit_sphere = random_in_unit_sphere();
+//     if in_unit_sphere.dot(normal) > 0.0 {
+//         in_unit_sphere
+//     } else {
+//         -in_unit_sphere
+//     }
+// }
 
 pub fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
-    v.clone() - 2.0 * v.dot(n.clone()) * n.clone()
+    v - 2.0 * v.dot(*n) * n
 }
 
 pub fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: f64) -> Vec3 {
-    let cos_theta = (-uv.clone()).dot(n.clone()).min(1.0);
-    let r_out_perp = etai_over_etat * (uv.clone() + cos_theta * n.clone());
-    let r_out_parallel = -((1.0 - r_out_perp.squared_length()).abs().sqrt()) * n.clone(); //
+    let cos_theta = (-uv).dot(*n).min(1.0);
+    let r_out_perp = etai_over_etat * (uv + cos_theta * n);
+    let r_out_parallel = -((1.0 - r_out_perp.squared_length()).abs().sqrt()) * n; //
     r_out_perp + r_out_parallel
 }
 
+/// A point uniform in the unit disk of the xy plane, from a 2D sample.
 pub fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let p = Vec3::new(random(-1.0, 1.0), random(-1.0, 1.0), 0.0);
-        if p.squared_length() > 1.0 {
-            continue;
-        };
-        return p;
-    }
+    // the concentric mapping of Shirley and Chiu, which keeps the strata of the
+    // square compact on the disk
+    let (a, b) = sample_2d();
+    let (a, b) = (2.0 * a - 1.0, 2.0 * b - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::zero();
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+/// A direction uniform on the unit sphere, from a 2D sample.
+pub fn random_unit_vector() -> Vec3 {
+    let (r1, r2) = sample_2d();
+    let z = 1.0 - 2.0 * r2;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * r1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
 }
 
+/// A direction around the z axis with a density proportional to its cosine, from a
+/// 2D sample.
 pub fn random_cosine_direction() -> Vec3 {
-    let r1 = random_f64();
-    let r2 = random_f64();
+    let (r1, r2) = sample_2d();
     let z = (1.0 - r2).sqrt();
     let phi = 2.0 * PI * r1;
     let x = phi.cos() * r2.sqrt();
@@ -127,110 +237,100 @@ pub fn random_cosine_direction() -> Vec3 {
     Vec3::new(x, y, z)
 }
 
-impl Add for Vec3 {
-    type Output = Self;
-
-    fn add(self, other: Self) -> Self {
-        Self {
-            x: self.x + other.x,
-            y: self.y + other.y,
-            z: self.z + other.z,
-        }
-    }
+/// A direction inside the cone that a sphere of `radius`, at squared distance
+/// `distance_squared`, subtends around the z axis.
+pub fn random_to_sphere(radius: f64, distance_squared: f64) -> Vec3 {
+    let (r1, r2) = sample_2d();
+    let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * (1.0 - z * z).sqrt();
+    let y = phi.sin() * (1.0 - z * z).sqrt();
+    Vec3::new(x, y, z)
 }
 
-impl Add<f64> for Vec3 {
-    type Output = Self;
-
-    fn add(self, other: f64) -> Self {
-        Self {
-            x: self.x + other,
-            y: self.y + other,
-            z: self.z + other,
+/// Element-wise `Vec3 op Vec3` and `Vec3 op f64`, for every mix of values and
+/// references.
+macro_rules! impl_op {
+    ($op:ident, $method:ident, $assign_op:ident, $assign_method:ident) => {
+        impl $op for Vec3 {
+            type Output = Vec3;
+            #[inline]
+            fn $method(self, rhs: Vec3) -> Vec3 {
+                self.zip(rhs, |a, b| a.$method(b))
+            }
         }
-    }
+        impl $op<&Vec3> for Vec3 {
+            type Output = Vec3;
+            #[inline]
+            fn $method(self, rhs: &Vec3) -> Vec3 {
+                self.$method(*rhs)
+            }
+        }
+        impl $op<Vec3> for &Vec3 {
+            type Output = Vec3;
+            #[inline]
+            fn $method(self, rhs: Vec3) -> Vec3 {
+                (*self).$method(rhs)
+            }
+        }
+        impl $op<&Vec3> for &Vec3 {
+            type Output = Vec3;
+            #[inline]
+            fn $method(self, rhs: &Vec3) -> Vec3 {
+                (*self).$method(*rhs)
+            }
+        }
+        impl $op<f64> for Vec3 {
+            type Output = Vec3;
+            #[inline]
+            fn $method(self, rhs: f64) -> Vec3 {
+                let rhs = rhs as Scalar;
+                self.map(|a| a.$method(rhs))
+            }
+        }
+        impl $op<f64> for &Vec3 {
+            type Output = Vec3;
+            #[inline]
+            fn $method(self, rhs: f64) -> Vec3 {
+                (*self).$method(rhs)
+            }
+        }
+        impl $assign_op<f64> for Vec3 {
+            #[inline]
+            fn $assign_method(&mut self, rhs: f64) {
+                *self = (*self).$method(rhs);
+            }
+        }
+    };
 }
 
+impl_op!(Add, add, AddAssign, add_assign);
+impl_op!(Sub, sub, SubAssign, sub_assign);
+impl_op!(Mul, mul, MulAssign, mul_assign);
+impl_op!(Div, div, DivAssign, div_assign);
+
 impl AddAssign for Vec3 {
+    #[inline]
     fn add_assign(&mut self, other: Self) {
-        *self = Self {
-            x: self.x + other.x,
-            y: self.y + other.y,
-            z: self.z + other.z,
-        };
-    }
-}
-
-impl Sub for Vec3 {
-    type Output = Self;
-    fn sub(self, other: Self) -> Self {
-        Self {
-            x: self.x - other.x,
-            y: self.y - other.y,
-            z: self.z - other.z,
-        }
+        *self = *self + other;
     }
 }
-
-impl Sub<f64> for Vec3 {
-    type Output = Self;
-
-    fn sub(self, other: f64) -> Self {
-        Self {
-            x: self.x - other,
-            y: self.y - other,
-            z: self.z - other,
-        }
+impl AddAssign<&Vec3> for Vec3 {
+    #[inline]
+    fn add_assign(&mut self, other: &Vec3) {
+        *self = *self + other;
     }
 }
-
 impl SubAssign for Vec3 {
+    #[inline]
     fn sub_assign(&mut self, other: Self) {
-        *self = Self {
-            x: self.x - other.x,
-            y: self.y - other.y,
-            z: self.z - other.z,
-        };
-    }
-}
-impl Mul<Vec3> for f64 {
-    type Output = Vec3;
-    fn mul(self, rhs: Vec3) -> Vec3 {
-        Vec3 {
-            x: self * rhs.x,
-            y: self * rhs.y,
-            z: self * rhs.z,
-        }
+        *self = *self - other;
     }
 }
-impl Mul<f64> for Vec3 {
-    type Output = Self;
-
-    fn mul(self, other: f64) -> Self {
-        Self {
-            x: self.x * other,
-            y: self.y * other,
-            z: self.z * other,
-        }
-    }
-}
-impl Mul<Vec3> for Vec3 {
-    type Output = Vec3;
-    fn mul(self, rhs: Vec3) -> Self {
-        Self {
-            x: self.x * rhs.x,
-            y: self.y * rhs.y,
-            z: self.z * rhs.z,
-        }
-    }
-}
-impl MulAssign<f64> for Vec3 {
-    fn mul_assign(&mut self, other: f64) {
-        *self = Self {
-            x: other * self.x,
-            y: other * self.y,
-            z: other * self.z,
-        }
+impl SubAssign<&Vec3> for Vec3 {
+    #[inline]
+    fn sub_assign(&mut self, other: &Vec3) {
+        *self = *self - other;
     }
 }
 impl MulAssign<Vec3> for Vec3 {
@@ -238,56 +338,32 @@ impl MulAssign<Vec3> for Vec3 {
         *self = self.cross(rhs)
     }
 }
-
-impl Div<f64> for Vec3 {
-    type Output = Self;
-    fn div(self, rhs: f64) -> Self::Output {
-        Self {
-            x: self.x / rhs,
-            y: self.y / rhs,
-            z: self.z / rhs,
-        }
+impl Mul<Vec3> for f64 {
+    type Output = Vec3;
+    #[inline]
+    fn mul(self, rhs: Vec3) -> Vec3 {
+        rhs * self
     }
 }
-impl DivAssign<f64> for Vec3 {
-    fn div_assign(&mut self, rhs: f64) {
-        *self = Self {
-            x: self.x / rhs,
-            y: self.y / rhs,
-            z: self.z / rhs,
-        }
+impl Mul<&Vec3> for f64 {
+    type Output = Vec3;
+    #[inline]
+    fn mul(self, rhs: &Vec3) -> Vec3 {
+        *rhs * self
     }
 }
 impl Neg for Vec3 {
     type Output = Self;
-
+    #[inline]
     fn neg(self) -> Self::Output {
-        Self {
-            x: -self.x,
-            y: -self.y,
-            z: -self.z,
-        }
+        self.map(|a| -a)
     }
 }
-impl Index<i32> for Vec3 {
-    type Output = f64;
-    fn index(&self, index: i32) -> &f64 {
-        match index {
-            0 => &self.x,
-            1 => &self.y,
-            2 => &self.z,
-            _ => panic!("Index out of bound!"),
-        }
-    }
-}
-impl IndexMut<i32> for Vec3 {
-    fn index_mut(&mut self, index: i32) -> &mut Self::Output {
-        match index {
-            0 => &mut self.x,
-            1 => &mut self.y,
-            2 => &mut self.z,
-            _ => panic!("Index out of bound!"),
-        }
+impl Neg for &Vec3 {
+    type Output = Vec3;
+    #[inline]
+    fn neg(self) -> Vec3 {
+        -*self
     }
 }
 pub type Point3 = Vec3;
@@ -386,6 +462,19 @@ mod tests {
     //     assert_eq!(-Vec3::new(1.0, -2.0, 3.0), Vec3::new(-1.0, 2.0, -3.0));
     // }
     #[test]
+    #[allow(clippy::op_ref)]
+    fn test_ops_by_reference() {
+        let a = Vec3::new(1.0, 2.0, 3.0);
+        let b = Vec3::new(-2.0, 0.5, 4.0);
+        assert_eq!(&a + &b, a + b);
+        assert_eq!(a - &b, &a - b);
+        assert_eq!(2.0 * &a, a * 2.0);
+        assert_eq!(-&a, Vec3::new(-1.0, -2.0, -3.0));
+        assert_eq!(a.min(b), Vec3::new(-2.0, 0.5, 3.0));
+        // scalar operations touch any padding lane too, equality must ignore it
+        assert_eq!(a + 1.0 - 1.0, a);
+    }
+    #[test]
     fn test_squared_length() {
         assert_eq!(Vec3::new(1.0, 2.0, 3.0).squared_length(), 14.0);
     }